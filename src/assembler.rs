@@ -0,0 +1,744 @@
+//! # Assembler
+//!
+//! Turns assembly source into ROM bytes. The syntax is the same as the output of the disassembler,
+//! i.e. the mnemonics of [`OpCode`] in the Cowgod syntax, so that a disassembled ROM assembles
+//! back to the same bytes.
+//!
+//! ```text
+//! ; Draw a sprite and wait forever
+//! X_POS = 12
+//!     LD V0, X_POS          ; Constants and labels can be used in place of numbers
+//!     LD V1, 0x08
+//!     LD I, sprite
+//!     DRW V0, V1, sprite_end - sprite
+//! loop:
+//!     JP loop
+//! sprite:
+//!     db 0b00111100, 0x42, 0x42, 0x3C
+//! sprite_end:
+//!     dw 0x1234             ; Big endian word
+//!     include "more.asm"    ; Relative to the including file
+//! ```
+//!
+//! Numbers are decimal, or hexadecimal with a `0x` or `#` prefix, or binary with a `0b` prefix,
+//! and can be added and subtracted. Labels end with a colon, constants are defined with `=`,
+//! and comments start with a semicolon. Mnemonics, registers and directives are case-insensitive.
+
+use crate::opcode::OpCode;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Maximum depth of nested include files, to stop include cycles
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// An error in the source, with the position where it was found
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssembleError {
+    /// Name of the source file
+    pub file: String,
+    /// Line number, starting at 1
+    pub line: usize,
+    /// Column number, starting at 1
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.file, self.line, self.column, self.message
+        )
+    }
+}
+
+impl Error for AssembleError {}
+
+/// Position of a token in the source
+#[derive(Debug, Clone)]
+struct Position {
+    file: usize,
+    line: usize,
+    column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Word(String),
+    Number(i64),
+    Str(String),
+    Punct(char),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    column: usize,
+}
+
+/// A number or symbol, plus or minus more of them
+#[derive(Debug, Clone)]
+struct Expr {
+    terms: Vec<(i64, Term)>,
+    column: usize,
+}
+
+#[derive(Debug, Clone)]
+enum Term {
+    Number(i64),
+    Symbol(String, usize),
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    V(u8),
+    I,
+    MemI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Long(Expr),
+    Expr(Expr),
+}
+
+#[derive(Debug)]
+enum Statement {
+    Instruction {
+        mnemonic: String,
+        operands: Vec<(Operand, usize)>,
+    },
+    Bytes(Vec<Expr>),
+    Words(Vec<Expr>),
+}
+
+/// A statement with the address it is assembled at
+struct Located {
+    addr: usize,
+    position: Position,
+    statement: Statement,
+}
+
+/// Assembles the source for a ROM loaded at the start address.
+/// Include files are looked up relative to the current directory.
+pub fn assemble(source: &str, start: u16) -> Result<Vec<u8>, AssembleError> {
+    Assembler::new(start).run("<source>", source, Path::new("."))
+}
+
+/// Assembles the source file for a ROM loaded at the start address.
+/// Include files are looked up relative to the directory of the source file.
+pub fn assemble_file(path: &Path, start: u16) -> Result<Vec<u8>, AssembleError> {
+    let source = std::fs::read_to_string(path).map_err(|e| AssembleError {
+        file: path.display().to_string(),
+        line: 0,
+        column: 0,
+        message: e.to_string(),
+    })?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    Assembler::new(start).run(&path.display().to_string(), &source, dir)
+}
+
+struct Assembler {
+    start: usize,
+    addr: usize,
+    files: Vec<String>,
+    labels: HashMap<String, i64>,
+    constants: HashMap<String, (Expr, Position)>,
+    statements: Vec<Located>,
+}
+
+impl Assembler {
+    fn new(start: u16) -> Self {
+        Self {
+            start: start as usize,
+            addr: start as usize,
+            files: Vec::new(),
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            statements: Vec::new(),
+        }
+    }
+
+    fn run(mut self, file: &str, source: &str, dir: &Path) -> Result<Vec<u8>, AssembleError> {
+        self.read_source(file, source, dir, 0)?;
+        let mut rom = Vec::new();
+        for located in &self.statements {
+            let bytes = self.encode(located)?;
+            rom.resize(located.addr - self.start, 0);
+            rom.extend(bytes);
+        }
+        Ok(rom)
+    }
+
+    /// First pass: parses the source lines, and records the address of every label.
+    fn read_source(
+        &mut self,
+        file: &str,
+        source: &str,
+        dir: &Path,
+        depth: usize,
+    ) -> Result<(), AssembleError> {
+        let file_index = self.files.len();
+        self.files.push(file.to_string());
+        for (line_nbr, line) in source.lines().enumerate() {
+            let position = |column| Position {
+                file: file_index,
+                line: line_nbr + 1,
+                column,
+            };
+            let tokens = tokenize(line).map_err(|(column, m)| self.error(&position(column), m))?;
+            let mut tokens = &tokens[..];
+
+            // Constant definition
+            if let [
+                Token {
+                    kind: TokenKind::Word(name),
+                    column,
+                },
+                Token {
+                    kind: TokenKind::Punct('='),
+                    ..
+                },
+                rest @ ..,
+            ] = tokens
+            {
+                let expr =
+                    parse_expr(rest, *column).map_err(|(c, m)| self.error(&position(c), &m))?;
+                self.define(name, &position(*column))?;
+                self.constants
+                    .insert(name.clone(), (expr, position(*column)));
+                continue;
+            }
+
+            // Labels
+            while let [
+                Token {
+                    kind: TokenKind::Word(name),
+                    column,
+                },
+                Token {
+                    kind: TokenKind::Punct(':'),
+                    ..
+                },
+                rest @ ..,
+            ] = tokens
+            {
+                self.define(name, &position(*column))?;
+                self.labels.insert(name.clone(), self.addr as i64);
+                tokens = rest;
+            }
+
+            let Some(first) = tokens.first() else {
+                continue;
+            };
+            let TokenKind::Word(word) = &first.kind else {
+                return Err(self.error(&position(first.column), "expected a mnemonic or directive"));
+            };
+            let operands = split_operands(&tokens[1..]);
+            let statement = match word.to_ascii_lowercase().as_str() {
+                "include" => {
+                    let [
+                        [
+                            Token {
+                                kind: TokenKind::Str(name),
+                                column,
+                            },
+                        ],
+                    ] = operands.as_slice()
+                    else {
+                        return Err(
+                            self.error(&position(first.column), "expected a file name in quotes")
+                        );
+                    };
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(
+                            self.error(&position(*column), "too deeply nested include files")
+                        );
+                    }
+                    let path: PathBuf = dir.join(name);
+                    let included = std::fs::read_to_string(&path).map_err(|e| {
+                        self.error(&position(*column), &format!("failed to read {name}: {e}"))
+                    })?;
+                    let included_dir = path.parent().unwrap_or(dir).to_path_buf();
+                    self.read_source(
+                        &path.display().to_string(),
+                        &included,
+                        &included_dir,
+                        depth + 1,
+                    )?;
+                    continue;
+                }
+                "db" | "dw" => {
+                    let mut exprs = Vec::new();
+                    for operand in &operands {
+                        match operand {
+                            [
+                                Token {
+                                    kind: TokenKind::Str(s),
+                                    column,
+                                },
+                            ] => exprs.extend(s.bytes().map(|b| Expr {
+                                terms: vec![(1, Term::Number(b as i64))],
+                                column: *column,
+                            })),
+                            _ => exprs.push(
+                                parse_expr(operand, first.column)
+                                    .map_err(|(c, m)| self.error(&position(c), &m))?,
+                            ),
+                        }
+                    }
+                    if word.eq_ignore_ascii_case("db") {
+                        Statement::Bytes(exprs)
+                    } else {
+                        Statement::Words(exprs)
+                    }
+                }
+                _ => {
+                    let mut parsed = Vec::new();
+                    for operand in &operands {
+                        let column = operand.first().map_or(first.column, |t| t.column);
+                        let op = parse_operand(operand, column)
+                            .map_err(|(c, m)| self.error(&position(c), &m))?;
+                        parsed.push((op, column));
+                    }
+                    Statement::Instruction {
+                        mnemonic: word.to_ascii_uppercase(),
+                        operands: parsed,
+                    }
+                }
+            };
+            let size = match &statement {
+                Statement::Instruction { operands, .. } => {
+                    if operands.iter().any(|(o, _)| matches!(o, Operand::Long(_))) {
+                        4
+                    } else {
+                        2
+                    }
+                }
+                Statement::Bytes(exprs) => exprs.len(),
+                Statement::Words(exprs) => exprs.len() * 2,
+            };
+            self.statements.push(Located {
+                addr: self.addr,
+                position: position(first.column),
+                statement,
+            });
+            self.addr += size;
+        }
+        Ok(())
+    }
+
+    /// Checks that a symbol isn't already defined.
+    fn define(&self, name: &str, position: &Position) -> Result<(), AssembleError> {
+        if is_reserved(name) {
+            return Err(self.error(position, &format!("{name} is a reserved name")));
+        }
+        if self.labels.contains_key(name) || self.constants.contains_key(name) {
+            return Err(self.error(position, &format!("{name} is already defined")));
+        }
+        Ok(())
+    }
+
+    /// Second pass: encodes a statement to bytes.
+    fn encode(&self, located: &Located) -> Result<Vec<u8>, AssembleError> {
+        let position = &located.position;
+        match &located.statement {
+            Statement::Bytes(exprs) => exprs
+                .iter()
+                .map(|e| Ok(self.eval_range(e, position, -0x80, 0xFF)? as u8))
+                .collect(),
+            Statement::Words(exprs) => {
+                let mut bytes = Vec::new();
+                for e in exprs {
+                    let word = self.eval_range(e, position, -0x8000, 0xFFFF)? as u16;
+                    bytes.extend(word.to_be_bytes());
+                }
+                Ok(bytes)
+            }
+            Statement::Instruction { mnemonic, operands } => {
+                let op = self.instruction(mnemonic, operands, position)?;
+                Ok(op.to_bytes())
+            }
+        }
+    }
+
+    /// Builds the op code of an instruction from its mnemonic and operands.
+    fn instruction(
+        &self,
+        mnemonic: &str,
+        operands: &[(Operand, usize)],
+        position: &Position,
+    ) -> Result<OpCode, AssembleError> {
+        use Operand as O;
+        let at = |column| Position {
+            column,
+            ..position.clone()
+        };
+        let byte = |e: &Expr| {
+            self.eval_range(e, &at(e.column), -0x80, 0xFF)
+                .map(|v| v as u8)
+        };
+        let addr = |e: &Expr| {
+            self.eval_range(e, &at(e.column), 0, 0xFFF)
+                .map(|v| v as u16)
+        };
+        let nibble = |e: &Expr| self.eval_range(e, &at(e.column), 0, 0xF).map(|v| v as u8);
+        let ops: Vec<&Operand> = operands.iter().map(|(o, _)| o).collect();
+        let op = match (mnemonic, ops.as_slice()) {
+            ("CLS", []) => OpCode::ClearScreen,
+            ("RET", []) => OpCode::Return,
+            ("SCD", [O::Expr(n)]) => OpCode::ScrollDown(nibble(n)?),
+            ("SCU", [O::Expr(n)]) => OpCode::ScrollUp(nibble(n)?),
+            ("SCR", []) => OpCode::ScrollRight,
+            ("SCL", []) => OpCode::ScrollLeft,
+            ("EXIT", []) => OpCode::Exit,
+            ("LOW", []) => OpCode::LowRes,
+            ("HIGH", []) => OpCode::HighRes,
+            ("JP", [O::Expr(a)]) => OpCode::Jump(addr(a)?),
+            ("JP", [O::V(0), O::Expr(a)]) => {
+                let val = addr(a)?;
+                OpCode::JumpWithOffset {
+                    vx: (val >> 8) as u8,
+                    val,
+                }
+            }
+            ("CALL", [O::Expr(a)]) => OpCode::Call(addr(a)?),
+            ("SE", [O::V(vx), O::V(vy)]) => OpCode::SkipIfVxEqVy { vx: *vx, vy: *vy },
+            ("SE", [O::V(vx), O::Expr(v)]) => OpCode::SkipIfVxEq {
+                vx: *vx,
+                val: byte(v)?,
+            },
+            ("SNE", [O::V(vx), O::V(vy)]) => OpCode::SkipIfVxNeqVy { vx: *vx, vy: *vy },
+            ("SNE", [O::V(vx), O::Expr(v)]) => OpCode::SkipIfVxNeq {
+                vx: *vx,
+                val: byte(v)?,
+            },
+            ("SAVE", [O::V(vx), O::V(vy)]) => OpCode::StoreRange { vx: *vx, vy: *vy },
+            ("LOAD", [O::V(vx), O::V(vy)]) => OpCode::LoadRange { vx: *vx, vy: *vy },
+            ("LD", [O::V(vx), O::V(vy)]) => OpCode::SetVxToVy { vx: *vx, vy: *vy },
+            ("LD", [O::V(vx), O::Expr(v)]) => OpCode::Set {
+                vx: *vx,
+                val: byte(v)?,
+            },
+            ("LD", [O::I, O::Expr(a)]) => OpCode::SetIndex(addr(a)?),
+            ("LD", [O::I, O::Long(a)]) => {
+                OpCode::SetIndexLong(self.eval_range(a, &at(a.column), 0, 0xFFFF)? as u16)
+            }
+            ("LD", [O::V(vx), O::Dt]) => OpCode::SetVxToDelayTimer { vx: *vx },
+            ("LD", [O::V(vx), O::K]) => OpCode::GetKey { vx: *vx },
+            ("LD", [O::Dt, O::V(vx)]) => OpCode::SetDelayTimerToVx { vx: *vx },
+            ("LD", [O::St, O::V(vx)]) => OpCode::SetSoundTimerToVx { vx: *vx },
+            ("LD", [O::F, O::V(vx)]) => OpCode::FontCharacter { vx: *vx },
+            ("LD", [O::Hf, O::V(vx)]) => OpCode::BigFontCharacter { vx: *vx },
+            ("LD", [O::B, O::V(vx)]) => OpCode::BinaryCodedDecimalConversion { vx: *vx },
+            ("LD", [O::MemI, O::V(vx)]) => OpCode::StoreMemory { vx: *vx },
+            ("LD", [O::V(vx), O::MemI]) => OpCode::LoadMemory { vx: *vx },
+            ("LD", [O::R, O::V(vx)]) => OpCode::StoreFlags { vx: *vx },
+            ("LD", [O::V(vx), O::R]) => OpCode::LoadFlags { vx: *vx },
+            ("ADD", [O::V(vx), O::V(vy)]) => OpCode::AddVyToVx { vx: *vx, vy: *vy },
+            ("ADD", [O::V(vx), O::Expr(v)]) => OpCode::Add {
+                vx: *vx,
+                val: byte(v)?,
+            },
+            ("ADD", [O::I, O::V(vx)]) => OpCode::AddToIndex { vx: *vx },
+            ("OR", [O::V(vx), O::V(vy)]) => OpCode::BinaryOr { vx: *vx, vy: *vy },
+            ("AND", [O::V(vx), O::V(vy)]) => OpCode::BinaryAnd { vx: *vx, vy: *vy },
+            ("XOR", [O::V(vx), O::V(vy)]) => OpCode::LogicalXor { vx: *vx, vy: *vy },
+            ("SUB", [O::V(vx), O::V(vy)]) => OpCode::SubVxVyToVx { vx: *vx, vy: *vy },
+            ("SUBN", [O::V(vx), O::V(vy)]) => OpCode::SubVyVxToVx { vx: *vx, vy: *vy },
+            ("SHR" | "SHL", [O::V(vx), rest @ ..]) => {
+                let vy = match rest {
+                    [] => *vx,
+                    [O::V(vy)] => *vy,
+                    _ => return Err(self.error(position, "expected SHR/SHL Vx[, Vy]")),
+                };
+                OpCode::Shift {
+                    vx: *vx,
+                    vy,
+                    left_shift: mnemonic == "SHL",
+                }
+            }
+            ("RND", [O::V(vx), O::Expr(v)]) => OpCode::Random {
+                vx: *vx,
+                val: byte(v)?,
+            },
+            ("DRW", [O::V(vx), O::V(vy), O::Expr(n)]) => OpCode::Display {
+                vx: *vx,
+                vy: *vy,
+                val: nibble(n)?,
+            },
+            ("SKP", [O::V(vx)]) => OpCode::SkipIfKeyPressed { vx: *vx },
+            ("SKNP", [O::V(vx)]) => OpCode::SkipIfKeyNotPressed { vx: *vx },
+            ("PLANE", [O::Expr(n)]) => OpCode::SelectPlanes(nibble(n)?),
+            ("AUDIO", []) => OpCode::LoadAudioPattern,
+            ("PITCH", [O::V(vx)]) => OpCode::SetPitchToVx { vx: *vx },
+            _ => {
+                let message = if MNEMONICS.contains(&mnemonic) {
+                    format!("invalid operands for {mnemonic}")
+                } else {
+                    format!("unknown mnemonic {mnemonic}")
+                };
+                return Err(self.error(position, &message));
+            }
+        };
+        Ok(op)
+    }
+
+    /// Evaluates an expression, and checks that the value is within the range.
+    fn eval_range(
+        &self,
+        expr: &Expr,
+        position: &Position,
+        min: i64,
+        max: i64,
+    ) -> Result<i64, AssembleError> {
+        let value = self.eval(expr, position, 0)?;
+        if value < min || value > max {
+            let at = Position {
+                column: expr.column,
+                ..position.clone()
+            };
+            return Err(self.error(&at, &format!("value {value} out of range")));
+        }
+        Ok(value)
+    }
+
+    /// Evaluates an expression, resolving the symbols in it.
+    fn eval(&self, expr: &Expr, position: &Position, depth: usize) -> Result<i64, AssembleError> {
+        let mut value = 0;
+        for (sign, term) in &expr.terms {
+            let term_value = match term {
+                Term::Number(n) => *n,
+                Term::Symbol(name, column) => {
+                    let at = Position {
+                        column: *column,
+                        ..position.clone()
+                    };
+                    if let Some(addr) = self.labels.get(name) {
+                        *addr
+                    } else if let Some((constant, constant_position)) = self.constants.get(name) {
+                        if depth > self.constants.len() {
+                            return Err(self.error(&at, &format!("circular definition of {name}")));
+                        }
+                        self.eval(constant, constant_position, depth + 1)?
+                    } else {
+                        return Err(self.error(&at, &format!("undefined symbol {name}")));
+                    }
+                }
+            };
+            value += sign * term_value;
+        }
+        Ok(value)
+    }
+
+    fn error(&self, position: &Position, message: &str) -> AssembleError {
+        AssembleError {
+            file: self.files[position.file].clone(),
+            line: position.line,
+            column: position.column,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// All mnemonics, to tell an unknown mnemonic apart from invalid operands
+const MNEMONICS: &[&str] = &[
+    "CLS", "RET", "SCD", "SCU", "SCR", "SCL", "EXIT", "LOW", "HIGH", "JP", "CALL", "SE", "SNE",
+    "SAVE", "LOAD", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SUBN", "SHR", "SHL", "RND", "DRW",
+    "SKP", "SKNP", "PLANE", "AUDIO", "PITCH",
+];
+
+/// Returns true if the name is a register or keyword, which can't be used as a symbol.
+fn is_reserved(name: &str) -> bool {
+    let upper = name.to_ascii_uppercase();
+    matches!(
+        upper.as_str(),
+        "I" | "DT" | "ST" | "K" | "F" | "HF" | "B" | "R" | "LONG" | "DB" | "DW" | "INCLUDE"
+    ) || register(&upper).is_some()
+}
+
+/// Parses a V register name.
+fn register(name: &str) -> Option<u8> {
+    let digit = name.strip_prefix(['V', 'v'])?;
+    if digit.len() == 1 {
+        u8::from_str_radix(digit, 16).ok()
+    } else {
+        None
+    }
+}
+
+/// Splits a line into tokens, stopping at a comment. Returns the column and a message on errors.
+fn tokenize(line: &str) -> Result<Vec<Token>, (usize, &'static str)> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        if c == ';' {
+            break;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || "_.".contains(chars[i])) {
+                i += 1;
+            }
+            let word = chars[start..i].iter().collect();
+            tokens.push(Token {
+                kind: TokenKind::Word(word),
+                column,
+            });
+        } else if c.is_ascii_digit() || c == '#' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().filter(|c| **c != '_').collect();
+            let lower = text.to_ascii_lowercase();
+            let number = if let Some(hex) = lower.strip_prefix("0x").or(lower.strip_prefix('#')) {
+                i64::from_str_radix(hex, 16)
+            } else if let Some(bin) = lower.strip_prefix("0b") {
+                i64::from_str_radix(bin, 2)
+            } else {
+                lower.parse()
+            };
+            let number = number.map_err(|_| (column, "invalid number"))?;
+            tokens.push(Token {
+                kind: TokenKind::Number(number),
+                column,
+            });
+        } else if c == '"' {
+            let start = i + 1;
+            i = start;
+            while i < chars.len() && chars[i] != '"' {
+                i += 1;
+            }
+            if i >= chars.len() {
+                return Err((column, "unterminated string"));
+            }
+            tokens.push(Token {
+                kind: TokenKind::Str(chars[start..i].iter().collect()),
+                column,
+            });
+            i += 1;
+        } else if ",:=[]+-".contains(c) {
+            tokens.push(Token {
+                kind: TokenKind::Punct(c),
+                column,
+            });
+            i += 1;
+        } else {
+            return Err((column, "unexpected character"));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Splits the operand tokens at the commas.
+fn split_operands(tokens: &[Token]) -> Vec<&[Token]> {
+    if tokens.is_empty() {
+        return Vec::new();
+    }
+    tokens.split(|t| t.kind == TokenKind::Punct(',')).collect()
+}
+
+/// Parses an instruction operand.
+fn parse_operand(tokens: &[Token], column: usize) -> Result<Operand, (usize, String)> {
+    match tokens {
+        [
+            Token {
+                kind: TokenKind::Punct('['),
+                ..
+            },
+            Token {
+                kind: TokenKind::Word(w),
+                ..
+            },
+            Token {
+                kind: TokenKind::Punct(']'),
+                ..
+            },
+        ] if w.eq_ignore_ascii_case("I") => Ok(Operand::MemI),
+        [
+            Token {
+                kind: TokenKind::Word(w),
+                column,
+            },
+            rest @ ..,
+        ] if w.eq_ignore_ascii_case("LONG") => Ok(Operand::Long(parse_expr(rest, *column)?)),
+        [
+            Token {
+                kind: TokenKind::Word(w),
+                ..
+            },
+        ] if is_reserved(w) => {
+            let upper = w.to_ascii_uppercase();
+            Ok(match upper.as_str() {
+                "I" => Operand::I,
+                "DT" => Operand::Dt,
+                "ST" => Operand::St,
+                "K" => Operand::K,
+                "F" => Operand::F,
+                "HF" => Operand::Hf,
+                "B" => Operand::B,
+                "R" => Operand::R,
+                _ => match register(&upper) {
+                    Some(x) => Operand::V(x),
+                    None => return Err((column, format!("unexpected {w}"))),
+                },
+            })
+        }
+        _ => Ok(Operand::Expr(parse_expr(tokens, column)?)),
+    }
+}
+
+/// Parses an expression of numbers and symbols, added or subtracted.
+fn parse_expr(tokens: &[Token], column: usize) -> Result<Expr, (usize, String)> {
+    let start_column = tokens.first().map_or(column, |t| t.column);
+    let mut terms = Vec::new();
+    let mut sign = 1;
+    let mut expect_term = true;
+    for token in tokens {
+        match (&token.kind, expect_term) {
+            (TokenKind::Punct('-'), true) => sign = -sign,
+            (TokenKind::Punct('+'), true) => (),
+            (TokenKind::Number(n), true) => {
+                terms.push((sign, Term::Number(*n)));
+                expect_term = false;
+            }
+            (TokenKind::Word(w), true) if !is_reserved(w) => {
+                terms.push((sign, Term::Symbol(w.clone(), token.column)));
+                expect_term = false;
+            }
+            (TokenKind::Punct('+'), false) => {
+                sign = 1;
+                expect_term = true;
+            }
+            (TokenKind::Punct('-'), false) => {
+                sign = -1;
+                expect_term = true;
+            }
+            _ => return Err((token.column, "unexpected token in expression".to_string())),
+        }
+    }
+    if expect_term {
+        let column = tokens.last().map_or(column, |t| t.column);
+        return Err((column, "expected a number or symbol".to_string()));
+    }
+    Ok(Expr {
+        terms,
+        column: start_column,
+    })
+}
+
+#[path = "unittest/test_assembler.rs"]
+#[cfg(test)]
+mod test_assembler;
@@ -0,0 +1,132 @@
+//! # Audio
+//!
+//! Contains the Audio module, opening an SDL2 audio playback device that plays a tone
+//! while the CHIP-8 sound timer is running.
+//! The tone is generated from the configured frequency, volume and waveform, unless an
+//! XO-CHIP program has loaded an audio pattern, in which case the pattern is played at the
+//! sample rate given by the pitch register.
+extern crate sdl2;
+
+use crate::chip8options::{self, Waveform};
+use sdl2::{
+    Sdl,
+    audio::{AudioCallback, AudioDevice, AudioSpecDesired},
+};
+use std::error::Error;
+
+/// Generates the samples for the audio device
+struct ToneGenerator {
+    sample_rate: f32,
+    phase: f32,
+    tone_frequency: f32,
+    volume: f32,
+    waveform: Waveform,
+    playing: bool,
+    pattern: Option<[u8; 16]>,
+    pitch: u8,
+}
+
+impl ToneGenerator {
+    /// Returns the sample at the current phase (0.0 - 1.0) of the tone, in the range -1.0 - 1.0.
+    fn tone_sample(&self) -> f32 {
+        match self.waveform {
+            Waveform::Square => {
+                if self.phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sine => (self.phase * std::f32::consts::TAU).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (self.phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * self.phase - 1.0,
+        }
+    }
+
+    /// Returns the bit of the XO-CHIP pattern at the current phase, where one period of the phase
+    /// covers all 128 bits of the pattern.
+    fn pattern_sample(&self, pattern: &[u8; 16]) -> f32 {
+        let bit = (self.phase * 128.0) as usize % 128;
+        if pattern[bit / 8] & (0x80 >> (bit % 8)) > 0 {
+            1.0
+        } else {
+            -1.0
+        }
+    }
+}
+
+impl AudioCallback for ToneGenerator {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        // The pattern is played at 4000 * 2^((pitch - 64) / 48) bits per second
+        let frequency = match self.pattern {
+            Some(_) => 4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0) / 128.0,
+            None => self.tone_frequency,
+        };
+        for x in out.iter_mut() {
+            if !self.playing {
+                *x = 0.0;
+                continue;
+            }
+            let sample = match &self.pattern {
+                Some(pattern) => self.pattern_sample(pattern),
+                None => self.tone_sample(),
+            };
+            *x = sample * self.volume;
+            self.phase = (self.phase + frequency / self.sample_rate) % 1.0;
+        }
+    }
+}
+
+pub struct Audio {
+    device: AudioDevice<ToneGenerator>,
+}
+
+impl Audio {
+    /// Creates a new Audio instance. The device is started right away, but stays silent
+    /// until the sound is turned on.
+    pub fn new(
+        sdl_context: &Sdl,
+        audio_options: &chip8options::Audio,
+    ) -> Result<Self, Box<dyn Error>> {
+        let audio_subsystem = sdl_context.audio()?;
+        let desired_spec = AudioSpecDesired {
+            freq: Some(44_100),
+            channels: Some(1),
+            samples: None,
+        };
+        let device = audio_subsystem.open_playback(None, &desired_spec, |spec| ToneGenerator {
+            sample_rate: spec.freq as f32,
+            phase: 0.0,
+            tone_frequency: audio_options.tone_frequency,
+            volume: audio_options.volume.clamp(0.0, 1.0),
+            waveform: audio_options.waveform,
+            playing: false,
+            pattern: None,
+            pitch: 64,
+        })?;
+        device.resume();
+        Ok(Self { device })
+    }
+
+    /// Turns the sound on or off.
+    pub fn set_playing(&mut self, playing: bool) {
+        let mut generator = self.device.lock();
+        if playing && !generator.playing {
+            generator.phase = 0.0;
+        }
+        generator.playing = playing;
+    }
+
+    /// Sets the XO-CHIP audio pattern and pitch. With no pattern the configured tone is played.
+    pub fn set_pattern(&mut self, pattern: Option<&[u8; 16]>, pitch: u8) {
+        let mut generator = self.device.lock();
+        generator.pattern = pattern.copied();
+        generator.pitch = pitch;
+    }
+}
+
+#[path = "unittest/test_audio.rs"]
+#[cfg(test)]
+mod test_audio;
@@ -0,0 +1,36 @@
+use chip8::{assembler, parse_address};
+use clap::Parser;
+use std::{fs, path::PathBuf, process};
+
+/// CHIP-8 assembler, turning assembly source into a ROM file
+#[derive(Parser, Debug)]
+#[command(version, long_about = None)]
+struct Args {
+    /// Assembly source file name
+    #[arg(name = "FILE")]
+    source_file: PathBuf,
+
+    /// Address the ROM is loaded at (hexadecimal)
+    #[arg(long, default_value = "200", value_parser = parse_address)]
+    start: u16,
+
+    /// ROM file to write, by default the source file name with the .ch8 extension
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+fn main() {
+    let args = Args::parse();
+    let rom = assembler::assemble_file(&args.source_file, args.start).unwrap_or_else(|e| {
+        eprintln!("Assembly error: {e}");
+        process::exit(1);
+    });
+
+    let output = args
+        .output
+        .unwrap_or_else(|| args.source_file.with_extension("ch8"));
+    if let Err(e) = fs::write(&output, rom) {
+        eprintln!("Application error: {e}");
+        process::exit(1);
+    }
+}
@@ -0,0 +1,39 @@
+use chip8::{disassembler, parse_address};
+use clap::Parser;
+use std::{fs, path::PathBuf, process};
+
+/// CHIP-8 disassembler, turning a ROM back into assembly source
+#[derive(Parser, Debug)]
+#[command(version, long_about = None)]
+struct Args {
+    /// ROM file name
+    #[arg(name = "FILE")]
+    rom_file: PathBuf,
+
+    /// Address the ROM is loaded at (hexadecimal)
+    #[arg(long, default_value = "200", value_parser = parse_address)]
+    start: u16,
+
+    /// File to write the assembly source to, instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+fn main() {
+    let args = Args::parse();
+    let rom = fs::read(&args.rom_file).unwrap_or_else(|e| {
+        eprintln!("Application error: {e}");
+        process::exit(1);
+    });
+
+    let source = disassembler::disassemble(&rom, args.start);
+    match &args.output {
+        Some(f) => {
+            if let Err(e) = fs::write(f, source) {
+                eprintln!("Application error: {e}");
+                process::exit(1);
+            }
+        }
+        None => print!("{source}"),
+    }
+}
@@ -1,17 +1,24 @@
 //! # Chip8 options
 //!
 //! Holds a TOML structure for the optional settings of the CHIP-8 program.
-//! Will try to read options.toml from the project root directory and deserialize it,
-//! if that fails it will use default settings.
-use serde::Deserialize;
+//! The settings start out from the preset of the selected platform, which bundles the op code quirks,
+//! memory layout and timing of that machine. Will try to read options.toml from the project root directory,
+//! and any values found there override the preset. If reading the file fails the preset is used as is.
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Clone, Copy)]
+#[derive(Serialize, Deserialize, Clone, Copy)]
 pub struct Display {
     pub display_width: u32,
     pub display_height: u32,
     pub scaling: u32,
     pub color_off_rgb: (u8, u8, u8),
     pub color_on_rgb: (u8, u8, u8),
+    pub color_plane2_rgb: (u8, u8, u8),
+    pub color_both_rgb: (u8, u8, u8),
+    /// Size of a CHIP-8 pixel in screenshots and recordings, in image pixels
+    pub capture_scale: u32,
+    /// Sync the frames to the vertical blank of the display
+    pub vsync: bool,
 }
 
 impl Default for Display {
@@ -22,14 +29,25 @@ impl Default for Display {
             scaling: 20,
             color_off_rgb: (0, 0, 0),
             color_on_rgb: (255, 255, 255),
+            color_plane2_rgb: (85, 85, 85),
+            color_both_rgb: (170, 170, 170),
+            capture_scale: 4,
+            vsync: false,
         }
     }
 }
 
-#[derive(Deserialize, Clone, Copy)]
+#[derive(Serialize, Deserialize, Clone, Copy)]
 pub struct Timing {
     pub display_frequency: u32,
     pub cpu_cycles_per_display_tick: u32,
+    pub rewind_seconds: u32,
+    /// Frames the emulator may run late and catch up, before dropping the missed frames
+    pub catch_up_frames: u32,
+    /// With turbo on, only every Nth frame is shown
+    pub turbo_frame_skip: u32,
+    /// How many times slower the emulator runs in slow motion
+    pub slow_motion_factor: u32,
 }
 
 impl Default for Timing {
@@ -37,15 +55,23 @@ impl Default for Timing {
         Timing {
             display_frequency: 60,
             cpu_cycles_per_display_tick: 10,
+            rewind_seconds: 10,
+            catch_up_frames: 6,
+            turbo_frame_skip: 8,
+            slow_motion_factor: 4,
         }
     }
 }
 
-#[derive(Deserialize, Clone, Copy)]
+#[derive(Serialize, Deserialize, Clone, Copy)]
 pub struct Opcode {
     pub shift_ignore_vy: bool,
     pub jump_w_offset_use_vx: bool,
     pub store_load_mem_use_i: bool,
+    pub logic_reset_vf: bool,
+    pub sprite_clipping: bool,
+    pub display_wait: bool,
+    pub add_index_overflow_vf: bool,
 }
 
 impl Default for Opcode {
@@ -54,15 +80,20 @@ impl Default for Opcode {
             shift_ignore_vy: true,
             jump_w_offset_use_vx: false,
             store_load_mem_use_i: false,
+            logic_reset_vf: false,
+            sprite_clipping: true,
+            display_wait: false,
+            add_index_overflow_vf: false,
         }
     }
 }
 
-#[derive(Deserialize, Clone, Copy)]
+#[derive(Serialize, Deserialize, Clone, Copy)]
 pub struct Memory {
-    pub mem_size: u16,
+    pub mem_size: u32,
     pub rom_start: u16,
     pub font_start: u16,
+    pub big_font_start: u16,
 }
 
 impl Default for Memory {
@@ -71,25 +102,199 @@ impl Default for Memory {
             mem_size: 4096,
             rom_start: 0x200,
             font_start: 0x50,
+            big_font_start: 0xA0,
         }
     }
 }
 
-#[derive(Deserialize, Default, Clone, Copy)]
+/// Waveform of the tone played while the sound timer is running
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+    Sawtooth,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct Audio {
+    pub tone_frequency: f32,
+    pub volume: f32,
+    pub waveform: Waveform,
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Audio {
+            tone_frequency: 440.0,
+            volume: 0.25,
+            waveform: Waveform::Square,
+        }
+    }
+}
+
+/// Algorithm of the random number generator for the random op code (CXNN)
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum RandomAlgorithm {
+    /// PCG32, statistically good random numbers
+    #[default]
+    Pcg,
+    /// A simple 8-bit routine with short-period patterns
+    #[serde(rename = "8-bit")]
+    #[value(name = "8-bit")]
+    EightBit,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub struct Random {
+    pub algorithm: RandomAlgorithm,
+    /// Fixed seed for reproducible runs, a random seed is used if not set
+    pub seed: Option<u64>,
+}
+
+/// The CHIP-8 platforms (i.e. interpreters) with a preset for the options
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Platform {
+    /// The original CHIP-8 interpreter on the COSMAC VIP
+    #[default]
+    CosmacVip,
+    /// CHIP-48 on the HP-48 calculators
+    Chip48,
+    /// SUPER-CHIP 1.1 as implemented by most modern interpreters
+    SuperChipModern,
+    /// The original SUPER-CHIP 1.1 on the HP-48 calculators
+    SuperChipLegacy,
+    /// XO-CHIP, as defined by Octo
+    XoChip,
+}
+
+impl Platform {
+    /// Returns the full set of options for the platform.
+    pub fn options(self) -> Chip8options {
+        let (opcode, cpu_cycles_per_display_tick, mem_size) = match self {
+            Platform::CosmacVip => (
+                Opcode {
+                    shift_ignore_vy: false,
+                    store_load_mem_use_i: true,
+                    logic_reset_vf: true,
+                    display_wait: true,
+                    ..Opcode::default()
+                },
+                11,
+                4096,
+            ),
+            Platform::Chip48 | Platform::SuperChipModern => (
+                Opcode {
+                    jump_w_offset_use_vx: true,
+                    ..Opcode::default()
+                },
+                30,
+                4096,
+            ),
+            Platform::SuperChipLegacy => (
+                Opcode {
+                    jump_w_offset_use_vx: true,
+                    display_wait: true,
+                    ..Opcode::default()
+                },
+                30,
+                4096,
+            ),
+            Platform::XoChip => (
+                Opcode {
+                    shift_ignore_vy: false,
+                    store_load_mem_use_i: true,
+                    sprite_clipping: false,
+                    ..Opcode::default()
+                },
+                1000,
+                65536,
+            ),
+        };
+        Chip8options {
+            platform: self,
+            display: Display::default(),
+            timing: Timing {
+                cpu_cycles_per_display_tick,
+                ..Timing::default()
+            },
+            opcode,
+            memory: Memory {
+                mem_size,
+                ..Memory::default()
+            },
+            audio: Audio::default(),
+            random: Random::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
 pub struct Chip8options {
+    pub platform: Platform,
     pub display: Display,
     pub timing: Timing,
     pub opcode: Opcode,
     pub memory: Memory,
+    pub audio: Audio,
+    pub random: Random,
+}
+
+impl Default for Chip8options {
+    fn default() -> Self {
+        Platform::default().options()
+    }
 }
 
 impl Chip8options {
-    pub fn get_options() -> Self {
+    /// Reads the options, starting from the preset of the platform and overriding it with the values
+    /// found in options.toml. The platform is taken from the input if given, otherwise from the file.
+    pub fn get_options(platform: Option<Platform>) -> Self {
+        Self::from_table(platform, Self::read_options_file().unwrap_or_default())
+    }
+
+    /// Builds the options from the preset of the platform, overridden by the values in the table.
+    /// The platform is taken from the input if given, otherwise from the table.
+    pub(crate) fn from_table(platform: Option<Platform>, mut overrides: toml::Table) -> Self {
+        let file_platform = overrides
+            .remove("platform")
+            .and_then(|p| match p.try_into() {
+                Ok(p) => Some(p),
+                Err(_) => {
+                    eprintln!("Unknown platform in config file, using the default platform");
+                    None
+                }
+            });
+        let platform = platform.or(file_platform).unwrap_or_default();
+
+        let mut options = match toml::Table::try_from(platform.options()) {
+            Ok(t) => t,
+            Err(_) => {
+                eprintln!("Failed to serialize platform preset, using default options");
+                return platform.options();
+            }
+        };
+        merge_tables(&mut options, overrides);
+
+        match options.try_into() {
+            Ok(o) => o,
+            Err(_) => {
+                eprintln!("Failed to deserialize config file, using platform preset options");
+                platform.options()
+            }
+        }
+    }
+
+    /// Reads options.toml from the current directory as a TOML table.
+    pub(crate) fn read_options_file() -> Option<toml::Table> {
         let cwd = match std::env::current_dir() {
             Ok(c) => c,
             Err(_) => {
-                eprintln!("Failed to get current directory, using default options");
-                return Self::default();
+                eprintln!("Failed to get current directory, using platform preset options");
+                return None;
             }
         };
         let filename = cwd.join("options.toml");
@@ -97,19 +302,35 @@ impl Chip8options {
         let options_file_str = match std::fs::read_to_string(filename) {
             Ok(f) => f,
             Err(_) => {
-                eprintln!("Failed to read config file, using default options");
-                return Self::default();
+                eprintln!("Failed to read config file, using platform preset options");
+                return None;
             }
         };
 
-        let options_file: Self = match toml::from_str(&options_file_str) {
-            Ok(f) => f,
+        match options_file_str.parse() {
+            Ok(t) => Some(t),
             Err(_) => {
-                eprintln!("Failed to deserialize config file, using default options");
-                Self::default()
+                eprintln!("Failed to parse config file, using platform preset options");
+                None
             }
-        };
+        }
+    }
+}
 
-        options_file
+/// Utility function to recursively merge the values in the overrides table into the base table.
+fn merge_tables(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(override_table)) => {
+                merge_tables(base_table, override_table);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
     }
 }
+
+#[path = "unittest/test_chip8options.rs"]
+#[cfg(test)]
+mod test_chip8options;
@@ -0,0 +1,171 @@
+//! # Controller
+//!
+//! Game controller input for CHIP-8, through the SDL game controller API, so that any controller known
+//! to SDL has the same button layout. The buttons are mapped with the controller bindings of the
+//! options and the controller profile of the ROM, see [`crate::keybindings`].
+//!
+//! Controllers can be plugged in and out while running. Keys held on a controller which is unplugged
+//! are released.
+
+use crate::emulator::KeyEvent;
+use crate::keybindings::{Binding, ControllerBindings};
+use sdl2::controller::{Axis, GameController};
+use sdl2::{GameControllerSubsystem, Sdl, event::Event};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+
+/// Trigger axis value from which a trigger counts as pressed, half of the full range
+const TRIGGER_THRESHOLD: i16 = i16::MAX / 2;
+
+pub struct Controllers {
+    subsystem: GameControllerSubsystem,
+    bindings: ControllerBindings,
+    /// The open controllers by joystick instance id
+    open: HashMap<u32, GameController>,
+    held: HeldButtons,
+    /// Events generated beyond the one returned for an SDL event
+    pending: VecDeque<KeyEvent>,
+}
+
+impl Controllers {
+    /// Creates a new Controllers instance. The controllers connected at the start are opened when
+    /// SDL reports them as added.
+    pub fn new(sdl_context: &Sdl, bindings: ControllerBindings) -> Result<Self, Box<dyn Error>> {
+        let subsystem = sdl_context.game_controller()?;
+        Ok(Self {
+            subsystem,
+            bindings,
+            open: HashMap::new(),
+            held: HeldButtons::default(),
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Returns the next event left over from an earlier SDL event.
+    pub fn pending_event(&mut self) -> Option<KeyEvent> {
+        self.pending.pop_front()
+    }
+
+    /// Handles a controller event, returning the key event it maps to, if any.
+    pub fn handle_event(&mut self, event: &Event) -> Option<KeyEvent> {
+        match event {
+            Event::ControllerDeviceAdded { which, .. } => {
+                self.add(*which);
+                None
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.remove(*which);
+                self.pending.pop_front()
+            }
+            Event::ControllerButtonDown { which, button, .. } => {
+                self.press(*which, &button.string())
+            }
+            Event::ControllerButtonUp { which, button, .. } => {
+                self.release(*which, &button.string())
+            }
+            Event::ControllerAxisMotion {
+                which, axis, value, ..
+            } if matches!(axis, Axis::TriggerLeft | Axis::TriggerRight) => {
+                if *value >= TRIGGER_THRESHOLD {
+                    self.press(*which, &axis.string())
+                } else {
+                    self.release(*which, &axis.string())
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Opens the controller with the joystick index.
+    fn add(&mut self, joystick_index: u32) {
+        match self.subsystem.open(joystick_index) {
+            Ok(controller) => {
+                println!("Controller connected: {}", controller.name());
+                self.open.insert(controller.instance_id(), controller);
+            }
+            Err(e) => eprintln!("Warning: Failed to open controller: {e}"),
+        }
+    }
+
+    /// Closes the controller with the instance id, releasing its held keys.
+    fn remove(&mut self, instance_id: u32) {
+        if let Some(controller) = self.open.remove(&instance_id) {
+            println!("Controller disconnected: {}", controller.name());
+        }
+        self.pending
+            .extend(self.held.release_controller(instance_id));
+    }
+
+    /// Presses the button, if it is bound.
+    fn press(&mut self, instance_id: u32, button: &str) -> Option<KeyEvent> {
+        let binding = self.bindings.lookup(button)?;
+        self.held.press(instance_id, button, binding)
+    }
+
+    /// Releases the button, if it is held.
+    fn release(&mut self, instance_id: u32, button: &str) -> Option<KeyEvent> {
+        self.held.release(instance_id, button)
+    }
+}
+
+/// The buttons held down on all controllers, with their bindings. Buttons bound to the same
+/// key hold it together: the key is pressed by the first and released by the last of them.
+#[derive(Default)]
+struct HeldButtons {
+    /// The bindings of the held buttons, by joystick instance id and button name
+    held: HashMap<(u32, String), Binding>,
+}
+
+impl HeldButtons {
+    /// Presses the button, returning the event if its binding isn't held yet.
+    fn press(&mut self, instance_id: u32, button: &str, binding: Binding) -> Option<KeyEvent> {
+        if self.is_held(binding) {
+            self.held.insert((instance_id, button.to_string()), binding);
+            return None;
+        }
+        self.held.insert((instance_id, button.to_string()), binding);
+        Some(match binding {
+            Binding::Key(k) => KeyEvent::KeyDown(k),
+            Binding::Action(action) => action.press_event(),
+        })
+    }
+
+    /// Releases the button, returning the event if no other held button has its binding.
+    fn release(&mut self, instance_id: u32, button: &str) -> Option<KeyEvent> {
+        let binding = self.held.remove(&(instance_id, button.to_string()))?;
+        if self.is_held(binding) {
+            return None;
+        }
+        release_event(binding)
+    }
+
+    /// Releases all buttons of the controller, returning the events.
+    fn release_controller(&mut self, instance_id: u32) -> Vec<KeyEvent> {
+        let buttons: Vec<String> = self
+            .held
+            .keys()
+            .filter(|(id, _)| *id == instance_id)
+            .map(|(_, button)| button.clone())
+            .collect();
+        buttons
+            .iter()
+            .filter_map(|button| self.release(instance_id, button))
+            .collect()
+    }
+
+    fn is_held(&self, binding: Binding) -> bool {
+        self.held.values().any(|b| *b == binding)
+    }
+}
+
+/// Returns the key event for releasing the binding, for the bindings which are held.
+fn release_event(binding: Binding) -> Option<KeyEvent> {
+    match binding {
+        Binding::Key(k) => Some(KeyEvent::KeyUp(k)),
+        Binding::Action(action) => action.release_event(),
+    }
+}
+
+#[path = "unittest/test_controller.rs"]
+#[cfg(test)]
+mod test_controller;
@@ -0,0 +1,610 @@
+//! # Debugger
+//!
+//! Contains an interactive debugger, running the emulator connected to a system like `Emulator::run`,
+//! but pausing the program on breakpoints and taking commands from the terminal while paused.
+//!
+//! The program is paused before executing an instruction when:
+//! - PC reaches a breakpoint
+//! - The instruction reads or writes a watched address, including writes by FX33, FX55 and 5XY2
+//! - A register condition becomes true, e.g. `V3 == 05`
+//! - The op code at PC can't be decoded
+//!
+//! Enter `help` (or `h`) while paused to list the commands.
+
+use crate::emulator::{Emulator, FrameResult, KeyEvent, System};
+use crate::headless;
+use crate::opcode::OpCode;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::Duration;
+
+/// Kind of memory access a watchpoint breaks on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// Comparison of a register condition
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Register of a register condition
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Register {
+    V(u8),
+    I,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Breakpoint {
+    /// Break before executing the instruction at the address
+    Pc(u16),
+    /// Break before an instruction accessing the address
+    Watch { addr: u16, access: Access },
+    /// Break when the condition becomes true
+    Condition {
+        reg: Register,
+        cmp: Comparison,
+        val: u16,
+    },
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Pc(addr) => write!(f, "break at {addr:04X}"),
+            Breakpoint::Watch { addr, access } => {
+                let access = match access {
+                    Access::Read => "read",
+                    Access::Write => "write",
+                    Access::ReadWrite => "read/write",
+                };
+                write!(f, "watch {access} of {addr:04X}")
+            }
+            Breakpoint::Condition { reg, cmp, val } => {
+                let cmp = match cmp {
+                    Comparison::Eq => "==",
+                    Comparison::Ne => "!=",
+                    Comparison::Lt => "<",
+                    Comparison::Le => "<=",
+                    Comparison::Gt => ">",
+                    Comparison::Ge => ">=",
+                };
+                match reg {
+                    Register::V(x) => write!(f, "when V{x:X} {cmp} {val:02X}"),
+                    Register::I => write!(f, "when I {cmp} {val:04X}"),
+                }
+            }
+        }
+    }
+}
+
+/// A debugger command
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Continue,
+    Step(u32),
+    Next,
+    Out,
+    Add(Breakpoint),
+    Info,
+    Delete(u32),
+    Registers,
+    List(Option<u16>),
+    Examine { addr: u16, len: u16 },
+    Help,
+    Quit,
+}
+
+/// Why the program was paused
+#[derive(Debug, Clone, PartialEq)]
+pub enum PauseReason {
+    /// The debugger was just started
+    Started,
+    /// A step command was completed
+    Stepped,
+    /// The breakpoint with the number was hit
+    Breakpoint(u32),
+    /// The op code at PC couldn't be decoded
+    InvalidOpCode,
+    /// The program was halted by the exit op code (00FD)
+    Halted,
+}
+
+/// What the debugger is doing
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Paused,
+    Running,
+    /// Running until returning to the address at the given stack depth
+    StepOver {
+        ret: u16,
+        depth: usize,
+    },
+    /// Running until the stack is below the given depth
+    StepOut {
+        depth: usize,
+    },
+}
+
+pub struct Debugger {
+    breakpoints: Vec<(u32, Breakpoint)>,
+    next_number: u32,
+    /// Result of the last evaluation of each condition, to only break when a condition becomes true
+    conditions_met: HashMap<u32, bool>,
+    mode: Mode,
+    /// Set when resuming, to not break again on the instruction the program was paused at
+    skip_check: bool,
+    pause_reason: Option<PauseReason>,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debugger {
+    /// Creates a new Debugger instance without any breakpoints, with the program paused.
+    pub fn new() -> Self {
+        Self {
+            breakpoints: Vec::new(),
+            next_number: 1,
+            conditions_met: HashMap::new(),
+            mode: Mode::Paused,
+            skip_check: false,
+            pause_reason: None,
+        }
+    }
+
+    /// The breakpoints with their numbers.
+    pub fn breakpoints(&self) -> &[(u32, Breakpoint)] {
+        &self.breakpoints
+    }
+
+    /// Adds a breakpoint and returns its number.
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint, emulator: &Emulator) -> u32 {
+        let number = self.next_number;
+        self.next_number += 1;
+        if let Breakpoint::Condition { reg, cmp, val } = breakpoint {
+            // A condition which is already true doesn't break until it becomes true again
+            self.conditions_met
+                .insert(number, condition_met(emulator, reg, cmp, val));
+        }
+        self.breakpoints.push((number, breakpoint));
+        number
+    }
+
+    /// Deletes the breakpoint with the number. Returns false if there is no such breakpoint.
+    pub fn delete_breakpoint(&mut self, number: u32) -> bool {
+        self.conditions_met.remove(&number);
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|(n, _)| *n != number);
+        self.breakpoints.len() != len
+    }
+
+    /// Checks if the program should be paused before executing the instruction at PC.
+    pub fn check(&mut self, emulator: &Emulator) -> Option<PauseReason> {
+        // The conditions are always evaluated, to keep track of when they become true
+        let mut reason = None;
+        for (number, breakpoint) in &self.breakpoints {
+            if let Breakpoint::Condition { reg, cmp, val } = *breakpoint {
+                let met = condition_met(emulator, reg, cmp, val);
+                let was_met = self.conditions_met.insert(*number, met).unwrap_or(false);
+                if met && !was_met && reason.is_none() {
+                    reason = Some(PauseReason::Breakpoint(*number));
+                }
+            }
+        }
+        if std::mem::take(&mut self.skip_check) {
+            return None;
+        }
+        match self.mode {
+            Mode::StepOver { ret, depth }
+                if emulator.pc() == ret && emulator.stack().len() == depth =>
+            {
+                return Some(PauseReason::Stepped);
+            }
+            Mode::StepOut { depth } if emulator.stack().len() < depth => {
+                return Some(PauseReason::Stepped);
+            }
+            _ => (),
+        }
+        if reason.is_some() {
+            return reason;
+        }
+
+        let opcode = emulator.next_opcode();
+        let access = opcode.and_then(|op| emulator.memory_access(op));
+        for (number, breakpoint) in &self.breakpoints {
+            let hit = match *breakpoint {
+                Breakpoint::Pc(addr) => emulator.pc() == addr,
+                Breakpoint::Watch { addr, access: kind } => access.as_ref().is_some_and(|a| {
+                    let kind_matches = match kind {
+                        Access::Read => !a.write,
+                        Access::Write => a.write,
+                        Access::ReadWrite => true,
+                    };
+                    kind_matches && a.contains(addr as usize)
+                }),
+                Breakpoint::Condition { .. } => false,
+            };
+            if hit {
+                return Some(PauseReason::Breakpoint(*number));
+            }
+        }
+        if opcode.is_none() {
+            return Some(PauseReason::InvalidOpCode);
+        }
+        None
+    }
+
+    /// Runs the emulator connected to the system, starting out paused. Takes the commands line by line
+    /// from the receiver while paused, and returns when quitting or when the receiver is disconnected.
+    pub fn run<T: System>(
+        &mut self,
+        emulator: &mut Emulator,
+        system_handle: &mut T,
+        commands: &Receiver<String>,
+    ) {
+        let frame_time = Duration::new(
+            0,
+            1_000_000_000u32 / emulator.options().timing.display_frequency,
+        );
+        self.mode = Mode::Paused;
+        self.print_pause(PauseReason::Started, emulator);
+        loop {
+            if self.mode == Mode::Paused {
+                // Keep handling the keys while paused, so that they can be held while stepping
+                while let Some(k) = system_handle.get_key_event() {
+                    match k {
+                        KeyEvent::Quit => return,
+                        KeyEvent::KeyDown(k) => emulator.set_key(k, true),
+                        KeyEvent::KeyUp(k) => emulator.set_key(k, false),
+                        _ => (),
+                    }
+                }
+                match commands.try_recv() {
+                    Ok(line) => {
+                        if !self.execute_line(&line, emulator, system_handle) {
+                            return;
+                        }
+                    }
+                    Err(TryRecvError::Empty) => std::thread::sleep(frame_time),
+                    Err(TryRecvError::Disconnected) => return,
+                }
+                continue;
+            }
+
+            self.pause_reason = None;
+            let result = emulator.run_system_frame(system_handle, |e| {
+                self.pause_reason = self.check(e);
+                self.pause_reason.is_some()
+            });
+            match result {
+                FrameResult::Completed => std::thread::sleep(frame_time),
+                FrameResult::Stopped => {
+                    let reason = self.pause_reason.take().unwrap_or(PauseReason::Stepped);
+                    self.print_pause(reason, emulator);
+                }
+                FrameResult::Halted => self.print_pause(PauseReason::Halted, emulator),
+                FrameResult::Quit => return,
+            }
+        }
+    }
+
+    /// Parses and executes a command line. Returns false if the emulator should quit.
+    fn execute_line<T: System>(
+        &mut self,
+        line: &str,
+        emulator: &mut Emulator,
+        system_handle: &mut T,
+    ) -> bool {
+        if line.trim().is_empty() {
+            prompt();
+            return true;
+        }
+        match parse_command(line) {
+            Ok(command) => self.execute(command, emulator, system_handle),
+            Err(e) => {
+                println!("Error: {e}");
+                prompt();
+                true
+            }
+        }
+    }
+
+    /// Executes a command. Returns false if the emulator should quit.
+    fn execute<T: System>(
+        &mut self,
+        command: Command,
+        emulator: &mut Emulator,
+        system_handle: &mut T,
+    ) -> bool {
+        match command {
+            Command::Continue => self.resume(Mode::Running),
+            Command::Step(count) => {
+                for _ in 0..count {
+                    emulator.step();
+                }
+                system_handle.update_screen(emulator.framebuffer());
+                self.print_pause(PauseReason::Stepped, emulator);
+            }
+            Command::Next => match emulator.next_opcode() {
+                Some(op @ OpCode::Call(_)) => self.resume(Mode::StepOver {
+                    ret: emulator.pc().wrapping_add(op.size()),
+                    depth: emulator.stack().len(),
+                }),
+                _ => return self.execute(Command::Step(1), emulator, system_handle),
+            },
+            Command::Out => {
+                if emulator.stack().is_empty() {
+                    println!("Error: not in a subroutine");
+                    prompt();
+                } else {
+                    self.resume(Mode::StepOut {
+                        depth: emulator.stack().len(),
+                    });
+                }
+            }
+            Command::Add(breakpoint) => {
+                let number = self.add_breakpoint(breakpoint, emulator);
+                println!("Breakpoint {number}: {breakpoint}");
+                prompt();
+            }
+            Command::Info => {
+                if self.breakpoints.is_empty() {
+                    println!("No breakpoints");
+                }
+                for (number, breakpoint) in &self.breakpoints {
+                    println!("{number}: {breakpoint}");
+                }
+                prompt();
+            }
+            Command::Delete(number) => {
+                if !self.delete_breakpoint(number) {
+                    println!("Error: no breakpoint {number}");
+                }
+                prompt();
+            }
+            Command::Registers => {
+                print!("{}", headless::dump_registers(emulator));
+                prompt();
+            }
+            Command::List(addr) => {
+                print!(
+                    "{}",
+                    disassemble_around(emulator, addr.unwrap_or(emulator.pc()))
+                );
+                prompt();
+            }
+            Command::Examine { addr, len } => {
+                print!("{}", dump_memory(emulator, addr, len));
+                prompt();
+            }
+            Command::Help => {
+                print!("{HELP}");
+                prompt();
+            }
+            Command::Quit => return false,
+        }
+        true
+    }
+
+    /// Resumes running the program in the given mode.
+    fn resume(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.skip_check = true;
+    }
+
+    /// Pauses the program, and shows why together with the registers and the disassembly.
+    fn print_pause(&mut self, reason: PauseReason, emulator: &Emulator) {
+        self.mode = Mode::Paused;
+        match reason {
+            PauseReason::Started => println!("Paused, type h for help"),
+            PauseReason::Stepped => (),
+            PauseReason::Breakpoint(number) => {
+                let breakpoint = self.breakpoints.iter().find(|(n, _)| *n == number);
+                match breakpoint {
+                    Some((_, b)) => println!("Breakpoint {number}: {b}"),
+                    None => println!("Breakpoint {number}"),
+                }
+            }
+            PauseReason::InvalidOpCode => println!("Failed to decode op code"),
+            PauseReason::Halted => println!("Program halted"),
+        }
+        print!("{}", headless::dump_registers(emulator));
+        print!("{}", disassemble_around(emulator, emulator.pc()));
+        prompt();
+    }
+}
+
+/// The command list shown by the `help` command
+const HELP: &str = "\
+c, continue                Run until the next break
+s, step [count]            Execute one (or count) instructions
+n, next                    Execute one instruction, stepping over subroutine calls
+o, out                     Run until the current subroutine returns
+b, break <addr>            Break when PC reaches the address
+w, watch <addr> [r|w|rw]   Break on reads and/or writes of the address, both by default
+when <reg> <op> <value>    Break when the condition becomes true, reg is V0-VF or I,
+                           op is one of == != < <= > >=
+i, info                    List the breakpoints
+d, delete <number>         Delete a breakpoint
+r, regs                    Show the registers and the stack
+l, list [addr]             Show the disassembly around PC or the address
+x <addr> [length]          Show the memory contents
+h, help                    Show the commands
+q, quit                    Quit the emulator
+Addresses, values and lengths are hexadecimal.
+";
+
+/// Prints the command prompt.
+fn prompt() {
+    print!("(chip8) ");
+    let _ = std::io::stdout().flush();
+}
+
+/// Spawns a thread reading commands from stdin, one per line.
+pub fn stdin_commands() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Utility function to evaluate a register condition.
+fn condition_met(emulator: &Emulator, reg: Register, cmp: Comparison, val: u16) -> bool {
+    let reg_val = match reg {
+        Register::V(x) => emulator.registers()[x as usize & 0xF] as u16,
+        Register::I => emulator.index(),
+    };
+    match cmp {
+        Comparison::Eq => reg_val == val,
+        Comparison::Ne => reg_val != val,
+        Comparison::Lt => reg_val < val,
+        Comparison::Le => reg_val <= val,
+        Comparison::Gt => reg_val > val,
+        Comparison::Ge => reg_val >= val,
+    }
+}
+
+/// Disassembles the instructions around the address, marking the one at PC.
+pub fn disassemble_around(emulator: &Emulator, addr: u16) -> String {
+    let memory = emulator.memory();
+    let start = addr.saturating_sub(6) as usize;
+    let end = (addr as usize + 10).min(memory.len());
+    let mut listing = String::new();
+    for a in (start..end).step_by(2) {
+        let marker = if a == emulator.pc() as usize {
+            '>'
+        } else {
+            ' '
+        };
+        let bytes = &memory[a..(a + 2).min(memory.len())];
+        let hex: String = bytes.iter().map(|b| format!("{b:02X}")).collect();
+        let text = match OpCode::from_bytes(&memory[a..]) {
+            Some(op) => op.to_string(),
+            None => "???".to_string(),
+        };
+        listing.push_str(&format!("{marker} {a:04X}: {hex:4}  {text}\n"));
+    }
+    listing
+}
+
+/// Dumps the memory contents as hexadecimal bytes, 16 bytes per line.
+fn dump_memory(emulator: &Emulator, addr: u16, len: u16) -> String {
+    let memory = emulator.memory();
+    let start = (addr as usize).min(memory.len());
+    let end = (start + len as usize).min(memory.len());
+    let mut dump = String::new();
+    for (n, row) in memory[start..end].chunks(16).enumerate() {
+        let hex: Vec<String> = row.iter().map(|b| format!("{b:02X}")).collect();
+        dump.push_str(&format!("{:04X}: {}\n", start + n * 16, hex.join(" ")));
+    }
+    dump
+}
+
+/// Utility function to parse a hexadecimal number, with or without a 0x prefix.
+fn parse_hex(s: &str) -> Result<u16, String> {
+    let digits = s.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid hexadecimal number {s}"))
+}
+
+/// Parses a debugger command line.
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let arg = |n: usize| -> Result<u16, String> {
+        fields
+            .get(n)
+            .ok_or_else(|| format!("missing argument to {}", fields[0]))
+            .and_then(|f| parse_hex(f))
+    };
+    let command = match fields.first().copied().unwrap_or_default() {
+        "c" | "continue" => Command::Continue,
+        "s" | "step" => match fields.get(1) {
+            Some(n) => Command::Step(n.parse().map_err(|_| format!("invalid count {n}"))?),
+            None => Command::Step(1),
+        },
+        "n" | "next" => Command::Next,
+        "o" | "out" => Command::Out,
+        "b" | "break" => Command::Add(Breakpoint::Pc(arg(1)?)),
+        "w" | "watch" => {
+            let access = match fields.get(2).copied() {
+                None | Some("rw") => Access::ReadWrite,
+                Some("r") => Access::Read,
+                Some("w") => Access::Write,
+                Some(a) => return Err(format!("invalid access {a}, expected r, w or rw")),
+            };
+            Command::Add(Breakpoint::Watch {
+                addr: arg(1)?,
+                access,
+            })
+        }
+        "when" => {
+            let reg = match fields.get(1).map(|r| r.to_ascii_uppercase()) {
+                Some(r) if r == "I" => Register::I,
+                Some(r) if r.len() == 2 && r.starts_with('V') => Register::V(
+                    parse_hex(&r[1..]).map_err(|_| format!("invalid register {r}"))? as u8,
+                ),
+                Some(r) => return Err(format!("invalid register {r}, expected V0-VF or I")),
+                None => return Err("missing register".to_string()),
+            };
+            let cmp = match fields.get(2).copied() {
+                Some("==") => Comparison::Eq,
+                Some("!=") => Comparison::Ne,
+                Some("<") => Comparison::Lt,
+                Some("<=") => Comparison::Le,
+                Some(">") => Comparison::Gt,
+                Some(">=") => Comparison::Ge,
+                _ => return Err("invalid comparison, expected == != < <= > or >=".to_string()),
+            };
+            Command::Add(Breakpoint::Condition {
+                reg,
+                cmp,
+                val: arg(3)?,
+            })
+        }
+        "i" | "info" => Command::Info,
+        "d" | "delete" => match fields.get(1) {
+            Some(n) => Command::Delete(n.parse().map_err(|_| format!("invalid number {n}"))?),
+            None => return Err("missing breakpoint number".to_string()),
+        },
+        "r" | "regs" => Command::Registers,
+        "l" | "list" => match fields.get(1) {
+            Some(_) => Command::List(Some(arg(1)?)),
+            None => Command::List(None),
+        },
+        "x" => Command::Examine {
+            addr: arg(1)?,
+            len: match fields.get(2) {
+                Some(_) => arg(2)?,
+                None => 16,
+            },
+        },
+        "h" | "help" => Command::Help,
+        "q" | "quit" => Command::Quit,
+        c => return Err(format!("unknown command {c}, type h for help")),
+    };
+    Ok(command)
+}
+
+#[path = "unittest/test_debugger.rs"]
+#[cfg(test)]
+mod test_debugger;
@@ -0,0 +1,177 @@
+//! # Disassembler
+//!
+//! Turns a ROM back into assembly source, using the mnemonics of [`OpCode`].
+//! The code is told apart from the data by following the control flow from the start address,
+//! through jumps, calls and skips. Everything that isn't reached is printed as `db` bytes.
+//!
+//! Targets of jumps, calls and index loads within the ROM get generated labels: `sub_XXXX` for
+//! subroutines, `loc_XXXX` for jump targets and `data_XXXX` for index targets. A target in the
+//! middle of an instruction or a data line is defined as a constant instead, e.g. `loc_0203 = 0x203`.
+//!
+//! ```text
+//!     LD I, data_0206          ; 0200: A206
+//!     DRW V0, V1, 2            ; 0202: D012
+//! loc_0204:
+//!     JP loc_0204              ; 0204: 1204
+//! data_0206:
+//!     db 0x3C, 0x42            ; 0206
+//! ```
+
+use crate::opcode::OpCode;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Number of bytes per `db` line
+const BYTES_PER_LINE: usize = 8;
+
+/// Column of the comment with the address and the op code bytes
+const COMMENT_COLUMN: usize = 28;
+
+/// How an address is referred to, in order of priority when naming the label
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum Reference {
+    Data,
+    Jump,
+    Call,
+}
+
+/// Disassembles the ROM loaded at the start address.
+pub fn disassemble(rom: &[u8], start: u16) -> String {
+    let start = start as usize;
+    let end = start + rom.len();
+    let in_rom = |addr: usize| (start..end).contains(&addr);
+
+    // Follow the control flow, recording the size of the instruction at each address reached
+    let mut code: Vec<Option<u16>> = vec![None; rom.len()];
+    let mut covered = vec![false; rom.len()];
+    let mut references: BTreeMap<usize, Reference> = BTreeMap::new();
+    let mut pending = vec![start];
+    while let Some(addr) = pending.pop() {
+        if !in_rom(addr) || code[addr - start].is_some() || covered[addr - start] {
+            continue;
+        }
+        let Some(op) = OpCode::from_bytes(&rom[addr - start..]) else {
+            continue;
+        };
+        let size = op.size() as usize;
+        if (addr..addr + size).any(|a| !in_rom(a) || covered[a - start]) {
+            continue;
+        }
+        code[addr - start] = Some(op.size());
+        for a in addr..addr + size {
+            covered[a - start] = true;
+        }
+
+        let next = addr + size;
+        if let Some(target) = op.target().map(|t| t as usize) {
+            let reference = match op {
+                OpCode::Call(_) => Reference::Call,
+                OpCode::Jump(_) | OpCode::JumpWithOffset { .. } => Reference::Jump,
+                _ => Reference::Data,
+            };
+            if in_rom(target) {
+                let r = references.entry(target).or_insert(reference);
+                if reference > *r {
+                    *r = reference;
+                }
+            }
+        }
+        match op {
+            OpCode::Jump(target) => pending.push(target as usize),
+            OpCode::Call(target) => {
+                pending.push(target as usize);
+                pending.push(next);
+            }
+            // The target of a jump with offset depends on a register, so it can't be followed
+            OpCode::Return | OpCode::Exit | OpCode::JumpWithOffset { .. } => (),
+            OpCode::SkipIfVxEq { .. }
+            | OpCode::SkipIfVxNeq { .. }
+            | OpCode::SkipIfVxEqVy { .. }
+            | OpCode::SkipIfVxNeqVy { .. }
+            | OpCode::SkipIfKeyPressed { .. }
+            | OpCode::SkipIfKeyNotPressed { .. } => {
+                pending.push(next);
+                // Skipping over F000 NNNN skips four bytes
+                let skipped = match rom.get(next - start..next - start + 2) {
+                    Some([0xF0, 0x00]) => 4,
+                    _ => 2,
+                };
+                pending.push(next + skipped);
+            }
+            _ => pending.push(next),
+        }
+    }
+
+    let label = |addr: usize| -> Option<String> {
+        references.get(&addr).map(|r| match r {
+            Reference::Call => format!("sub_{addr:04X}"),
+            Reference::Jump => format!("loc_{addr:04X}"),
+            Reference::Data => format!("data_{addr:04X}"),
+        })
+    };
+
+    // Split the ROM into lines, each either one instruction or up to BYTES_PER_LINE data bytes
+    let mut lines: Vec<(usize, Option<OpCode>, usize)> = Vec::new();
+    let mut addr = start;
+    while addr < end {
+        match code[addr - start] {
+            Some(size) => {
+                let op = OpCode::from_bytes(&rom[addr - start..]);
+                lines.push((addr, op, size as usize));
+                addr += size as usize;
+            }
+            None => {
+                let mut len = 1;
+                while len < BYTES_PER_LINE
+                    && addr + len < end
+                    && !covered[addr + len - start]
+                    && !references.contains_key(&(addr + len))
+                {
+                    len += 1;
+                }
+                lines.push((addr, None, len));
+                addr += len;
+            }
+        }
+    }
+
+    let mut source = String::new();
+    // Targets which aren't at the start of a line can't be labels
+    for &addr in references.keys() {
+        let at_line_start = lines.iter().any(|(a, _, _)| *a == addr);
+        if let Some(name) = label(addr).filter(|_| !at_line_start) {
+            let _ = writeln!(source, "{name} = 0x{addr:03X}");
+        }
+    }
+    for (addr, op, len) in lines {
+        if let Some(name) = label(addr) {
+            let _ = writeln!(source, "{name}:");
+        }
+        let bytes = &rom[addr - start..addr - start + len];
+        let (text, comment) = match op {
+            Some(op) => {
+                let target = op.target().and_then(|t| label(t as usize));
+                let text = match target {
+                    Some(target) => op.to_string_with_target(&target),
+                    None => op.to_string(),
+                };
+                let hex: String = bytes.iter().map(|b| format!("{b:02X}")).collect();
+                (text, format!("{addr:04X}: {hex}"))
+            }
+            None => {
+                let data: Vec<String> = bytes.iter().map(|b| format!("0x{b:02X}")).collect();
+                (format!("db {}", data.join(", ")), format!("{addr:04X}"))
+            }
+        };
+        let _ = writeln!(
+            source,
+            "    {text:width$} ; {comment}",
+            width = COMMENT_COLUMN - 4
+        );
+    }
+    source
+}
+
+#[path = "unittest/test_disassembler.rs"]
+#[cfg(test)]
+mod test_disassembler;
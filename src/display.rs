@@ -1,10 +1,15 @@
 //! # Display
 //!
 //! Contains the Display module, creating a canvas for an SLD2 context,
-//! and draws pixels according to the input display buffer.
+//! and draws pixels according to the input framebuffer.
+//!
+//! With vsync, the screen is presented at the vertical blank of the monitor rather than on each
+//! update, once per wait of the emulator loop, which the vertical blank then paces.
 extern crate sdl2;
 
 use crate::chip8options;
+use crate::framebuffer::Framebuffer;
+use crate::pacing::Speed;
 use sdl2::{Sdl, pixels::Color, rect::Rect, render::Canvas, video::Window};
 use std::error::Error;
 
@@ -13,8 +18,11 @@ pub struct Display {
     screen_width: u32,
     screen_height: u32,
     scaling: u32,
-    on_color: Color,
-    off_color: Color,
+    /// Colors indexed by the plane bit mask of a pixel
+    colors: [Color; 4],
+    vsync: bool,
+    /// The screen presented at the next vertical blank, with vsync
+    framebuffer: Option<Framebuffer>,
 }
 
 impl Display {
@@ -35,51 +43,91 @@ impl Display {
             )
             .position_centered()
             .build()?;
-        let canvas = window.into_canvas().build()?;
+        let canvas = if disp_options.vsync {
+            window.into_canvas().present_vsync().build()?
+        } else {
+            window.into_canvas().build()?
+        };
         Ok(Self {
             canvas,
             screen_width,
             screen_height,
             scaling,
-            on_color: Color::RGB(
-                disp_options.color_on_rgb.0,
-                disp_options.color_on_rgb.1,
-                disp_options.color_on_rgb.1,
-            ),
-            off_color: Color::RGB(
-                disp_options.color_off_rgb.0,
-                disp_options.color_off_rgb.1,
-                disp_options.color_off_rgb.1,
-            ),
+            colors: [
+                rgb_color(disp_options.color_off_rgb),
+                rgb_color(disp_options.color_on_rgb),
+                rgb_color(disp_options.color_plane2_rgb),
+                rgb_color(disp_options.color_both_rgb),
+            ],
+            vsync: disp_options.vsync,
+            framebuffer: None,
         })
     }
 
-    /// Updates the display with the input display buffer.
-    /// The length of the display_data should match the total number of pixels.
-    /// If it's too short, the remaining pixels will be left at background color.
-    /// If it's too long, the overshooting elements will be ignored.
-    pub fn draw_screen(&mut self, display_data: &[bool]) {
-        self.canvas.set_draw_color(self.off_color);
+    /// Updates the display with the input framebuffer. The pixels are scaled to fill the window,
+    /// so a high resolution framebuffer (e.g. SUPER-CHIP 128x64) is drawn with smaller pixels
+    /// than a low resolution one. Each pixel gets the color matching the planes it is set in.
+    /// With vsync, the framebuffer is kept for the next vertical blank instead.
+    pub fn draw_screen(&mut self, framebuffer: &Framebuffer) {
+        if self.vsync {
+            self.framebuffer = Some(framebuffer.clone());
+        } else {
+            self.render(framebuffer);
+        }
+    }
+
+    /// Presents the last screen at the vertical blank, waiting for it. Returns false without vsync.
+    pub fn wait_vsync(&mut self) -> bool {
+        if !self.vsync {
+            return false;
+        }
+        // The canvas contents are undefined after presenting, so the screen is drawn every time
+        if let Some(framebuffer) = self.framebuffer.take() {
+            self.render(&framebuffer);
+            self.framebuffer = Some(framebuffer);
+        } else {
+            self.canvas.present();
+        }
+        true
+    }
+
+    /// Shows the speed in the window title, with the frames dropped if there were any.
+    pub fn show_speed(&mut self, speed: Speed) {
+        let mut title = format!(
+            "CHIP-8 emulator - {:.0} FPS, {:.0} IPS",
+            speed.fps, speed.ips
+        );
+        if speed.dropped > 0 {
+            title.push_str(&format!(", {} frames dropped", speed.dropped));
+        }
+        let _ = self.canvas.window_mut().set_title(&title);
+    }
+
+    fn render(&mut self, framebuffer: &Framebuffer) {
+        self.canvas.set_draw_color(self.colors[0]);
         self.canvas.clear();
 
-        self.canvas.set_draw_color(self.on_color);
+        let width = framebuffer.width().max(1) as u32;
+        let height = framebuffer.height().max(1) as u32;
+        let pixel_width = (self.screen_width * self.scaling / width).max(1);
+        let pixel_height = (self.screen_height * self.scaling / height).max(1);
 
-        for (i, v) in display_data.iter().enumerate() {
-            if i > self.screen_width as usize * self.screen_height as usize {
-                break;
-            }
-            if *v {
-                let x = (i as u32 % self.screen_width) * self.scaling;
-                let y = (i as u32 / self.screen_width) * self.scaling;
-                let _ = self.canvas.fill_rect(Rect::new(
-                    x as i32,
-                    y as i32,
-                    self.scaling,
-                    self.scaling,
-                ));
+        for (i, v) in framebuffer.pixels().iter().enumerate() {
+            if *v > 0 {
+                self.canvas.set_draw_color(self.colors[*v as usize & 0x3]);
+                let x = (i as u32 % width) * pixel_width;
+                let y = (i as u32 / width) * pixel_height;
+                let _ =
+                    self.canvas
+                        .fill_rect(Rect::new(x as i32, y as i32, pixel_width, pixel_height));
             }
         }
 
         self.canvas.present();
     }
 }
+
+/// Utility function to convert an RGB tuple from the options to an SDL color.
+fn rgb_color(rgb: (u8, u8, u8)) -> Color {
+    Color::RGB(rgb.0, rgb.1, rgb.2)
+}
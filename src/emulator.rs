@@ -2,11 +2,24 @@
 //!
 //! The main part of the CHIP-8 emulator module
 use crate::chip8options::Chip8options;
+use crate::framebuffer::{self, Framebuffer};
+use crate::headless::KeyScript;
+use crate::movie::Recorder;
 use crate::opcode::OpCode;
-use rand::Rng;
-use std::time::Duration;
+use crate::pacing::{FramePacer, Speed, SpeedMeter};
+use crate::random::{self, RandomSource};
+use crate::recording::GifRecorder;
+use crate::savestate::{MachineState, SaveState};
+use crate::trace::Tracer;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::time::Instant;
 
-const FONTS: [u8; 16 * 5] = [
+/// Maximum number of warnings waiting to be shown
+const MAX_PENDING_WARNINGS: usize = 64;
+
+pub(crate) const FONTS: [u8; 16 * 5] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
     0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
@@ -25,32 +38,156 @@ const FONTS: [u8; 16 * 5] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+pub(crate) const BIG_FONTS: [u8; 16 * 10] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x3C, 0x7E, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+    0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+/// Initial value of the XO-CHIP pitch register, giving a 4000 Hz sample rate
+const DEFAULT_PITCH: u8 = 64;
+
+/// The outcome of running one frame
+#[derive(Debug, PartialEq)]
+pub enum FrameResult {
+    /// All CPU cycles of the frame were executed
+    Completed,
+    /// The stop condition was met before executing the instruction at PC
+    Stopped,
+    /// The program was halted by the exit op code (00FD)
+    Halted,
+    /// The system requested to quit
+    Quit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum KeyEvent {
     KeyDown(u8),
     KeyUp(u8),
     Quit,
     Restart,
+    SaveState,
+    LoadState,
+    /// Rewind key pressed (true) or released (false)
+    Rewind(bool),
+    Screenshot,
+    /// Starts or stops recording the screen to an animated GIF
+    Record,
+    /// Pauses or resumes the emulator
+    Pause,
+    /// Runs a single frame, pausing the emulator first if it is running
+    FrameAdvance,
+    /// Turbo key pressed (true) or released (false), running the frames as fast as possible
+    Turbo(bool),
+    /// Switches turbo on or off
+    ToggleTurbo,
+    /// Switches slow motion on or off
+    SlowMotion,
+}
+
+impl KeyEvent {
+    /// True for the events controlling the speed of the emulator, which leave the machine state
+    /// as it is. They are neither recorded in movies nor taken from them.
+    pub fn is_speed_control(self) -> bool {
+        matches!(
+            self,
+            KeyEvent::Pause
+                | KeyEvent::FrameAdvance
+                | KeyEvent::Turbo(_)
+                | KeyEvent::ToggleTurbo
+                | KeyEvent::SlowMotion
+        )
+    }
+}
+
+/// Memory accessed by an instruction, not counting the fetch of the instruction itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryAccess {
+    /// True if the memory is written, false if it is read
+    pub write: bool,
+    /// The accessed addresses up to the end of memory
+    pub range: Range<usize>,
+    /// The accessed addresses wrapping around to the start of memory, empty if none
+    pub wrapped: Range<usize>,
+}
+
+impl MemoryAccess {
+    /// Returns true if the address is accessed.
+    pub fn contains(&self, addr: usize) -> bool {
+        self.range.contains(&addr) || self.wrapped.contains(&addr)
+    }
 }
 
 /// Traits to be supported by the surrounding CHIP8 system
 pub trait System {
-    /// Triggers a screen update, providing the current display output in a vector
-    fn update_screen(&mut self, display_output: &[bool]);
+    /// Triggers a screen update, providing the current framebuffer including the plane data
+    fn update_screen(&mut self, framebuffer: &Framebuffer);
 
     /// Polls for queued up key event
     fn get_key_event(&mut self) -> Option<KeyEvent>;
 
     /// Updates the beep sound state ON/OFF.
     fn set_sound_state(&mut self, _sound_on: bool) {}
+
+    /// Updates the XO-CHIP audio pattern buffer (128 1-bit samples) and the pitch register.
+    /// The pattern is None until the program loads one, meaning the default beep should be used.
+    fn set_audio_pattern(&mut self, _pattern: Option<&[u8; 16]>, _pitch: u8) {}
+
+    /// Stores a save state requested by the user, e.g. to a file.
+    fn store_state(&mut self, _state: SaveState) {}
+
+    /// Saves a screenshot of the framebuffer requested by the user, e.g. to a PNG file.
+    fn save_screenshot(&mut self, _framebuffer: &Framebuffer) {}
+
+    /// Opens the output of a recording requested by the user, e.g. a new GIF file.
+    /// None if recordings aren't supported or the output failed to open.
+    fn open_recording(&mut self) -> Option<Box<dyn Write>> {
+        None
+    }
+
+    /// Reports the end of a recording, with the error if writing it failed.
+    fn recording_stopped(&mut self, _result: io::Result<()>) {}
+
+    /// Presents the screen at the vertical blank of the display, waiting for it. Returns false if
+    /// the system doesn't sync to the display, in which case the emulator sleeps until the next frame.
+    fn wait_vsync(&mut self) -> bool {
+        false
+    }
+
+    /// Shows the speed the emulator runs at and the frames it dropped, measured about once a second.
+    fn show_speed(&mut self, _speed: Speed) {}
+
+    /// Shows a warning about the running program, e.g. an op code which couldn't be decoded.
+    fn show_warning(&mut self, message: &str) {
+        eprintln!("Warning: {message}");
+    }
+
+    /// Fetches the save state to restore when requested by the user, None if there is none.
+    fn fetch_state(&mut self) -> Option<SaveState> {
+        None
+    }
 }
 
 /// Contains the data for the emulator
 pub struct Emulator {
     rom: Vec<u8>,
     memory: Vec<u8>,
-    display_output: Vec<bool>,
-    display_width: u8,
-    display_height: u8,
+    framebuffer: Framebuffer,
+    hires: bool,
+    selected_planes: u8,
     pc: u16,
     reg_i: u16,
     stack: Vec<u16>,
@@ -58,10 +195,40 @@ pub struct Emulator {
     sound_timer: u8,
     reg_vx: [u8; 16],
     keypad: [bool; 16],
+    flags: [u8; 16],
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
     options: Chip8options,
-    rng: rand::rngs::ThreadRng,
+    seed: u64,
+    rng: Box<dyn RandomSource>,
+    /// Number of frames run since the start, not reset by restarts
+    frame: u64,
+    /// Number of instructions executed since the start, not reset by restarts
+    instructions: u64,
     display_updated: bool,
+    audio_updated: bool,
     sound_playing: bool,
+    halted: bool,
+    rewind_buffer: VecDeque<MachineState>,
+    rewinding: bool,
+    paused: bool,
+    /// A single frame is to be run while paused
+    frame_advance: bool,
+    /// The turbo key is held
+    turbo_held: bool,
+    /// Turbo is switched on
+    turbo_on: bool,
+    slow_motion: bool,
+    /// Warnings waiting to be shown by the system
+    warnings: Vec<String>,
+    tracer: Option<Tracer>,
+    recorder: Option<Recorder>,
+    playback: Option<KeyScript>,
+    /// The save state slot while a movie is recorded or played back, None without a movie.
+    /// It is kept in memory and starts out empty, so a replayed load restores the state saved
+    /// in the movie rather than a save state file, which a replayed save leaves alone.
+    movie_slot: Option<Option<SaveState>>,
+    gif_recorder: Option<GifRecorder>,
 }
 
 impl Emulator {
@@ -71,20 +238,23 @@ impl Emulator {
         for (i, n) in FONTS.iter().enumerate() {
             memory[i + options.memory.font_start as usize] = *n;
         }
+        for (i, n) in BIG_FONTS.iter().enumerate() {
+            memory[i + options.memory.big_font_start as usize] = *n;
+        }
         for (i, n) in rom.iter().enumerate() {
             memory[i + options.memory.rom_start as usize] = *n;
         }
+        let seed = options.random.seed.unwrap_or_else(rand::random);
 
         Self {
             rom: rom.to_vec(),
             memory,
-            display_output: vec![
-                false;
-                options.display.display_width as usize
-                    * options.display.display_height as usize
-            ],
-            display_width: options.display.display_width as u8,
-            display_height: options.display.display_height as u8,
+            framebuffer: Framebuffer::new(
+                options.display.display_width as usize,
+                options.display.display_height as usize,
+            ),
+            hires: false,
+            selected_planes: framebuffer::PLANE_1,
             pc: options.memory.rom_start,
             reg_i: 0,
             stack: Vec::new(),
@@ -92,21 +262,45 @@ impl Emulator {
             sound_timer: 0,
             reg_vx: [0; 16],
             keypad: [false; 16],
+            flags: [0; 16],
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
             options: *options,
-            rng: rand::rng(),
+            seed,
+            rng: random::from_seed(options.random.algorithm, seed),
+            frame: 0,
+            instructions: 0,
             display_updated: false,
+            audio_updated: false,
             sound_playing: false,
+            halted: false,
+            rewind_buffer: VecDeque::new(),
+            rewinding: false,
+            paused: false,
+            frame_advance: false,
+            turbo_held: false,
+            turbo_on: false,
+            slow_motion: false,
+            warnings: Vec::new(),
+            tracer: None,
+            recorder: None,
+            playback: None,
+            movie_slot: None,
+            gif_recorder: None,
         }
     }
 
     /// Restarts the emulator by resetting the memory and loading the ROM from scratch, resetting all registers etc.
-    fn reset(&mut self) {
+    pub fn reset(&mut self) {
         for i in self.memory.iter_mut() {
             *i = 0;
         }
         for (i, n) in FONTS.iter().enumerate() {
             self.memory[i + self.options.memory.font_start as usize] = *n;
         }
+        for (i, n) in BIG_FONTS.iter().enumerate() {
+            self.memory[i + self.options.memory.big_font_start as usize] = *n;
+        }
         for (i, n) in self.rom.iter().enumerate() {
             self.memory[i + self.options.memory.rom_start as usize] = *n;
         }
@@ -116,101 +310,707 @@ impl Emulator {
         self.delay_timer = 0;
         self.sound_timer = 0;
         self.sound_playing = false;
+        self.halted = false;
+        self.selected_planes = framebuffer::PLANE_1;
+        self.audio_pattern = None;
+        self.pitch = DEFAULT_PITCH;
+        self.audio_updated = true;
         for vx in self.reg_vx.iter_mut() {
             *vx = 0;
         }
-        // Clear the display output too just in case the program doesn't start with ClearScreen
-        for d in self.display_output.iter_mut() {
-            *d = false;
+        // Resetting the resolution also clears the display output, just in case the program
+        // doesn't start with ClearScreen. Note that the flag registers are intentionally kept.
+        self.set_resolution(false);
+    }
+
+    /// Switches between low resolution (the configured display size) and the SUPER-CHIP
+    /// high resolution mode (double width and height). The display output is cleared.
+    fn set_resolution(&mut self, hires: bool) {
+        let factor = if hires { 2 } else { 1 };
+        self.hires = hires;
+        self.framebuffer.resize(
+            (self.options.display.display_width * factor) as usize,
+            (self.options.display.display_height * factor) as usize,
+        );
+        self.display_updated = true;
+    }
+
+    /// Scrolls the selected planes of the display output by dx columns and dy rows.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        self.framebuffer.scroll(dx, dy, self.selected_planes);
+        self.display_updated = true;
+    }
+
+    /// Queues a warning for the system to show. Warnings beyond the limit are dropped, until the
+    /// queue is emptied.
+    fn warn(&mut self, message: &str) {
+        if self.warnings.len() < MAX_PENDING_WARNINGS {
+            self.warnings.push(message.to_string());
         }
     }
 
-    /// Starts running the emulator until the program is halted.
+    /// Takes the warnings not yet shown, for callers running the emulator with `step`. They are
+    /// otherwise shown at the end of each frame.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// The index of the memory at I plus the offset, wrapping around at the end of the memory.
+    fn memory_index(&self, offset: usize) -> usize {
+        (self.reg_i as usize + offset) % self.memory.len()
+    }
+
+    /// Skips the next instruction, taking into account that F000 NNNN is four bytes long.
+    fn skip_instruction(&mut self) {
+        let pc = self.pc as usize;
+        let skip = match self.memory.get(pc..pc + 2) {
+            Some([0xF0, 0x00]) => 4,
+            _ => 2,
+        };
+        self.pc = self.pc.wrapping_add(skip);
+    }
+
+    /// Takes a snapshot of the full machine state.
+    pub fn save_state(&self) -> SaveState {
+        SaveState {
+            rom: self.rom.clone(),
+            options: self.options,
+            machine: self.machine_state(),
+        }
+    }
+
+    /// Restores the machine state from a snapshot.
+    pub fn load_state(&mut self, state: &SaveState) {
+        self.rom = state.rom.clone();
+        self.options = state.options;
+        self.load_machine_state(&state.machine);
+    }
+
+    /// Takes a snapshot of the state changing while the program runs.
+    fn machine_state(&self) -> MachineState {
+        MachineState {
+            memory: self.memory.clone(),
+            framebuffer: self.framebuffer.clone(),
+            hires: self.hires,
+            selected_planes: self.selected_planes,
+            pc: self.pc,
+            reg_i: self.reg_i,
+            stack: self.stack.clone(),
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            reg_vx: self.reg_vx,
+            keypad: self.keypad,
+            flags: self.flags,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+            rng: self.rng.snapshot(),
+            halted: self.halted,
+        }
+    }
+
+    fn load_machine_state(&mut self, state: &MachineState) {
+        self.memory = state.memory.clone();
+        self.framebuffer = state.framebuffer.clone();
+        self.hires = state.hires;
+        self.selected_planes = state.selected_planes;
+        self.pc = state.pc;
+        self.reg_i = state.reg_i;
+        self.stack = state.stack.clone();
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.reg_vx = state.reg_vx;
+        self.keypad = state.keypad;
+        self.flags = state.flags;
+        self.audio_pattern = state.audio_pattern;
+        self.pitch = state.pitch;
+        if let Some(rng) = state.rng.restore() {
+            self.rng = rng;
+        }
+        self.halted = state.halted;
+        self.display_updated = true;
+        self.audio_updated = true;
+    }
+
+    /// Steps back one frame, restoring the state recorded at the start of the previous frame.
+    /// Returns false if there are no more frames in the rewind buffer.
+    pub fn rewind(&mut self) -> bool {
+        match self.rewind_buffer.pop_back() {
+            Some(state) => {
+                // Keep the keys as they are held now, rather than as they were held back then
+                let keypad = self.keypad;
+                self.load_machine_state(&state);
+                self.keypad = keypad;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Records the current state in the rewind buffer, dropping the oldest frames beyond
+    /// the configured depth.
+    fn record_rewind_frame(&mut self) {
+        let timing = &self.options.timing;
+        let depth = timing
+            .rewind_seconds
+            .saturating_mul(timing.display_frequency) as usize;
+        if depth == 0 {
+            return;
+        }
+        while self.rewind_buffer.len() >= depth {
+            self.rewind_buffer.pop_front();
+        }
+        self.rewind_buffer.push_back(self.machine_state());
+    }
+
+    /// Starts running the emulator until the program is halted, in real time at the display frequency.
+    /// The frames due are run, catching up on late frames, before waiting for the next one.
+    /// In turbo, the frames run back to back instead, and in slow motion they are further apart.
     pub fn run<T: System>(&mut self, system_handle: &mut T) {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(&self.options.timing, start);
+        let mut meter = SpeedMeter::new(start, self.frame, self.instructions, 0);
+        // Bounds the frames run without showing the screen, when not keeping up
+        let max_frames = self.options.timing.catch_up_frames + 1;
         'running: loop {
-            // Decrement timers
-            if self.delay_timer > 0 {
-                self.delay_timer -= 1;
+            if self.is_turbo() {
+                // One shown frame's worth, to keep handling the key events
+                for _ in 0..self.options.timing.turbo_frame_skip.max(1) {
+                    if self.run_system_frame(system_handle, |_| false) != FrameResult::Completed {
+                        break 'running;
+                    }
+                }
+                system_handle.wait_vsync();
+                pacer.restart(Instant::now());
+            } else {
+                pacer.set_slow_down(if self.slow_motion {
+                    self.options.timing.slow_motion_factor
+                } else {
+                    1
+                });
+                let mut frames = 0;
+                while frames < max_frames && pacer.frame_due(Instant::now()) {
+                    if self.run_system_frame(system_handle, |_| false) != FrameResult::Completed {
+                        break 'running;
+                    }
+                    frames += 1;
+                }
+                if !system_handle.wait_vsync() {
+                    std::thread::sleep(pacer.time_to_next_frame(Instant::now()));
+                }
             }
-            if self.sound_timer > 0 {
-                self.sound_timer -= 1;
+            let dropped = pacer.dropped();
+            if let Some(speed) =
+                meter.measure(Instant::now(), self.frame, self.instructions, dropped)
+            {
+                system_handle.show_speed(speed);
             }
-            // Note: The sound timer on a COSMAC VIP would not respond to a value of 1.
-            // I.e., if the timer had been set to 1 and now decremented to 0, don't trigger the sound on.
-            if self.sound_timer > 0 {
-                if !self.sound_playing {
-                    system_handle.set_sound_state(true);
-                    self.sound_playing = true;
+        }
+        if self.is_recording() {
+            system_handle.recording_stopped(self.stop_recording());
+        }
+    }
+
+    /// True if the emulator is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// True if turbo is held or switched on, and the emulator isn't paused.
+    pub fn is_turbo(&self) -> bool {
+        (self.turbo_held || self.turbo_on) && !self.paused
+    }
+
+    /// True if slow motion is switched on.
+    pub fn is_slow_motion(&self) -> bool {
+        self.slow_motion
+    }
+
+    /// Runs one display frame connected to a system: handles the queued up key events, ticks the timers,
+    /// executes the CPU cycles of the frame and updates the system with the result.
+    /// While the rewind key is held, the frame instead steps back to the previous frame.
+    /// While paused, the frame only handles the key events, unless a frame advance was requested.
+    /// The stop function is called before each instruction, and the frame ends early if it returns true.
+    pub fn run_system_frame<T: System, F: FnMut(&Emulator) -> bool>(
+        &mut self,
+        system_handle: &mut T,
+        stop: F,
+    ) -> FrameResult {
+        // Empty the key events
+        while let Some(k) = self.next_key_event(system_handle) {
+            if let Some(recorder) = &mut self.recorder
+                && !k.is_speed_control()
+            {
+                recorder.record(self.frame, k);
+            }
+            match k {
+                KeyEvent::Quit => {
+                    return FrameResult::Quit;
+                }
+                KeyEvent::Restart => {
+                    if self.sound_playing {
+                        system_handle.set_sound_state(false);
+                    }
+                    self.reset();
+                    break; // Save remaining events until next frame
+                }
+                KeyEvent::SaveState => {
+                    let state = self.save_state();
+                    match &mut self.movie_slot {
+                        Some(slot) => *slot = Some(state),
+                        None => system_handle.store_state(state),
+                    }
+                }
+                KeyEvent::LoadState => {
+                    let state = match &self.movie_slot {
+                        Some(slot) => slot.clone(),
+                        None => system_handle.fetch_state(),
+                    };
+                    if let Some(state) = state {
+                        self.load_state(&state);
+                    }
+                }
+                KeyEvent::Rewind(pressed) => {
+                    self.rewinding = pressed;
+                }
+                KeyEvent::Screenshot => {
+                    system_handle.save_screenshot(&self.framebuffer);
+                }
+                KeyEvent::Record => {
+                    if self.is_recording() {
+                        system_handle.recording_stopped(self.stop_recording());
+                    } else if let Some(writer) = system_handle.open_recording()
+                        && let Err(e) = self.start_recording(writer)
+                    {
+                        system_handle.recording_stopped(Err(e));
+                    }
+                }
+                KeyEvent::Pause => {
+                    self.paused = !self.paused;
+                }
+                KeyEvent::FrameAdvance => {
+                    self.paused = true;
+                    self.frame_advance = true;
+                    break; // Save remaining events until after the frame
+                }
+                KeyEvent::Turbo(pressed) => {
+                    self.turbo_held = pressed;
+                }
+                KeyEvent::ToggleTurbo => {
+                    self.turbo_on = !self.turbo_on;
                 }
-            } else if self.sound_playing {
+                KeyEvent::SlowMotion => {
+                    self.slow_motion = !self.slow_motion;
+                }
+                KeyEvent::KeyDown(k) => {
+                    self.set_key(k, true);
+                }
+                KeyEvent::KeyUp(k) => {
+                    self.set_key(k, false);
+                }
+            }
+        }
+
+        if self.paused && !std::mem::take(&mut self.frame_advance) {
+            if self.sound_playing {
                 system_handle.set_sound_state(false);
                 self.sound_playing = false;
             }
+            return FrameResult::Completed;
+        }
 
-            let mut wait_for_display_interrupt = false;
-            for cpu_cycle in 0..self.options.timing.cpu_cycles_per_display_tick {
-                // Empty the key events
-                while let Some(k) = system_handle.get_key_event() {
-                    match k {
-                        KeyEvent::Quit => {
-                            break 'running;
-                        }
-                        KeyEvent::Restart => {
-                            if self.sound_playing {
-                                system_handle.set_sound_state(false);
-                            }
-                            self.reset();
-                            break; // Save remaining events until next cycle
-                        }
-                        KeyEvent::KeyDown(k) => {
-                            self.keypad[k as usize] = true;
-                        }
-                        KeyEvent::KeyUp(k) => {
-                            self.keypad[k as usize] = false;
-                        }
-                    }
-                }
+        if self.rewinding {
+            if self.sound_playing {
+                system_handle.set_sound_state(false);
+                self.sound_playing = false;
+            }
+            self.rewind();
+            self.update_system(system_handle);
+            self.frame += 1;
+            return FrameResult::Completed;
+        }
+        self.record_rewind_frame();
 
-                // Process CPU op codes
-                let opcode = OpCode::from_bytes(&self.memory[self.pc as usize..]);
-                self.pc += 2;
-                if let Some(op) = opcode {
-                    match op {
-                        OpCode::Display { .. } => {
-                            if cpu_cycle > 0 && wait_for_display_interrupt {
-                                self.pc -= 2;
-                                break;
-                            }
-                        }
-                        _ => {
-                            wait_for_display_interrupt = true;
-                        }
+        self.tick_timers();
+        // Note: The sound timer on a COSMAC VIP would not respond to a value of 1.
+        // I.e., if the timer had been set to 1 and now decremented to 0, don't trigger the sound on.
+        if self.sound_timer > 0 {
+            if !self.sound_playing {
+                system_handle.set_sound_state(true);
+                self.sound_playing = true;
+            }
+        } else if self.sound_playing {
+            system_handle.set_sound_state(false);
+            self.sound_playing = false;
+        }
+
+        let result = self.run_cycles(stop);
+        self.update_system(system_handle);
+        self.frame += 1;
+        result
+    }
+
+    /// Returns the next key event of the frame, from the movie while one is played back.
+    /// Only Quit and the speed controls are taken from the system during the playback, to keep the
+    /// replay exact.
+    fn next_key_event<T: System>(&mut self, system_handle: &mut T) -> Option<KeyEvent> {
+        let Some(playback) = &mut self.playback else {
+            return system_handle.get_key_event();
+        };
+        while let Some(k) = system_handle.get_key_event() {
+            if k == KeyEvent::Quit || k.is_speed_control() {
+                return Some(k);
+            }
+        }
+        let event = playback.next_event(self.frame);
+        if playback.is_finished() {
+            self.playback = None;
+        }
+        event
+    }
+
+    /// Pushes the screen and the audio pattern to the system if they were updated, and the warnings.
+    /// In turbo, the screen is only pushed every `turbo_frame_skip` frames.
+    fn update_system<T: System>(&mut self, system_handle: &mut T) {
+        for warning in self.take_warnings() {
+            system_handle.show_warning(&warning);
+        }
+        let skip = self.options.timing.turbo_frame_skip.max(1) as u64;
+        let shown = !self.is_turbo() || (self.frame + 1).is_multiple_of(skip);
+        if self.display_updated && shown {
+            system_handle.update_screen(&self.framebuffer);
+            if let Some(gif_recorder) = &mut self.gif_recorder {
+                // The screen is shown from the end of the current frame
+                gif_recorder.add_frame(&self.framebuffer, self.frame + 1);
+            }
+            self.display_updated = false;
+        }
+        if self.audio_updated {
+            system_handle.set_audio_pattern(self.audio_pattern.as_ref(), self.pitch);
+            self.audio_updated = false;
+        }
+    }
+
+    /// Runs one 60 Hz display frame: ticks the timers and executes the CPU cycles of the frame.
+    pub fn run_frame(&mut self) -> FrameResult {
+        self.run_frame_until(|_| false)
+    }
+
+    /// Same as run_frame, but the stop function is called before each instruction,
+    /// and the frame ends early if it returns true.
+    pub fn run_frame_until<F: FnMut(&Emulator) -> bool>(&mut self, stop: F) -> FrameResult {
+        self.tick_timers();
+        let result = self.run_cycles(stop);
+        for warning in self.take_warnings() {
+            eprintln!("Warning: {warning}");
+        }
+        result
+    }
+
+    /// Decrements the delay and sound timers, which should be done at 60 Hz.
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    /// Executes the CPU cycles of one frame, until done, stopped or halted.
+    fn run_cycles<F: FnMut(&Emulator) -> bool>(&mut self, mut stop: F) -> FrameResult {
+        let mut wait_for_display_interrupt = false;
+        for cpu_cycle in 0..self.options.timing.cpu_cycles_per_display_tick {
+            if self.halted {
+                return FrameResult::Halted;
+            }
+            if stop(self) {
+                return FrameResult::Stopped;
+            }
+            match self.next_opcode() {
+                Some(OpCode::Display { .. }) => {
+                    if self.options.opcode.display_wait
+                        && cpu_cycle > 0
+                        && wait_for_display_interrupt
+                    {
+                        break;
                     }
-                    self.execute_opcode(op);
-                } else {
-                    eprintln!("Warning: Failed to decode op code");
                 }
+                _ => {
+                    wait_for_display_interrupt = true;
+                }
+            }
+            // Process CPU op codes
+            if self.step().is_none() {
+                self.warn("Failed to decode op code");
+            }
+        }
+        if self.halted {
+            FrameResult::Halted
+        } else {
+            FrameResult::Completed
+        }
+    }
+
+    /// Decodes the op code at PC without executing it.
+    pub fn next_opcode(&self) -> Option<OpCode> {
+        OpCode::from_bytes(self.memory.get(self.pc as usize..).unwrap_or_default())
+    }
+
+    /// Executes the instruction at PC. Returns the executed op code, or None if it couldn't be decoded,
+    /// in which case the instruction is skipped. Does nothing if the program has been halted.
+    pub fn step(&mut self) -> Option<OpCode> {
+        if self.halted {
+            return None;
+        }
+        if let Some(mut tracer) = self.tracer.take() {
+            tracer.trace(self);
+            self.tracer = Some(tracer);
+        }
+        self.instructions += 1;
+        let opcode = self.next_opcode();
+        match opcode {
+            Some(op) => {
+                self.pc = self.pc.wrapping_add(op.size());
+                self.execute_opcode(op);
             }
-            if self.display_updated {
-                system_handle.update_screen(&self.display_output);
-                self.display_updated = false;
+            None => {
+                self.pc = self.pc.wrapping_add(2);
             }
+        }
+        opcode
+    }
+
+    /// Sets the tracer logging each executed instruction, or None to stop tracing.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    /// Sets the recorder writing the key events to a movie, or None to stop recording.
+    /// While recording, the save states are kept in an empty slot in memory, see `set_playback`.
+    pub fn set_recorder(&mut self, recorder: Option<Recorder>) {
+        if recorder.is_some() {
+            self.movie_slot = Some(None);
+        } else if self.playback.is_none() {
+            self.movie_slot = None;
+        }
+        self.recorder = recorder;
+    }
 
-            // If we want to be really picky about time, maybe consider subtracting the execution time of this loop cycle
-            std::thread::sleep(Duration::new(
-                0,
-                1_000_000_000u32 / self.options.timing.display_frequency,
-            ));
+    /// Sets the movie playback feeding the key events instead of the system, or None to stop
+    /// the playback. The playback stops by itself after the last event.
+    /// From the start of the movie on, the save states are kept in a slot in memory rather than
+    /// passed to the system, so the states saved and loaded are the ones of the movie.
+    pub fn set_playback(&mut self, playback: Option<KeyScript>) {
+        if playback.is_some() {
+            self.movie_slot = Some(None);
+        } else if self.recorder.is_none() {
+            self.movie_slot = None;
         }
+        self.playback = playback;
+    }
+
+    /// Starts recording the screen to an animated GIF written to the writer, from the current
+    /// screen on. A recording in progress is ended first.
+    pub fn start_recording(&mut self, writer: Box<dyn Write>) -> io::Result<()> {
+        self.stop_recording()?;
+        let recorder = GifRecorder::new(writer, &self.framebuffer, &self.options, self.frame)?;
+        self.gif_recorder = Some(recorder);
+        Ok(())
+    }
+
+    /// Ends the recording of the screen, writing out the last frame. Does nothing if not recording.
+    pub fn stop_recording(&mut self) -> io::Result<()> {
+        match self.gif_recorder.take() {
+            Some(recorder) => recorder.finish(self.frame),
+            None => Ok(()),
+        }
+    }
+
+    /// True while the screen is recorded.
+    pub fn is_recording(&self) -> bool {
+        self.gif_recorder.is_some()
+    }
+
+    /// True while a movie is played back.
+    pub fn is_playing_back(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    /// Reseeds the random number generator of the configured algorithm, for reproducible runs.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = random::from_seed(self.options.random.algorithm, seed);
+    }
+
+    /// Replaces the random number generator with another source. The seed no longer applies,
+    /// and save states keep the source as it is when loaded.
+    pub fn set_random_source(&mut self, source: Box<dyn RandomSource>) {
+        self.rng = source;
+    }
+
+    /// The seed the random number generator was last seeded with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Number of frames run since the start.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Number of instructions executed since the start.
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Sets the pressed state of a key (0x0-0xF) on the keypad.
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        self.keypad[key as usize & 0xF] = pressed;
+    }
+
+    /// The pressed state of the keys on the keypad.
+    pub fn keypad(&self) -> &[bool; 16] {
+        &self.keypad
+    }
+
+    /// Current value of the program counter.
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    /// Current value of the I-register.
+    pub fn index(&self) -> u16 {
+        self.reg_i
+    }
+
+    /// The V0-VF registers.
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.reg_vx
+    }
+
+    /// The SUPER-CHIP/XO-CHIP flag registers.
+    pub fn flags(&self) -> &[u8; 16] {
+        &self.flags
+    }
+
+    /// The return addresses on the stack, with the most recent call last.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    /// The whole memory, including fonts and the loaded ROM.
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Current value of the delay timer.
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    /// Current value of the sound timer.
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// The XO-CHIP audio pattern, None until the program loads one.
+    pub fn audio_pattern(&self) -> Option<&[u8; 16]> {
+        self.audio_pattern.as_ref()
+    }
+
+    /// Current value of the XO-CHIP pitch register.
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    /// The current display output.
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    /// True if the program has been halted by the exit op code (00FD).
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// The options the emulator is running with.
+    pub fn options(&self) -> &Chip8options {
+        &self.options
+    }
+
+    /// Sets the program counter.
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    /// Sets the I-register.
+    pub fn set_index(&mut self, val: u16) {
+        self.reg_i = val;
+    }
+
+    /// Sets one of the V0-VF registers.
+    pub fn set_register(&mut self, vx: u8, val: u8) {
+        self.reg_vx[vx as usize & 0xF] = val;
+    }
+
+    /// Sets the depth of the stack, dropping return addresses or pushing zeros.
+    pub fn set_stack_pointer(&mut self, sp: usize) {
+        self.stack.resize(sp, 0);
+    }
+
+    /// Sets the delay timer.
+    pub fn set_delay_timer(&mut self, val: u8) {
+        self.delay_timer = val;
+    }
+
+    /// Sets the sound timer.
+    pub fn set_sound_timer(&mut self, val: u8) {
+        self.sound_timer = val;
+    }
+
+    /// Writes the bytes to memory at the address. Returns false, without writing anything,
+    /// if they don't fit in memory.
+    pub fn write_memory(&mut self, addr: usize, data: &[u8]) -> bool {
+        let Some(end) = addr.checked_add(data.len()) else {
+            return false;
+        };
+        match self.memory.get_mut(addr..end) {
+            Some(m) => {
+                m.copy_from_slice(data);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the memory the op code would access if it was executed now, wrapping around
+    /// the end of memory like the instructions do, or None if it doesn't access memory.
+    pub fn memory_access(&self, opcode: OpCode) -> Option<MemoryAccess> {
+        let start = self.reg_i as usize;
+        let (write, len) = match opcode {
+            OpCode::StoreRange { vx, vy } => (true, vx.abs_diff(vy) as usize + 1),
+            OpCode::LoadRange { vx, vy } => (false, vx.abs_diff(vy) as usize + 1),
+            OpCode::BinaryCodedDecimalConversion { .. } => (true, 3),
+            OpCode::StoreMemory { vx } => (true, vx as usize + 1),
+            OpCode::LoadMemory { vx } => (false, vx as usize + 1),
+            OpCode::LoadAudioPattern => (false, 16),
+            OpCode::Display { val, .. } => {
+                let sprite_len = if val == 0 { 32 } else { val as usize };
+                let planes = (self.selected_planes & framebuffer::ALL_PLANES).count_ones() as usize;
+                (false, sprite_len * planes)
+            }
+            _ => return None,
+        };
+        let mem_size = self.memory.len();
+        let end = start + len.min(mem_size);
+        Some(MemoryAccess {
+            write,
+            range: start..end.min(mem_size),
+            wrapped: 0..end.saturating_sub(mem_size),
+        })
     }
 
     /// Performs the OP code operation on the emulator
     fn execute_opcode(&mut self, opcode: OpCode) {
         match opcode {
             OpCode::ClearScreen => {
-                for v in self.display_output.iter_mut() {
-                    *v = false;
-                }
+                self.framebuffer.clear(self.selected_planes);
                 self.display_updated = true;
             }
             OpCode::Jump(v) => {
@@ -224,9 +1024,30 @@ impl Emulator {
                 if let Some(r) = self.stack.pop() {
                     self.pc = r;
                 } else {
-                    eprintln!("Warning: Return called with empty stack.")
+                    self.warn("Return called with empty stack.")
                 }
             }
+            OpCode::ScrollDown(n) => {
+                self.scroll(0, n as isize);
+            }
+            OpCode::ScrollUp(n) => {
+                self.scroll(0, -(n as isize));
+            }
+            OpCode::ScrollRight => {
+                self.scroll(4, 0);
+            }
+            OpCode::ScrollLeft => {
+                self.scroll(-4, 0);
+            }
+            OpCode::Exit => {
+                self.halted = true;
+            }
+            OpCode::LowRes => {
+                self.set_resolution(false);
+            }
+            OpCode::HighRes => {
+                self.set_resolution(true);
+            }
             OpCode::Set { vx, val } => {
                 self.reg_vx[vx as usize] = val;
             }
@@ -235,39 +1056,59 @@ impl Emulator {
             }
             OpCode::SkipIfVxEq { vx, val } => {
                 if self.reg_vx[vx as usize] == val {
-                    self.pc += 2;
+                    self.skip_instruction();
                 }
             }
             OpCode::SkipIfVxNeq { vx, val } => {
                 if self.reg_vx[vx as usize] != val {
-                    self.pc += 2;
+                    self.skip_instruction();
                 }
             }
             OpCode::SkipIfVxEqVy { vx, vy } => {
                 if self.reg_vx[vx as usize] == self.reg_vx[vy as usize] {
-                    self.pc += 2;
+                    self.skip_instruction();
                 }
             }
             OpCode::SkipIfVxNeqVy { vx, vy } => {
                 if self.reg_vx[vx as usize] != self.reg_vx[vy as usize] {
-                    self.pc += 2;
+                    self.skip_instruction();
+                }
+            }
+            OpCode::StoreRange { vx, vy } => {
+                // The range may be given in either direction, VX is always stored at I
+                for (n, x) in register_range(vx, vy).into_iter().enumerate() {
+                    let addr = self.memory_index(n);
+                    self.memory[addr] = self.reg_vx[x as usize];
+                }
+            }
+            OpCode::LoadRange { vx, vy } => {
+                for (n, x) in register_range(vx, vy).into_iter().enumerate() {
+                    self.reg_vx[x as usize] = self.memory[self.memory_index(n)];
                 }
             }
             OpCode::SetVxToVy { vx, vy } => {
                 self.reg_vx[vx as usize] = self.reg_vx[vy as usize];
-                self.reg_vx[0xF] = 0;
+                if self.options.opcode.logic_reset_vf {
+                    self.reg_vx[0xF] = 0;
+                }
             }
             OpCode::BinaryOr { vx, vy } => {
                 self.reg_vx[vx as usize] |= self.reg_vx[vy as usize];
-                self.reg_vx[0xF] = 0;
+                if self.options.opcode.logic_reset_vf {
+                    self.reg_vx[0xF] = 0;
+                }
             }
             OpCode::BinaryAnd { vx, vy } => {
                 self.reg_vx[vx as usize] &= self.reg_vx[vy as usize];
-                self.reg_vx[0xF] = 0;
+                if self.options.opcode.logic_reset_vf {
+                    self.reg_vx[0xF] = 0;
+                }
             }
             OpCode::LogicalXor { vx, vy } => {
                 self.reg_vx[vx as usize] ^= self.reg_vx[vy as usize];
-                self.reg_vx[0xF] = 0;
+                if self.options.opcode.logic_reset_vf {
+                    self.reg_vx[0xF] = 0;
+                }
             }
             OpCode::AddVyToVx { vx, vy } => {
                 let old_vx = self.reg_vx[vx as usize];
@@ -309,7 +1150,7 @@ impl Emulator {
                     self.reg_vx[0xF] = old_vx & 0x1;
                 }
             }
-            OpCode::SetIndex(val) => {
+            OpCode::SetIndex(val) | OpCode::SetIndexLong(val) => {
                 self.reg_i = val;
             }
             OpCode::JumpWithOffset { vx, val } => {
@@ -320,38 +1161,79 @@ impl Emulator {
                 };
             }
             OpCode::Random { vx, val } => {
-                let rnd: u8 = self.rng.random();
+                let rnd = self.rng.next_byte();
                 self.reg_vx[vx as usize] = rnd & val;
             }
             OpCode::Display { vx, vy, val } => {
-                let x_start = self.reg_vx[vx as usize] % self.display_width;
-                let y_start = self.reg_vx[vy as usize] % self.display_height;
-                let x_stop = (x_start + 8).min(self.display_width);
-                let y_stop = (y_start + val).min(self.display_height);
+                // A height of 0 draws a 16x16 sprite (SUPER-CHIP), stored as two bytes per row.
+                let (sprite_width, sprite_height) = if val == 0 {
+                    (16, 16)
+                } else {
+                    (8, val as usize)
+                };
+                let bytes_per_row = sprite_width / 8;
+                let (width, height) = (self.framebuffer.width(), self.framebuffer.height());
+                let x_start = self.reg_vx[vx as usize] as usize % width;
+                let y_start = self.reg_vx[vy as usize] as usize % height;
+                // Sprites either clip at the edges of the screen, or wrap around
+                let (x_stop, y_stop) = if self.options.opcode.sprite_clipping {
+                    (
+                        (x_start + sprite_width).min(width),
+                        (y_start + sprite_height).min(height),
+                    )
+                } else {
+                    (x_start + sprite_width, y_start + sprite_height)
+                };
                 self.reg_vx[0xF] = 0;
-                for (n, y) in (y_start..y_stop).enumerate() {
-                    let sprite = self.memory[self.reg_i as usize + n];
-                    for (i, x) in (x_start..x_stop).enumerate() {
-                        let idx = x as usize + ((y as usize) * self.display_width as usize);
-                        let old_pixel = self.display_output[idx];
-                        let new_pixel = (sprite & (0x80 >> i)) > 0;
-                        self.display_output[idx] = old_pixel ^ new_pixel;
-                        if old_pixel && new_pixel {
-                            self.reg_vx[0xF] = 1;
+                // With several planes selected (XO-CHIP), the sprite data for each plane follows
+                // directly after the data for the previous one.
+                let mut sprite_addr = self.reg_i as usize;
+                for plane in [framebuffer::PLANE_1, framebuffer::PLANE_2] {
+                    if self.selected_planes & plane == 0 {
+                        continue;
+                    }
+                    for (n, y) in (y_start..y_stop).enumerate() {
+                        let row_addr = sprite_addr + n * bytes_per_row;
+                        let sprite = (0..bytes_per_row).fold(0u16, |acc, b| {
+                            (acc << 8) | self.memory[(row_addr + b) % self.memory.len()] as u16
+                        });
+                        let msb = 1u16 << (sprite_width - 1);
+                        for (i, x) in (x_start..x_stop).enumerate() {
+                            if (sprite & (msb >> i)) > 0
+                                && self.framebuffer.toggle(x % width, y % height, plane)
+                            {
+                                self.reg_vx[0xF] = 1;
+                            }
                         }
                     }
+                    sprite_addr += sprite_height * bytes_per_row;
                 }
                 self.display_updated = true;
             }
             OpCode::SkipIfKeyPressed { vx } => {
                 if self.keypad[self.reg_vx[vx as usize & 0xF] as usize] {
-                    self.pc += 2;
+                    self.skip_instruction();
                 }
             }
             OpCode::SkipIfKeyNotPressed { vx } => {
                 if !self.keypad[self.reg_vx[vx as usize & 0xF] as usize] {
-                    self.pc += 2;
+                    self.skip_instruction();
+                }
+            }
+            OpCode::SelectPlanes(planes) => {
+                self.selected_planes = planes & framebuffer::ALL_PLANES;
+            }
+            OpCode::LoadAudioPattern => {
+                let mut pattern = [0; 16];
+                for (n, p) in pattern.iter_mut().enumerate() {
+                    *p = self.memory[self.memory_index(n)];
                 }
+                self.audio_pattern = Some(pattern);
+                self.audio_updated = true;
+            }
+            OpCode::SetPitchToVx { vx } => {
+                self.pitch = self.reg_vx[vx as usize];
+                self.audio_updated = true;
             }
             OpCode::SetVxToDelayTimer { vx } => {
                 self.reg_vx[vx as usize] = self.delay_timer;
@@ -363,11 +1245,15 @@ impl Emulator {
                 self.sound_timer = self.reg_vx[vx as usize];
             }
             OpCode::AddToIndex { vx } => {
-                self.reg_i += self.reg_vx[vx as usize] as u16;
+                let index = self.reg_i as u32 + self.reg_vx[vx as usize] as u32;
                 // Overflow handling
-                if self.reg_i >= self.options.memory.mem_size {
-                    self.reg_i %= self.options.memory.mem_size;
-                    self.reg_vx[0xF] = 1;
+                if index >= self.options.memory.mem_size {
+                    self.reg_i = (index % self.options.memory.mem_size) as u16;
+                    if self.options.opcode.add_index_overflow_vf {
+                        self.reg_vx[0xF] = 1;
+                    }
+                } else {
+                    self.reg_i = index as u16;
                 }
             }
             OpCode::GetKey { vx } => {
@@ -389,31 +1275,56 @@ impl Emulator {
                 self.reg_i =
                     self.options.memory.font_start + (5 * (self.reg_vx[vx as usize] & 0xF) as u16);
             }
+            OpCode::BigFontCharacter { vx } => {
+                self.reg_i = self.options.memory.big_font_start
+                    + (10 * (self.reg_vx[vx as usize] & 0xF) as u16);
+            }
             OpCode::BinaryCodedDecimalConversion { vx } => {
-                self.memory[self.reg_i as usize] = self.reg_vx[vx as usize] / 100;
-                self.memory[self.reg_i as usize + 1] = (self.reg_vx[vx as usize] % 100) / 10;
-                self.memory[self.reg_i as usize + 2] = self.reg_vx[vx as usize] % 10;
+                let value = self.reg_vx[vx as usize];
+                for (n, digit) in [value / 100, (value % 100) / 10, value % 10]
+                    .into_iter()
+                    .enumerate()
+                {
+                    let addr = self.memory_index(n);
+                    self.memory[addr] = digit;
+                }
             }
             OpCode::StoreMemory { vx } => {
                 for x in 0..vx + 1 {
-                    self.memory[self.reg_i as usize + x as usize] = self.reg_vx[x as usize];
+                    let addr = self.memory_index(x as usize);
+                    self.memory[addr] = self.reg_vx[x as usize];
                 }
                 if self.options.opcode.store_load_mem_use_i {
-                    self.reg_i += vx as u16 + 1;
+                    self.reg_i = self.reg_i.wrapping_add(vx as u16 + 1);
                 }
             }
             OpCode::LoadMemory { vx } => {
                 for x in 0..vx + 1 {
-                    self.reg_vx[x as usize] = self.memory[self.reg_i as usize + x as usize];
+                    self.reg_vx[x as usize] = self.memory[self.memory_index(x as usize)];
                 }
                 if self.options.opcode.store_load_mem_use_i {
-                    self.reg_i += vx as u16 + 1;
+                    self.reg_i = self.reg_i.wrapping_add(vx as u16 + 1);
                 }
             }
+            OpCode::StoreFlags { vx } => {
+                self.flags[..=vx as usize].copy_from_slice(&self.reg_vx[..=vx as usize]);
+            }
+            OpCode::LoadFlags { vx } => {
+                self.reg_vx[..=vx as usize].copy_from_slice(&self.flags[..=vx as usize]);
+            }
         }
     }
 }
 
+/// Returns the register numbers from vx to vy, counting downwards if vy is less than vx.
+fn register_range(vx: u8, vy: u8) -> Vec<u8> {
+    if vx <= vy {
+        (vx..=vy).collect()
+    } else {
+        (vy..=vx).rev().collect()
+    }
+}
+
 #[path = "unittest/test_emulator.rs"]
 #[cfg(test)]
 mod test_emulator;
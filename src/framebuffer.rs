@@ -0,0 +1,98 @@
+//! # Framebuffer
+//!
+//! Contains the framebuffer holding the pixels of the CHIP-8 screen.
+//! Each pixel is stored as a bit mask of the XO-CHIP bitplanes it is set in,
+//! i.e. bit 0 for the first plane and bit 1 for the second plane. A plain CHIP-8 or
+//! SUPER-CHIP program only ever draws to the first plane.
+use serde::{Deserialize, Serialize};
+
+/// Bit mask for the first bitplane
+pub const PLANE_1: u8 = 0b01;
+/// Bit mask for the second bitplane (XO-CHIP)
+pub const PLANE_2: u8 = 0b10;
+/// Bit mask covering all bitplanes
+pub const ALL_PLANES: u8 = PLANE_1 | PLANE_2;
+
+/// Holds the pixels of the screen at its current resolution
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Framebuffer {
+    /// Creates a new, blank Framebuffer with the given resolution
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    /// Width of the framebuffer in pixels
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height of the framebuffer in pixels
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The pixels in row-major order, each one a bit mask of the planes it is set in
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// True if there is a pixel for each position, e.g. after deserializing
+    pub(crate) fn is_consistent(&self) -> bool {
+        self.width.checked_mul(self.height) == Some(self.pixels.len())
+    }
+
+    /// Changes the resolution of the framebuffer, which also clears all pixels
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.pixels.clear();
+        self.pixels.resize(width * height, 0);
+    }
+
+    /// Clears the given planes in all pixels
+    pub fn clear(&mut self, planes: u8) {
+        for p in self.pixels.iter_mut() {
+            *p &= !planes;
+        }
+    }
+
+    /// Toggles the given plane in the pixel at (x, y).
+    /// Returns true if the pixel was set in that plane before, i.e. if it was erased.
+    pub fn toggle(&mut self, x: usize, y: usize, plane: u8) -> bool {
+        let idx = x + y * self.width;
+        let was_set = self.pixels[idx] & plane > 0;
+        self.pixels[idx] ^= plane;
+        was_set
+    }
+
+    /// Scrolls the given planes, moving every pixel by dx columns and dy rows.
+    /// Pixels scrolled in from outside the screen are cleared.
+    pub fn scroll(&mut self, dx: isize, dy: isize, planes: u8) {
+        let width = self.width as isize;
+        let height = self.height as isize;
+        let old_pixels = self.pixels.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let (src_x, src_y) = (x - dx, y - dy);
+                let inside = (0..width).contains(&src_x) && (0..height).contains(&src_y);
+                let scrolled = if inside {
+                    old_pixels[(src_x + src_y * width) as usize] & planes
+                } else {
+                    0
+                };
+                let idx = (x + y * width) as usize;
+                self.pixels[idx] = (self.pixels[idx] & !planes) | scrolled;
+            }
+        }
+    }
+}
@@ -0,0 +1,568 @@
+//! # GDB stub
+//!
+//! Lets `gdb`, or any other client of the GDB remote serial protocol, debug the running emulator
+//! over a local TCP connection:
+//!
+//! ```text
+//! chip8 --gdb 1234 game.ch8
+//! gdb -ex "target remote localhost:1234"
+//! ```
+//!
+//! Supported are reading and writing the registers and the memory, software and hardware
+//! breakpoints, write, read and access watchpoints, continue, single-step, interrupt (Ctrl-C),
+//! detach and kill. The breakpoints are checked by a [`Debugger`].
+//!
+//! The CHIP-8 register file is described to the client by a target description XML,
+//! with the registers numbered as follows:
+//!
+//! ```text
+//! 0-15  v0-vf   8 bits
+//! 16    i       16 bits
+//! 17    pc      16 bits
+//! 18    sp      8 bits, the stack depth
+//! 19    dt      8 bits
+//! 20    st      8 bits
+//! ```
+//!
+//! Registers of 16 bits are transferred in little endian byte order.
+
+use crate::debugger::{Access, Breakpoint, Debugger, PauseReason};
+use crate::emulator::{Emulator, FrameResult, KeyEvent, System};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+/// Largest packet the stub accepts and sends, as advertised to the client
+const PACKET_SIZE: usize = 0x1000;
+
+/// Names and sizes in bytes of the registers, in register number order
+const REGISTERS: [(&str, usize); 21] = [
+    ("v0", 1),
+    ("v1", 1),
+    ("v2", 1),
+    ("v3", 1),
+    ("v4", 1),
+    ("v5", 1),
+    ("v6", 1),
+    ("v7", 1),
+    ("v8", 1),
+    ("v9", 1),
+    ("va", 1),
+    ("vb", 1),
+    ("vc", 1),
+    ("vd", 1),
+    ("ve", 1),
+    ("vf", 1),
+    ("i", 2),
+    ("pc", 2),
+    ("sp", 1),
+    ("dt", 1),
+    ("st", 1),
+];
+
+/// How often the connection is polled while the program is stopped
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Data received from the client
+#[derive(Debug, PartialEq)]
+enum Incoming {
+    Packet(String),
+    /// Ctrl-C
+    Interrupt,
+    Disconnected,
+}
+
+/// What to do after handling a packet
+#[derive(Debug, PartialEq)]
+enum Action {
+    Reply(String),
+    Continue,
+    Step,
+    Detach,
+    Kill,
+}
+
+/// Waits for a client to connect on the local port.
+pub fn accept(port: u16) -> io::Result<TcpStream> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("Waiting for GDB to connect on port {port}");
+    let (stream, addr) = listener.accept()?;
+    println!("GDB connected from {addr}");
+    Ok(stream)
+}
+
+pub struct GdbStub {
+    stream: TcpStream,
+    /// Received data not yet handled
+    buffer: Vec<u8>,
+    /// Set when the client turned off the acknowledgments
+    no_ack: bool,
+    debugger: Debugger,
+    /// Debugger breakpoint numbers of each breakpoint or watchpoint set by the client,
+    /// by type, address and length
+    points: HashMap<(u8, u16, u16), Vec<u32>>,
+    /// Reply to the ? packet, the reason the program was last stopped
+    last_stop: String,
+}
+
+impl GdbStub {
+    /// Creates a stub talking to the client over the connected stream.
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            buffer: Vec::new(),
+            no_ack: false,
+            debugger: Debugger::new(),
+            points: HashMap::new(),
+            last_stop: "S05".to_string(),
+        })
+    }
+
+    /// Runs the emulator connected to the system, starting out stopped, and serves the client
+    /// until it kills the program or disconnects. If it detaches, the program keeps running as usual.
+    pub fn run<T: System>(
+        &mut self,
+        emulator: &mut Emulator,
+        system_handle: &mut T,
+    ) -> io::Result<()> {
+        let frame_time = Duration::new(
+            0,
+            1_000_000_000u32 / emulator.options().timing.display_frequency,
+        );
+        let mut running = false;
+        let mut resumed = false;
+        loop {
+            if !running {
+                // Keep handling the keys while stopped, so that they can be held while stepping
+                while let Some(k) = system_handle.get_key_event() {
+                    match k {
+                        KeyEvent::Quit => return Ok(()),
+                        KeyEvent::KeyDown(k) => emulator.set_key(k, true),
+                        KeyEvent::KeyUp(k) => emulator.set_key(k, false),
+                        _ => (),
+                    }
+                }
+                let packet = match self.receive()? {
+                    Some(Incoming::Packet(packet)) => packet,
+                    Some(Incoming::Disconnected) => return Ok(()),
+                    Some(Incoming::Interrupt) => continue,
+                    None => {
+                        std::thread::sleep(POLL_INTERVAL);
+                        continue;
+                    }
+                };
+                match self.handle_packet(&packet, emulator) {
+                    Action::Reply(reply) => self.send(&reply)?,
+                    Action::Continue => {
+                        running = true;
+                        resumed = true;
+                    }
+                    Action::Step => {
+                        emulator.step();
+                        system_handle.update_screen(emulator.framebuffer());
+                        let reason = if emulator.is_halted() {
+                            PauseReason::Halted
+                        } else {
+                            PauseReason::Stepped
+                        };
+                        self.stop(reason)?;
+                    }
+                    Action::Detach => {
+                        self.send("OK")?;
+                        emulator.run(system_handle);
+                        return Ok(());
+                    }
+                    Action::Kill => return Ok(()),
+                }
+                continue;
+            }
+
+            match self.receive()? {
+                Some(Incoming::Interrupt) => {
+                    running = false;
+                    self.send_stop("S02".to_string())?;
+                    continue;
+                }
+                Some(Incoming::Disconnected) => return Ok(()),
+                // Packets other than the interrupt aren't expected while running
+                Some(Incoming::Packet(_)) | None => (),
+            }
+            let mut reason = None;
+            let debugger = &mut self.debugger;
+            let result = emulator.run_system_frame(system_handle, |e| {
+                let check = debugger.check(e);
+                // Don't stop again on the instruction the program was stopped at
+                if std::mem::take(&mut resumed) {
+                    return false;
+                }
+                reason = check;
+                reason.is_some()
+            });
+            match result {
+                FrameResult::Completed => std::thread::sleep(frame_time),
+                FrameResult::Stopped => {
+                    running = false;
+                    self.stop(reason.unwrap_or(PauseReason::Stepped))?;
+                }
+                FrameResult::Halted => {
+                    running = false;
+                    self.stop(PauseReason::Halted)?;
+                }
+                FrameResult::Quit => return Ok(()),
+            }
+        }
+    }
+
+    /// Handles a packet while the program is stopped.
+    fn handle_packet(&mut self, packet: &str, emulator: &mut Emulator) -> Action {
+        let (command, args) = packet.split_at(packet.len().min(1));
+        let reply = match command {
+            "?" => self.last_stop.clone(),
+            "g" => (0..REGISTERS.len())
+                .map(|n| to_hex(&read_register(emulator, n)))
+                .collect(),
+            "G" => match from_hex(args) {
+                Some(bytes) if bytes.len() == register_file_size() => {
+                    let mut offset = 0;
+                    for (n, (_, size)) in REGISTERS.iter().enumerate() {
+                        write_register(emulator, n, &bytes[offset..offset + size]);
+                        offset += size;
+                    }
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(n) if n < REGISTERS.len() => to_hex(&read_register(emulator, n)),
+                _ => "E01".to_string(),
+            },
+            "P" => {
+                let register = args.split_once('=').and_then(|(n, value)| {
+                    let n = usize::from_str_radix(n, 16).ok()?;
+                    let bytes = from_hex(value)?;
+                    (n < REGISTERS.len() && bytes.len() == REGISTERS[n].1).then_some((n, bytes))
+                });
+                match register {
+                    Some((n, bytes)) => {
+                        write_register(emulator, n, &bytes);
+                        "OK".to_string()
+                    }
+                    None => "E01".to_string(),
+                }
+            }
+            "m" => {
+                let memory = emulator.memory();
+                match parse_addr_len(args) {
+                    Some((addr, len)) if addr < memory.len() => {
+                        // Two hex digits per byte
+                        let end = addr + len.min(PACKET_SIZE / 2);
+                        to_hex(&memory[addr..end.min(memory.len())])
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            "M" => {
+                let write = args.split_once(':').and_then(|(addr_len, data)| {
+                    let (addr, len) = parse_addr_len(addr_len)?;
+                    let bytes = from_hex(data)?;
+                    (bytes.len() == len).then_some((addr, bytes))
+                });
+                match write {
+                    Some((addr, bytes)) if emulator.write_memory(addr, &bytes) => "OK".to_string(),
+                    _ => "E01".to_string(),
+                }
+            }
+            "c" | "s" => {
+                if let Ok(addr) = u16::from_str_radix(args, 16) {
+                    emulator.set_pc(addr);
+                }
+                return if command == "c" {
+                    Action::Continue
+                } else {
+                    Action::Step
+                };
+            }
+            "Z" | "z" => self.set_point(command == "Z", args, emulator),
+            "D" => return Action::Detach,
+            "k" => return Action::Kill,
+            "H" | "T" => "OK".to_string(),
+            "Q" if args == "StartNoAckMode" => {
+                // The packet itself is still acknowledged
+                self.no_ack = true;
+                "OK".to_string()
+            }
+            "q" => self.query(args),
+            _ => String::new(),
+        };
+        Action::Reply(reply)
+    }
+
+    /// Answers a general query packet.
+    fn query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            return format!(
+                "PacketSize={PACKET_SIZE:x};qXfer:features:read+;swbreak+;hwbreak+;QStartNoAckMode+"
+            );
+        }
+        if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let Some((offset, len)) = parse_addr_len(range) else {
+                return "E01".to_string();
+            };
+            let xml = target_xml();
+            let start = offset.min(xml.len());
+            // One byte for the 'm' or 'l' prefix
+            let end = (start + len.min(PACKET_SIZE - 1)).min(xml.len());
+            let more = if end < xml.len() { 'm' } else { 'l' };
+            return format!("{more}{}", &xml[start..end]);
+        }
+        match query {
+            "Attached" => "1".to_string(),
+            "C" => "QC1".to_string(),
+            "fThreadInfo" => "m1".to_string(),
+            "sThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    /// Inserts or removes a breakpoint or watchpoint, given as "type,addr,kind".
+    fn set_point(&mut self, insert: bool, args: &str, emulator: &Emulator) -> String {
+        let mut parts = args.split(',');
+        let parsed = (|| {
+            let kind = parts.next()?.parse::<u8>().ok()?;
+            let addr = u16::from_str_radix(parts.next()?, 16).ok()?;
+            let len = u16::from_str_radix(parts.next()?, 16).ok()?;
+            Some((kind, addr, len))
+        })();
+        let Some((kind, addr, len)) = parsed else {
+            return "E01".to_string();
+        };
+        let access = match kind {
+            0 | 1 => None,
+            2 => Some(Access::Write),
+            3 => Some(Access::Read),
+            4 => Some(Access::ReadWrite),
+            _ => return String::new(),
+        };
+        let key = (kind, addr, len);
+        if !insert {
+            for number in self.points.remove(&key).unwrap_or_default() {
+                self.debugger.delete_breakpoint(number);
+            }
+            return "OK".to_string();
+        }
+        if self.points.contains_key(&key) {
+            return "OK".to_string();
+        }
+        let breakpoints: Vec<Breakpoint> = match access {
+            None => vec![Breakpoint::Pc(addr)],
+            Some(access) => (0..len.max(1))
+                .map(|offset| Breakpoint::Watch {
+                    addr: addr.wrapping_add(offset),
+                    access,
+                })
+                .collect(),
+        };
+        let numbers = breakpoints
+            .into_iter()
+            .map(|b| self.debugger.add_breakpoint(b, emulator))
+            .collect();
+        self.points.insert(key, numbers);
+        "OK".to_string()
+    }
+
+    /// Reports to the client why the program stopped.
+    fn stop(&mut self, reason: PauseReason) -> io::Result<()> {
+        let reply = match reason {
+            PauseReason::Breakpoint(number) => {
+                let breakpoint = self
+                    .debugger
+                    .breakpoints()
+                    .iter()
+                    .find(|(n, _)| *n == number);
+                match breakpoint {
+                    Some((_, Breakpoint::Watch { addr, access })) => {
+                        let kind = match access {
+                            Access::Write => "watch",
+                            Access::Read => "rwatch",
+                            Access::ReadWrite => "awatch",
+                        };
+                        format!("T05{kind}:{addr:x};")
+                    }
+                    _ => "T05swbreak:;".to_string(),
+                }
+            }
+            PauseReason::InvalidOpCode => "S04".to_string(),
+            PauseReason::Halted => "W00".to_string(),
+            PauseReason::Started | PauseReason::Stepped => "S05".to_string(),
+        };
+        self.send_stop(reply)
+    }
+
+    fn send_stop(&mut self, reply: String) -> io::Result<()> {
+        self.send(&reply)?;
+        self.last_stop = reply;
+        Ok(())
+    }
+
+    /// Sends a packet with the data.
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${data}#{:02x}", checksum(data.as_bytes()));
+        self.write_all(packet.as_bytes())
+    }
+
+    /// Writes to the non-blocking stream, waiting while it is full.
+    fn write_all(&mut self, mut data: &[u8]) -> io::Result<()> {
+        while !data.is_empty() {
+            match self.stream.write(data) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(n) => data = &data[n..],
+                Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::sleep(POLL_INTERVAL),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the next packet or interrupt received, None if there is nothing complete yet.
+    fn receive(&mut self) -> io::Result<Option<Incoming>> {
+        let mut chunk = [0; 1024];
+        let mut disconnected = false;
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    disconnected = true;
+                    break;
+                }
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        while let Some(&first) = self.buffer.first() {
+            match first {
+                0x03 => {
+                    self.buffer.remove(0);
+                    return Ok(Some(Incoming::Interrupt));
+                }
+                b'$' => {
+                    let Some(end) = self.buffer.iter().position(|&b| b == b'#') else {
+                        break;
+                    };
+                    if self.buffer.len() < end + 3 {
+                        break;
+                    }
+                    let data = self.buffer[1..end].to_vec();
+                    let sum = std::str::from_utf8(&self.buffer[end + 1..end + 3])
+                        .ok()
+                        .and_then(|s| u8::from_str_radix(s, 16).ok());
+                    self.buffer.drain(..end + 3);
+                    if sum != Some(checksum(&data)) {
+                        self.write_all(b"-")?;
+                        continue;
+                    }
+                    if !self.no_ack {
+                        self.write_all(b"+")?;
+                    }
+                    return Ok(Some(Incoming::Packet(
+                        String::from_utf8_lossy(&data).to_string(),
+                    )));
+                }
+                // Acknowledgments, and anything outside of a packet
+                _ => {
+                    self.buffer.remove(0);
+                }
+            }
+        }
+        Ok(disconnected.then_some(Incoming::Disconnected))
+    }
+}
+
+/// Returns the register value as bytes, little endian.
+fn read_register(emulator: &Emulator, n: usize) -> Vec<u8> {
+    match n {
+        0..=15 => vec![emulator.registers()[n]],
+        16 => emulator.index().to_le_bytes().to_vec(),
+        17 => emulator.pc().to_le_bytes().to_vec(),
+        18 => vec![emulator.stack().len() as u8],
+        19 => vec![emulator.delay_timer()],
+        _ => vec![emulator.sound_timer()],
+    }
+}
+
+/// Sets the register from its bytes, little endian.
+fn write_register(emulator: &mut Emulator, n: usize, bytes: &[u8]) {
+    let word = || u16::from_le_bytes([bytes[0], bytes[1]]);
+    match n {
+        0..=15 => emulator.set_register(n as u8, bytes[0]),
+        16 => emulator.set_index(word()),
+        17 => emulator.set_pc(word()),
+        18 => emulator.set_stack_pointer(bytes[0] as usize),
+        19 => emulator.set_delay_timer(bytes[0]),
+        _ => emulator.set_sound_timer(bytes[0]),
+    }
+}
+
+fn register_file_size() -> usize {
+    REGISTERS.iter().map(|(_, size)| size).sum()
+}
+
+/// Returns the target description of the CHIP-8 registers.
+fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n\
+         <feature name=\"org.chip8.core\">\n",
+    );
+    for (n, (name, size)) in REGISTERS.iter().enumerate() {
+        let kind = match *name {
+            "pc" => "code_ptr",
+            "i" => "data_ptr",
+            _ if *size == 2 => "uint16",
+            _ => "uint8",
+        };
+        let _ = writeln!(
+            xml,
+            "<reg name=\"{name}\" bitsize=\"{}\" type=\"{kind}\" regnum=\"{n}\"/>",
+            size * 8
+        );
+    }
+    xml.push_str("</feature>\n</target>\n");
+    xml
+}
+
+/// Parses "addr,len" in hexadecimal. Returns None if the end of the range overflows.
+fn parse_addr_len(s: &str) -> Option<(usize, usize)> {
+    let (addr, len) = s.split_once(',')?;
+    let addr = usize::from_str_radix(addr, 16).ok()?;
+    let len = usize::from_str_radix(len, 16).ok()?;
+    addr.checked_add(len)?;
+    Some((addr, len))
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, b| sum.wrapping_add(*b))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[path = "unittest/test_gdbstub.rs"]
+#[cfg(test)]
+mod test_gdbstub;
@@ -0,0 +1,130 @@
+//! # GIF
+//!
+//! A small encoder for animated GIF images, without any image library, for recordings of the
+//! framebuffer. All frames share a global palette of up to 256 colors, with one byte per pixel
+//! indexing the palette, and the animation loops forever.
+//!
+//! The pixel data of each frame is compressed with the variable length LZW codes of the GIF format,
+//! starting over with a clear code whenever the code table is full.
+
+use crate::png::BitWriter;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Number of codes of the 12-bit LZW code table
+const MAX_CODES: u16 = 4096;
+
+/// Writes an animated GIF frame by frame.
+pub struct Encoder<W: Write> {
+    writer: W,
+    width: u16,
+    height: u16,
+    /// Bits per pixel of the palette, at least 2 as required by the LZW minimum code size
+    depth: u8,
+}
+
+impl<W: Write> Encoder<W> {
+    /// Writes the header, the palette and the loop extension of the animation.
+    pub fn new(
+        mut writer: W,
+        width: u16,
+        height: u16,
+        palette: &[(u8, u8, u8)],
+    ) -> io::Result<Self> {
+        let depth = (palette.len().max(4) - 1).ilog2() as u8 + 1;
+        writer.write_all(b"GIF89a")?;
+        writer.write_all(&width.to_le_bytes())?;
+        writer.write_all(&height.to_le_bytes())?;
+        // Global color table with 8 bits per primary color, no background color or aspect ratio
+        writer.write_all(&[0xF0 | (depth - 1), 0, 0])?;
+        for i in 0..1 << depth {
+            let (r, g, b) = palette.get(i).copied().unwrap_or_default();
+            writer.write_all(&[r, g, b])?;
+        }
+        // Netscape application extension, looping forever
+        writer.write_all(&[0x21, 0xFF, 11])?;
+        writer.write_all(b"NETSCAPE2.0")?;
+        writer.write_all(&[3, 1, 0, 0, 0])?;
+        Ok(Self {
+            writer,
+            width,
+            height,
+            depth,
+        })
+    }
+
+    /// Writes a frame shown for the delay in hundredths of a second. The pixels hold one palette
+    /// index per pixel, row by row, for the full image.
+    pub fn write_frame(&mut self, pixels: &[u8], delay: u16) -> io::Result<()> {
+        // Graphic control extension: the frame is left in place, no transparent color
+        self.writer.write_all(&[0x21, 0xF9, 4, 0x04])?;
+        self.writer.write_all(&delay.to_le_bytes())?;
+        self.writer.write_all(&[0, 0])?;
+        // Image descriptor covering the full image, without a local color table
+        self.writer.write_all(&[0x2C, 0, 0, 0, 0])?;
+        self.writer.write_all(&self.width.to_le_bytes())?;
+        self.writer.write_all(&self.height.to_le_bytes())?;
+        self.writer.write_all(&[0, self.depth])?;
+        // The data is split into sub-blocks of at most 255 bytes, ending with an empty one
+        for block in lzw(pixels, self.depth).chunks(255) {
+            self.writer.write_all(&[block.len() as u8])?;
+            self.writer.write_all(block)?;
+        }
+        self.writer.write_all(&[0])
+    }
+
+    /// Writes the trailer and returns the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.write_all(&[0x3B])?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Compresses the pixels with LZW codes, starting at `min_code_size` + 1 bits.
+pub fn lzw(pixels: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    let mut bits = BitWriter::default();
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next_code = end + 1;
+    let mut code_size = min_code_size + 1;
+    bits.write(clear as u32, code_size);
+
+    let Some((first, rest)) = pixels.split_first() else {
+        bits.write(end as u32, code_size);
+        return bits.finish();
+    };
+    let mut prefix = *first as u16;
+    for pixel in rest {
+        if let Some(code) = table.get(&(prefix, *pixel)) {
+            prefix = *code;
+            continue;
+        }
+        bits.write(prefix as u32, code_size);
+        if next_code < MAX_CODES {
+            // The decoder adds its table entry one code later, and widens the codes as it does
+            if next_code == 1 << code_size {
+                code_size += 1;
+            }
+            table.insert((prefix, *pixel), next_code);
+            next_code += 1;
+        } else {
+            bits.write(clear as u32, code_size);
+            table.clear();
+            next_code = end + 1;
+            code_size = min_code_size + 1;
+        }
+        prefix = *pixel as u16;
+    }
+    bits.write(prefix as u32, code_size);
+    if next_code == 1 << code_size && next_code < MAX_CODES {
+        code_size += 1;
+    }
+    bits.write(end as u32, code_size);
+    bits.finish()
+}
+
+#[path = "unittest/test_gif.rs"]
+#[cfg(test)]
+mod test_gif;
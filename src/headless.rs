@@ -0,0 +1,296 @@
+//! # Headless
+//!
+//! Contains a System implementation without any display, sound or keyboard device, for running
+//! ROMs on machines without a display, e.g. in CI. The emulator runs as fast as possible for a
+//! given number of frames, or until the program counter reaches a given address.
+//!
+//! Key events are fed from a key script, with one event per line on the format
+//! `<frame> <action> [key]`, where the action is one of `down`, `up`, `restart`, `quit`, `save`,
+//! `load`, `rewind`, `forward`, `screenshot`, `record`, `pause`, `advance`, `turbo`, `normal`,
+//! `toggle-turbo` or `slow-motion`, and the key is the hexadecimal CHIP-8 key 0-F. Empty lines and
+//! lines starting with # are ignored.
+//!
+//! The save state is kept in memory, so `load` restores the state from the latest `save`. The
+//! screenshots and the GIF recordings started and stopped by `record` are collected in memory, for
+//! the caller to write out. `rewind` steps back one frame per frame until `forward` resumes running
+//! the program. The frames of the key script count on while the program is paused, so a paused
+//! program can be stepped with `advance` a frame at a time. As the headless run has no real time to
+//! keep to, turbo only leaves out screens of the recordings, and slow motion has no effect.
+//!
+//! ```text
+//! # Press and release key 5
+//! 10 down 5
+//! 14 up 5
+//! ```
+
+use crate::emulator::{Emulator, FrameResult, KeyEvent, System};
+use crate::framebuffer::Framebuffer;
+use crate::output::SharedBuffer;
+use crate::savestate::SaveState;
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::io;
+
+/// Limits for how long the headless run continues
+#[derive(Default, Clone, Copy)]
+pub struct Limits {
+    /// Maximum number of frames to run
+    pub frames: Option<u64>,
+    /// Address to stop at, before executing the instruction there
+    pub until_pc: Option<u16>,
+}
+
+/// Why the headless run ended
+#[derive(Debug, PartialEq)]
+pub enum StopReason {
+    /// The maximum number of frames was reached
+    FrameLimit,
+    /// The program counter reached the requested address
+    PcReached,
+    /// The program was halted by the exit op code (00FD)
+    Halted,
+    /// A quit event in the key script
+    Quit,
+}
+
+pub struct Headless {
+    frame: u64,
+    key_script: KeyScript,
+    saved_state: Option<SaveState>,
+    screenshots: Vec<Framebuffer>,
+    /// The recordings kept in memory, shared with the emulator writing them
+    recordings: Vec<SharedBuffer>,
+}
+
+/// The key events of a key script or a movie, fed by frame number
+pub struct KeyScript {
+    events: VecDeque<(u64, KeyEvent)>,
+}
+
+impl KeyScript {
+    /// Creates the key script of the events, in frame order.
+    pub fn new(events: Vec<(u64, KeyEvent)>) -> Self {
+        let mut events = events;
+        events.sort_by_key(|(frame, _)| *frame);
+        Self {
+            events: events.into(),
+        }
+    }
+
+    /// Returns the next event at or before the frame, None if there are no more events
+    /// in the frame.
+    pub fn next_event(&mut self, frame: u64) -> Option<KeyEvent> {
+        match self.events.front() {
+            Some((f, _)) if *f <= frame => self.events.pop_front().map(|(_, e)| e),
+            _ => None,
+        }
+    }
+
+    /// True when all events have been fed.
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+impl System for Headless {
+    fn update_screen(&mut self, _framebuffer: &Framebuffer) {}
+
+    fn get_key_event(&mut self) -> Option<KeyEvent> {
+        self.key_script.next_event(self.frame)
+    }
+
+    fn store_state(&mut self, state: SaveState) {
+        self.saved_state = Some(state);
+    }
+
+    fn save_screenshot(&mut self, framebuffer: &Framebuffer) {
+        self.screenshots.push(framebuffer.clone());
+    }
+
+    fn open_recording(&mut self) -> Option<Box<dyn io::Write>> {
+        let buffer = SharedBuffer::default();
+        self.recordings.push(buffer.clone());
+        Some(Box::new(buffer))
+    }
+
+    fn fetch_state(&mut self) -> Option<SaveState> {
+        self.saved_state.clone()
+    }
+}
+
+impl Headless {
+    /// Creates a new Headless instance, feeding the key events of the key script.
+    pub fn new(key_script: Vec<(u64, KeyEvent)>) -> Self {
+        Self {
+            frame: 0,
+            key_script: KeyScript::new(key_script),
+            saved_state: None,
+            screenshots: Vec::new(),
+            recordings: Vec::new(),
+        }
+    }
+
+    /// The screenshots taken by the key script so far.
+    pub fn screenshots(&self) -> &[Framebuffer] {
+        &self.screenshots
+    }
+
+    /// The GIF files recorded by the key script so far. The last one is incomplete while the
+    /// emulator is still recording.
+    pub fn recordings(&self) -> Vec<Vec<u8>> {
+        self.recordings.iter().map(SharedBuffer::contents).collect()
+    }
+
+    /// Number of frames run so far.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Runs the emulator without any delay between the frames, until one of the limits is reached
+    /// or the program is halted.
+    pub fn run(&mut self, emulator: &mut Emulator, limits: Limits) -> StopReason {
+        loop {
+            if limits.frames.is_some_and(|f| self.frame >= f) {
+                return StopReason::FrameLimit;
+            }
+            let result = emulator.run_system_frame(self, |e| Some(e.pc()) == limits.until_pc);
+            if result != FrameResult::Quit {
+                self.frame += 1;
+            }
+            match result {
+                FrameResult::Completed => (),
+                FrameResult::Stopped => return StopReason::PcReached,
+                FrameResult::Halted => return StopReason::Halted,
+                FrameResult::Quit => return StopReason::Quit,
+            }
+        }
+    }
+}
+
+/// Parses a key script. Returns an error message with the line number if a line is invalid.
+pub fn parse_key_script(script: &str) -> Result<Vec<(u64, KeyEvent)>, String> {
+    let mut events = Vec::new();
+    for (line_nbr, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |msg: &str| format!("Key script line {}: {msg}", line_nbr + 1);
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let frame = fields
+            .first()
+            .and_then(|f| f.parse().ok())
+            .ok_or_else(|| error("invalid frame number"))?;
+        let key = fields
+            .get(2)
+            .and_then(|k| u8::from_str_radix(k, 16).ok())
+            .filter(|k| *k <= 0xF)
+            .ok_or_else(|| error("invalid key, expected 0-F"));
+        let event = match fields.get(1) {
+            Some(&"down") => KeyEvent::KeyDown(key?),
+            Some(&"up") => KeyEvent::KeyUp(key?),
+            Some(&"restart") => KeyEvent::Restart,
+            Some(&"quit") => KeyEvent::Quit,
+            Some(&"save") => KeyEvent::SaveState,
+            Some(&"load") => KeyEvent::LoadState,
+            Some(&"rewind") => KeyEvent::Rewind(true),
+            Some(&"forward") => KeyEvent::Rewind(false),
+            Some(&"screenshot") => KeyEvent::Screenshot,
+            Some(&"record") => KeyEvent::Record,
+            Some(&"pause") => KeyEvent::Pause,
+            Some(&"advance") => KeyEvent::FrameAdvance,
+            Some(&"turbo") => KeyEvent::Turbo(true),
+            Some(&"normal") => KeyEvent::Turbo(false),
+            Some(&"toggle-turbo") => KeyEvent::ToggleTurbo,
+            Some(&"slow-motion") => KeyEvent::SlowMotion,
+            _ => {
+                return Err(error(
+                    "invalid action, expected down, up, restart, quit, save, load, rewind, forward, \
+                     screenshot, record, pause, advance, turbo, normal, toggle-turbo or slow-motion",
+                ));
+            }
+        };
+        events.push((frame, event));
+    }
+    Ok(events)
+}
+
+/// Formats a key event as the action and key of a key script line, e.g. `down 5`.
+pub fn format_key_event(event: KeyEvent) -> String {
+    match event {
+        KeyEvent::KeyDown(k) => format!("down {k:X}"),
+        KeyEvent::KeyUp(k) => format!("up {k:X}"),
+        KeyEvent::Restart => "restart".to_string(),
+        KeyEvent::Quit => "quit".to_string(),
+        KeyEvent::SaveState => "save".to_string(),
+        KeyEvent::LoadState => "load".to_string(),
+        KeyEvent::Rewind(true) => "rewind".to_string(),
+        KeyEvent::Rewind(false) => "forward".to_string(),
+        KeyEvent::Screenshot => "screenshot".to_string(),
+        KeyEvent::Record => "record".to_string(),
+        KeyEvent::Pause => "pause".to_string(),
+        KeyEvent::FrameAdvance => "advance".to_string(),
+        KeyEvent::Turbo(true) => "turbo".to_string(),
+        KeyEvent::Turbo(false) => "normal".to_string(),
+        KeyEvent::ToggleTurbo => "toggle-turbo".to_string(),
+        KeyEvent::SlowMotion => "slow-motion".to_string(),
+    }
+}
+
+/// Dumps the register state and the framebuffer of the emulator as text.
+/// Pixels are drawn as `.` when off, and `#`, `+` or `@` when set in the first, second or both planes.
+pub fn dump_state(emulator: &Emulator) -> String {
+    let mut dump = dump_registers(emulator);
+    let framebuffer = emulator.framebuffer();
+    let _ = writeln!(
+        dump,
+        "Framebuffer {}x{}:",
+        framebuffer.width(),
+        framebuffer.height()
+    );
+    for row in framebuffer.pixels().chunks(framebuffer.width().max(1)) {
+        let line: String = row
+            .iter()
+            .map(|p| match p & 0x3 {
+                0 => '.',
+                1 => '#',
+                2 => '+',
+                _ => '@',
+            })
+            .collect();
+        let _ = writeln!(dump, "{line}");
+    }
+    dump
+}
+
+/// Dumps the registers, timers and the stack of the emulator as text.
+pub fn dump_registers(emulator: &Emulator) -> String {
+    let mut dump = String::new();
+    let _ = writeln!(
+        dump,
+        "PC: {:04X}  I: {:04X}  SP: {}  DT: {:02X}  ST: {:02X}",
+        emulator.pc(),
+        emulator.index(),
+        emulator.stack().len(),
+        emulator.delay_timer(),
+        emulator.sound_timer()
+    );
+    let registers: Vec<String> = emulator
+        .registers()
+        .iter()
+        .enumerate()
+        .map(|(i, v)| format!("V{i:X}: {v:02X}"))
+        .collect();
+    let _ = writeln!(dump, "{}", registers.join("  "));
+    let stack: Vec<String> = emulator
+        .stack()
+        .iter()
+        .map(|a| format!("{a:04X}"))
+        .collect();
+    let _ = writeln!(dump, "Stack: {}", stack.join(" "));
+    dump
+}
+
+#[path = "unittest/test_headless.rs"]
+#[cfg(test)]
+mod test_headless;
@@ -0,0 +1,441 @@
+//! # Key bindings
+//!
+//! Maps the keys of the keyboard to the CHIP-8 keys 0x0-0xF and to the control actions, configured in the
+//! `[keyboard]` section of options.toml. Keys are named as in SDL, e.g. `Q`, `1`, `Up`, `Space`,
+//! `Keypad 7` or `F5`, case insensitive.
+//!
+//! The CHIP-8 keys start out from a layout preset:
+//!
+//! ```text
+//!  COSMAC VIP    qwerty     numpad
+//!  1 2 3 C       1 2 3 4    Keypad 1  2  3  -
+//!  4 5 6 D   =>  Q W E R    Keypad 4  5  6  +
+//!  7 8 9 E       A S D F    Keypad 7  8  9  Enter
+//!  A 0 B F       Z X C V    Keypad /  0  *  .
+//! ```
+//!
+//! Single keys can then be rebound, for all ROMs or for one ROM only, named by its file name without
+//! the extension. This is handy for games which only use a few keys:
+//!
+//! ```toml
+//! [keyboard]
+//! layout = "qwerty"
+//! keys = { 5 = "Up", 8 = "Down" }
+//! quit = "Escape"
+//!
+//! [keyboard.rom.blitz]
+//! keys = { 5 = "Space" }
+//! ```
+//!
+//! Game controller buttons are bound the other way around, from the SDL button name to a CHIP-8 key
+//! or an action, in the `[controller]` section. The triggers count as buttons when pressed halfway.
+//! A controller profile next to the ROM file, e.g. `blitz.controller.toml` for `blitz.ch8`, overrides
+//! single buttons for that ROM:
+//!
+//! ```toml
+//! [buttons]
+//! a = "5"
+//! start = "restart"
+//! ```
+
+use crate::emulator::KeyEvent;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// Layout presets for the CHIP-8 keys
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    /// The COSMAC VIP keypad on the 1234/QWER/ASDF/ZXCV block of the keyboard
+    #[default]
+    Qwerty,
+    /// The COSMAC VIP keypad on the numeric keypad, with A-F on the keys around the digits
+    Numpad,
+}
+
+impl Layout {
+    /// Returns the key names of the CHIP-8 keys 0x0-0xF.
+    pub fn keys(self) -> [&'static str; 16] {
+        match self {
+            Layout::Qwerty => [
+                "X", "1", "2", "3", "Q", "W", "E", "A", "S", "D", "Z", "C", "4", "R", "F", "V",
+            ],
+            Layout::Numpad => [
+                "Keypad 0",
+                "Keypad 1",
+                "Keypad 2",
+                "Keypad 3",
+                "Keypad 4",
+                "Keypad 5",
+                "Keypad 6",
+                "Keypad 7",
+                "Keypad 8",
+                "Keypad 9",
+                "Keypad /",
+                "Keypad *",
+                "Keypad -",
+                "Keypad +",
+                "Keypad Enter",
+                "Keypad .",
+            ],
+        }
+    }
+}
+
+/// The control actions which can be bound to keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    Restart,
+    SaveState,
+    LoadState,
+    /// Rewind while the key is held
+    Rewind,
+    Screenshot,
+    /// Start or stop recording the screen
+    Record,
+    Pause,
+    FrameAdvance,
+    /// Turbo while the key is held
+    Turbo,
+    ToggleTurbo,
+    SlowMotion,
+}
+
+impl Action {
+    /// Parses the action from its name in the options, e.g. `save_state`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "quit" => Some(Action::Quit),
+            "restart" => Some(Action::Restart),
+            "save_state" => Some(Action::SaveState),
+            "load_state" => Some(Action::LoadState),
+            "rewind" => Some(Action::Rewind),
+            "screenshot" => Some(Action::Screenshot),
+            "record" => Some(Action::Record),
+            "pause" => Some(Action::Pause),
+            "frame_advance" => Some(Action::FrameAdvance),
+            "turbo" => Some(Action::Turbo),
+            "toggle_turbo" => Some(Action::ToggleTurbo),
+            "slow_motion" => Some(Action::SlowMotion),
+            _ => None,
+        }
+    }
+
+    /// Returns the key event for pressing the key of the action.
+    pub fn press_event(self) -> KeyEvent {
+        match self {
+            Action::Quit => KeyEvent::Quit,
+            Action::Restart => KeyEvent::Restart,
+            Action::SaveState => KeyEvent::SaveState,
+            Action::LoadState => KeyEvent::LoadState,
+            Action::Rewind => KeyEvent::Rewind(true),
+            Action::Screenshot => KeyEvent::Screenshot,
+            Action::Record => KeyEvent::Record,
+            Action::Pause => KeyEvent::Pause,
+            Action::FrameAdvance => KeyEvent::FrameAdvance,
+            Action::Turbo => KeyEvent::Turbo(true),
+            Action::ToggleTurbo => KeyEvent::ToggleTurbo,
+            Action::SlowMotion => KeyEvent::SlowMotion,
+        }
+    }
+
+    /// Returns the key event for releasing the key of the action, for the actions which are held.
+    pub fn release_event(self) -> Option<KeyEvent> {
+        match self {
+            Action::Rewind => Some(KeyEvent::Rewind(false)),
+            Action::Turbo => Some(KeyEvent::Turbo(false)),
+            _ => None,
+        }
+    }
+}
+
+/// What a key is bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Binding {
+    /// A CHIP-8 key 0x0-0xF
+    Key(u8),
+    Action(Action),
+}
+
+/// Key binding overrides for a single ROM
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct RomKeyboard {
+    pub layout: Option<Layout>,
+    pub keys: BTreeMap<String, String>,
+}
+
+/// The `[keyboard]` section of the options
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct KeyboardOptions {
+    pub layout: Layout,
+    /// Key names of CHIP-8 keys, by hexadecimal key number, overriding the layout
+    pub keys: BTreeMap<String, String>,
+    pub quit: String,
+    pub restart: String,
+    pub save_state: String,
+    pub load_state: String,
+    pub rewind: String,
+    pub screenshot: String,
+    pub record: String,
+    pub pause: String,
+    pub frame_advance: String,
+    pub turbo: String,
+    pub toggle_turbo: String,
+    pub slow_motion: String,
+    /// Overrides by ROM file name, without the extension
+    pub rom: BTreeMap<String, RomKeyboard>,
+}
+
+impl Default for KeyboardOptions {
+    fn default() -> Self {
+        KeyboardOptions {
+            layout: Layout::default(),
+            keys: BTreeMap::new(),
+            quit: "Escape".to_string(),
+            restart: "F5".to_string(),
+            save_state: "F1".to_string(),
+            load_state: "F2".to_string(),
+            rewind: "Backspace".to_string(),
+            screenshot: "F12".to_string(),
+            record: "F11".to_string(),
+            pause: "F6".to_string(),
+            frame_advance: "F7".to_string(),
+            turbo: "Tab".to_string(),
+            toggle_turbo: "F8".to_string(),
+            slow_motion: "F9".to_string(),
+            rom: BTreeMap::new(),
+        }
+    }
+}
+
+impl KeyboardOptions {
+    /// Reads the keyboard options from the `[keyboard]` section of the options table.
+    /// Falls back to the defaults if the section is invalid.
+    pub fn from_table(options: &toml::Table) -> Self {
+        match options.get("keyboard") {
+            Some(section) => section.clone().try_into().unwrap_or_else(|e| {
+                eprintln!("Warning: Invalid [keyboard] options, using the default keys: {e}");
+                KeyboardOptions::default()
+            }),
+            None => KeyboardOptions::default(),
+        }
+    }
+}
+
+/// The resolved key bindings, by lower case key name
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyBindings {
+    bindings: HashMap<String, Binding>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self::new(&KeyboardOptions::default(), None)
+    }
+}
+
+impl KeyBindings {
+    /// Resolves the key bindings of the options, with the overrides for the ROM if there are any.
+    /// Invalid CHIP-8 key numbers are skipped with a warning.
+    pub fn new(options: &KeyboardOptions, rom_name: Option<&str>) -> Self {
+        let rom = rom_name.and_then(|r| options.rom.get(r));
+        let layout = rom.and_then(|r| r.layout).unwrap_or(options.layout);
+
+        let mut keys = layout.keys().map(|k| k.to_string());
+        let overrides = options.keys.iter().chain(rom.iter().flat_map(|r| &r.keys));
+        for (key, name) in overrides {
+            match u8::from_str_radix(key, 16) {
+                Ok(k) if k < 16 => keys[k as usize] = name.clone(),
+                _ => eprintln!("Warning: Invalid CHIP-8 key {key} in the key bindings"),
+            }
+        }
+
+        let mut bindings = HashMap::new();
+        for (name, action) in [
+            (&options.quit, Action::Quit),
+            (&options.restart, Action::Restart),
+            (&options.save_state, Action::SaveState),
+            (&options.load_state, Action::LoadState),
+            (&options.rewind, Action::Rewind),
+            (&options.screenshot, Action::Screenshot),
+            (&options.record, Action::Record),
+            (&options.pause, Action::Pause),
+            (&options.frame_advance, Action::FrameAdvance),
+            (&options.turbo, Action::Turbo),
+            (&options.toggle_turbo, Action::ToggleTurbo),
+            (&options.slow_motion, Action::SlowMotion),
+        ] {
+            bindings.insert(name.to_lowercase(), Binding::Action(action));
+        }
+        for (name, first, second) in duplicate_keys(&keys) {
+            eprintln!(
+                "Warning: Key {name} is bound to both CHIP-8 keys {first:X} and {second:X}, \
+                 key {first:X} is left unbound"
+            );
+        }
+        // A key rebound from an action to a CHIP-8 key takes precedence
+        for (k, name) in keys.iter().enumerate() {
+            bindings.insert(name.to_lowercase(), Binding::Key(k as u8));
+        }
+        Self { bindings }
+    }
+
+    /// Returns what the key with the name is bound to.
+    pub fn lookup(&self, key_name: &str) -> Option<Binding> {
+        self.bindings.get(&key_name.to_lowercase()).copied()
+    }
+}
+
+/// Returns the key names bound to more than one CHIP-8 key, with the two CHIP-8 keys. The later
+/// key takes the key name, leaving the earlier one unbound.
+fn duplicate_keys(keys: &[String; 16]) -> Vec<(&str, u8, u8)> {
+    let mut seen: HashMap<String, u8> = HashMap::new();
+    let mut duplicates = Vec::new();
+    for (k, name) in keys.iter().enumerate() {
+        if let Some(first) = seen.insert(name.to_lowercase(), k as u8) {
+            duplicates.push((name.as_str(), first, k as u8));
+        }
+    }
+    duplicates
+}
+
+/// The SDL names of the game controller buttons, with the triggers
+const CONTROLLER_BUTTONS: [&str; 23] = [
+    "a",
+    "b",
+    "x",
+    "y",
+    "back",
+    "guide",
+    "start",
+    "leftstick",
+    "rightstick",
+    "leftshoulder",
+    "rightshoulder",
+    "dpup",
+    "dpdown",
+    "dpleft",
+    "dpright",
+    "misc1",
+    "paddle1",
+    "paddle2",
+    "paddle3",
+    "paddle4",
+    "touchpad",
+    "lefttrigger",
+    "righttrigger",
+];
+
+/// The `[controller]` section of the options, or a controller profile
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct ControllerOptions {
+    /// CHIP-8 keys in hexadecimal, or action names, by controller button name
+    pub buttons: BTreeMap<String, String>,
+}
+
+impl Default for ControllerOptions {
+    /// The d-pad on 5/7/8/9 and A/B on 6/4, the keys most games use for moving and acting
+    fn default() -> Self {
+        let buttons = [
+            ("dpup", "5"),
+            ("dpleft", "7"),
+            ("dpdown", "8"),
+            ("dpright", "9"),
+            ("a", "6"),
+            ("b", "4"),
+        ];
+        ControllerOptions {
+            buttons: buttons
+                .iter()
+                .map(|(b, k)| (b.to_string(), k.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl ControllerOptions {
+    /// Reads the controller options from the `[controller]` section of the options table.
+    /// Falls back to the defaults if the section is invalid.
+    pub fn from_table(options: &toml::Table) -> Self {
+        match options.get("controller") {
+            Some(section) => section.clone().try_into().unwrap_or_else(|e| {
+                eprintln!("Warning: Invalid [controller] options, using the default buttons: {e}");
+                ControllerOptions::default()
+            }),
+            None => ControllerOptions::default(),
+        }
+    }
+
+    /// Overrides the buttons with the ones of the controller profile file, if it exists.
+    pub fn with_profile_file(mut self, path: &Path) -> Self {
+        if !path.exists() {
+            return self;
+        }
+        let profile = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|s| toml::from_str::<ControllerOptions>(&s).map_err(|e| e.to_string()));
+        match profile {
+            Ok(profile) => self.buttons.extend(profile.buttons),
+            Err(e) => eprintln!(
+                "Warning: Failed to read controller profile {}: {e}",
+                path.display()
+            ),
+        }
+        self
+    }
+}
+
+/// The resolved game controller bindings, by button name
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControllerBindings {
+    bindings: HashMap<String, Binding>,
+}
+
+impl Default for ControllerBindings {
+    fn default() -> Self {
+        Self::new(&ControllerOptions::default())
+    }
+}
+
+impl ControllerBindings {
+    /// Resolves the controller bindings of the options.
+    /// Unknown buttons and invalid bindings are skipped with a warning.
+    pub fn new(options: &ControllerOptions) -> Self {
+        let mut bindings = HashMap::new();
+        for (button, target) in &options.buttons {
+            let button = button.to_lowercase();
+            if !CONTROLLER_BUTTONS.contains(&button.as_str()) {
+                eprintln!("Warning: Unknown controller button {button}");
+                continue;
+            }
+            let binding = match u8::from_str_radix(target, 16) {
+                Ok(k) if k < 16 => Binding::Key(k),
+                _ => match Action::from_name(target) {
+                    Some(action) => Binding::Action(action),
+                    None => {
+                        eprintln!(
+                            "Warning: Invalid binding {target} of controller button {button}"
+                        );
+                        continue;
+                    }
+                },
+            };
+            bindings.insert(button, binding);
+        }
+        Self { bindings }
+    }
+
+    /// Returns what the button with the SDL name is bound to.
+    pub fn lookup(&self, button_name: &str) -> Option<Binding> {
+        self.bindings.get(&button_name.to_lowercase()).copied()
+    }
+}
+
+#[path = "unittest/test_keybindings.rs"]
+#[cfg(test)]
+mod test_keybindings;
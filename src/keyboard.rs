@@ -1,94 +1,94 @@
 //! # Keyboard
 //!
 //! Contains the keyboard module for CHIP-8.
-//! Keys are mapped according to the original COSMAC VIP keypad:
+//! Keys are mapped with the key bindings of the options, see [`crate::keybindings`]. By default the
+//! CHIP-8 keys follow the original COSMAC VIP keypad layout on the 1234/QWER/ASDF/ZXCV keys.
 //!
-//! 1 2 3 C        1 2 3 4
-//! 4 5 6 D   =>   q w e r
-//! 7 8 9 E        a s d f
-//! A 0 B F        z x c v
-//!
-//! Additional keys are:
+//! Additional keys by default are:
 //! ESC: To exit the program.
+//! F1:  To save the machine state to the save state file of the ROM.
+//! F2:  To load the machine state from the save state file of the ROM.
 //! F5:  To restart the program.
+//! Backspace: Hold to rewind the program frame by frame.
+//! F11: To start and stop recording the screen to a GIF next to the ROM file.
+//! F12: To save a screenshot next to the ROM file.
+//! F6:  To pause and resume the program.
+//! F7:  To run a single frame, pausing the program first.
+//! Tab: Hold to run the program as fast as possible, showing every few frames.
+//! F8:  To switch turbo on and off.
+//! F9:  To switch slow motion on and off.
+//!
+//! The game controller events arrive through the same event queue, and are passed on to
+//! [`crate::controller`].
 
+use crate::controller::Controllers;
 use crate::emulator::KeyEvent;
-use sdl2::{EventPump, Sdl, event::Event, keyboard::Keycode};
+use crate::keybindings::{Binding, KeyBindings};
+use sdl2::{EventPump, Sdl, event::Event};
 use std::error::Error;
 
-/// Utility function to map the SDL keycode to the internal CHIP-8 number 0x0-0xF.
-fn map_keycode(key: &Keycode) -> Option<u8> {
-    match *key {
-        Keycode::Num1 => Some(0x1),
-        Keycode::Num2 => Some(0x2),
-        Keycode::Num3 => Some(0x3),
-        Keycode::Num4 => Some(0xC),
-
-        Keycode::Q => Some(0x4),
-        Keycode::W => Some(0x5),
-        Keycode::E => Some(0x6),
-        Keycode::R => Some(0xD),
-
-        Keycode::A => Some(0x7),
-        Keycode::S => Some(0x8),
-        Keycode::D => Some(0x9),
-        Keycode::F => Some(0xE),
-
-        Keycode::Z => Some(0xA),
-        Keycode::X => Some(0x0),
-        Keycode::C => Some(0xB),
-        Keycode::V => Some(0xF),
-        _ => None,
-    }
-}
-
 pub struct Keyboard {
     event_pump: EventPump,
+    bindings: KeyBindings,
+    controllers: Option<Controllers>,
 }
 
 impl Keyboard {
     /// Creates a new Keyboard instance
-    pub fn new(sdl_context: &Sdl) -> Result<Self, Box<dyn Error>> {
+    pub fn new(
+        sdl_context: &Sdl,
+        bindings: KeyBindings,
+        controllers: Option<Controllers>,
+    ) -> Result<Self, Box<dyn Error>> {
         let event_pump = sdl_context.event_pump()?;
-        Ok(Self { event_pump })
+        Ok(Self {
+            event_pump,
+            bindings,
+            controllers,
+        })
     }
 
     /// Get the next queued up CHIP-8 key. Ignores unmapped events in the queue.
     /// Returns None if nothing is queued.
     pub fn get_chip8_key_events(&mut self) -> Option<KeyEvent> {
+        if let Some(e) = self.controllers.as_mut().and_then(|c| c.pending_event()) {
+            return Some(e);
+        }
         for event in self.event_pump.poll_iter() {
             match event {
-                // Events for exiting program
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
-                } => {
-                    return Some(KeyEvent::Quit);
-                }
-                // Events for restarting program
+                // Closing the window exits the program
+                Event::Quit { .. } => return Some(KeyEvent::Quit),
                 Event::KeyDown {
-                    keycode: Some(Keycode::F5),
+                    keycode: Some(key),
+                    repeat,
                     ..
-                } => {
-                    return Some(KeyEvent::Restart);
-                }
-                // Events mapping to regular keys
-                Event::KeyDown {
-                    keycode: Some(key), ..
-                } => {
-                    if let Some(k) = map_keycode(&key) {
-                        return Some(KeyEvent::KeyDown(k));
-                    }
-                }
+                } => match self.bindings.lookup(&key.name()) {
+                    Some(Binding::Key(k)) => return Some(KeyEvent::KeyDown(k)),
+                    // The actions ignore the key repeats while held, e.g. for rewinding
+                    Some(Binding::Action(_)) if repeat => (),
+                    Some(Binding::Action(action)) => return Some(action.press_event()),
+                    None => (),
+                },
                 Event::KeyUp {
                     keycode: Some(key), ..
-                } => {
-                    if let Some(k) = map_keycode(&key) {
-                        return Some(KeyEvent::KeyUp(k));
+                } => match self.bindings.lookup(&key.name()) {
+                    Some(Binding::Key(k)) => return Some(KeyEvent::KeyUp(k)),
+                    Some(Binding::Action(action)) => {
+                        if let Some(e) = action.release_event() {
+                            return Some(e);
+                        }
+                    }
+                    None => (),
+                },
+                event => {
+                    if let Some(e) = self
+                        .controllers
+                        .as_mut()
+                        .and_then(|c| c.handle_event(&event))
+                    {
+                        return Some(e);
                     }
                 }
-                _ => (),
             }
         }
         None
@@ -1,70 +1,503 @@
+//! # CHIP-8
+//!
+//! A CHIP-8, SUPER-CHIP and XO-CHIP emulator. Besides the SDL2 and terminal frontends, the emulator core can be
+//! embedded as a library, either by implementing the [`emulator::System`] trait for the surrounding
+//! system, or by stepping the [`emulator::Emulator`] directly:
+//!
+//! ```
+//! use chip8::{chip8options::Chip8options, emulator::Emulator};
+//!
+//! // V0 := 5, then loop forever
+//! let mut emulator = Emulator::new(&[0x60, 0x05, 0x12, 0x02], &Chip8options::default());
+//! emulator.step();
+//! assert_eq!(emulator.registers()[0], 5);
+//! emulator.set_key(0xA, true);
+//! emulator.run_frame();
+//! assert_eq!(emulator.pc(), 0x202);
+//! ```
 use clap::Parser;
-use std::{error::Error, fs, path::PathBuf};
+use std::{error::Error, fs, io::BufWriter, ops::RangeInclusive, path::PathBuf};
 
-use chip8options::Chip8options;
+#[cfg(feature = "sdl")]
+use audio::Audio;
+use chip8options::{Chip8options, Platform, RandomAlgorithm};
+#[cfg(feature = "sdl")]
+use controller::Controllers;
+use debugger::Debugger;
+#[cfg(feature = "sdl")]
 use display::Display;
-use emulator::Emulator;
+use emulator::{Emulator, System};
+#[cfg(feature = "sdl")]
+use framebuffer::Framebuffer;
+use gdbstub::GdbStub;
+use headless::{Headless, KeyScript};
+#[cfg(feature = "sdl")]
+use keybindings::{ControllerBindings, ControllerOptions};
+use keybindings::{KeyBindings, KeyboardOptions};
+#[cfg(feature = "sdl")]
 use keyboard::Keyboard;
+use movie::{Movie, Recorder};
+#[cfg(feature = "sdl")]
+use savestate::SaveState;
+use trace::{TraceFilter, Tracer};
+use tui::Terminal;
 
-mod chip8options;
+pub mod assembler;
+#[cfg(feature = "sdl")]
+mod audio;
+pub mod chip8options;
+#[cfg(feature = "sdl")]
+mod controller;
+pub mod debugger;
+pub mod disassembler;
+#[cfg(feature = "sdl")]
 mod display;
-mod emulator;
+pub mod emulator;
+pub mod framebuffer;
+pub mod gdbstub;
+mod gif;
+pub mod headless;
+pub mod keybindings;
+#[cfg(feature = "sdl")]
 mod keyboard;
-mod opcode;
+pub mod movie;
+pub mod octo;
+pub mod opcode;
+mod output;
+pub mod pacing;
+mod png;
+pub mod random;
+pub mod recording;
+pub mod savestate;
+pub mod screenshot;
+pub mod trace;
+mod tui;
 
+#[cfg(feature = "sdl")]
 struct Peripherals {
     display: display::Display,
     keyboard: keyboard::Keyboard,
+    audio: Option<audio::Audio>,
+    state_file: PathBuf,
+    rom_file: PathBuf,
+    disp_options: chip8options::Display,
 }
 
+#[cfg(feature = "sdl")]
 impl emulator::System for Peripherals {
-    fn update_screen(&mut self, display_output: &[bool]) {
-        self.display.draw_screen(display_output);
+    fn update_screen(&mut self, framebuffer: &Framebuffer) {
+        self.display.draw_screen(framebuffer);
     }
 
     fn get_key_event(&mut self) -> Option<emulator::KeyEvent> {
         self.keyboard.get_chip8_key_events()
     }
+
+    fn set_sound_state(&mut self, sound_on: bool) {
+        if let Some(audio) = &mut self.audio {
+            audio.set_playing(sound_on);
+        }
+    }
+
+    fn set_audio_pattern(&mut self, pattern: Option<&[u8; 16]>, pitch: u8) {
+        if let Some(audio) = &mut self.audio {
+            audio.set_pattern(pattern, pitch);
+        }
+    }
+
+    fn store_state(&mut self, state: SaveState) {
+        match state.save(&self.state_file) {
+            Ok(()) => println!("Saved state to {}", self.state_file.display()),
+            Err(e) => eprintln!("Warning: Failed to save state: {e}"),
+        }
+    }
+
+    fn save_screenshot(&mut self, framebuffer: &Framebuffer) {
+        let path = screenshot::timestamped_path(&self.rom_file, "png");
+        match screenshot::save_png(&path, framebuffer, &self.disp_options) {
+            Ok(()) => println!("Saved screenshot to {}", path.display()),
+            Err(e) => eprintln!("Warning: Failed to save screenshot: {e}"),
+        }
+    }
+
+    fn open_recording(&mut self) -> Option<Box<dyn std::io::Write>> {
+        let path = screenshot::timestamped_path(&self.rom_file, "gif");
+        match fs::File::create(&path) {
+            Ok(file) => {
+                println!("Recording to {}", path.display());
+                Some(Box::new(BufWriter::new(file)))
+            }
+            Err(e) => {
+                eprintln!("Warning: Failed to create recording: {e}");
+                None
+            }
+        }
+    }
+
+    fn recording_stopped(&mut self, result: std::io::Result<()>) {
+        match result {
+            Ok(()) => println!("Recording stopped"),
+            Err(e) => eprintln!("Warning: Failed to save recording: {e}"),
+        }
+    }
+
+    fn wait_vsync(&mut self) -> bool {
+        self.display.wait_vsync()
+    }
+
+    fn show_speed(&mut self, speed: pacing::Speed) {
+        self.display.show_speed(speed);
+    }
+
+    fn fetch_state(&mut self) -> Option<SaveState> {
+        SaveState::load(&self.state_file)
+            .inspect_err(|e| eprintln!("Warning: Failed to load state: {e}"))
+            .ok()
+    }
+}
+
+/// The frontends the emulator can run in
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum Frontend {
+    /// SDL2 window with sound
+    Sdl,
+    /// Terminal, drawing with Unicode half blocks
+    Tui,
+}
+
+impl Default for Frontend {
+    fn default() -> Self {
+        if cfg!(feature = "sdl") {
+            Frontend::Sdl
+        } else {
+            Frontend::Tui
+        }
+    }
 }
 
 /// CHIP-8 emulator program
 #[derive(Parser, Debug)]
 #[command(version, long_about = None)]
 pub struct Args {
-    /// ROM file name
+    /// ROM file name, or an Octo source file (.8o) which is compiled before running
     #[arg(name = "FILE")]
     rom_file: PathBuf,
+
+    /// Platform preset for the op code quirks, memory layout and timing, overrides the one in options.toml
+    #[arg(short, long, value_enum)]
+    platform: Option<Platform>,
+
+    /// Seed of the random number generator, for reproducible runs, overrides the one in options.toml
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Algorithm of the random number generator, overrides the one in options.toml
+    #[arg(long, value_enum)]
+    rng: Option<RandomAlgorithm>,
+
+    /// Frontend for the display, sound and keyboard
+    #[arg(long, value_enum, default_value_t = Frontend::default())]
+    frontend: Frontend,
+
+    /// Run without display, sound and keyboard, and dump the final state when done
+    #[arg(long)]
+    headless: bool,
+
+    /// Start paused in the debugger, taking commands from the terminal
+    #[arg(long, conflicts_with = "headless")]
+    debug: bool,
+
+    /// Start stopped and wait for a GDB remote protocol client to connect on the local port
+    #[arg(long, value_name = "PORT", num_args = 0..=1, default_missing_value = "1234",
+          conflicts_with_all = ["headless", "debug"])]
+    gdb: Option<u16>,
+
+    /// Headless mode: number of frames to run
+    #[arg(long, requires = "headless")]
+    frames: Option<u64>,
+
+    /// Headless mode: stop when the program counter reaches this (hexadecimal) address
+    #[arg(long, requires = "headless", value_parser = parse_address)]
+    until_pc: Option<u16>,
+
+    /// Headless mode: key script file, with one "<frame> <action> [key]" event per line
+    #[arg(long, requires = "headless")]
+    keys: Option<PathBuf>,
+
+    /// Headless mode: file to dump the final framebuffer and register state to, instead of stdout
+    #[arg(long, requires = "headless")]
+    dump: Option<PathBuf>,
+
+    /// Headless mode: PNG file to write a screenshot of the final framebuffer to
+    #[arg(long, requires = "headless")]
+    screenshot: Option<PathBuf>,
+
+    /// Animated GIF file to record the screen to, from the start until the program quits
+    #[arg(long)]
+    gif: Option<PathBuf>,
+
+    /// File to write a trace of every executed instruction to
+    #[arg(long)]
+    trace: Option<PathBuf>,
+
+    /// Trace only the instructions in this (hexadecimal) PC range, e.g. 200-2FF, can be repeated
+    #[arg(long, requires = "trace", value_parser = trace::parse_pc_range)]
+    trace_pc: Vec<RangeInclusive<u16>>,
+
+    /// Trace only this kind of op code, e.g. Display or Call, can be repeated
+    #[arg(long, requires = "trace", value_parser = trace::parse_opcode_name)]
+    trace_op: Vec<String>,
+
+    /// File to record the key events to as a movie, for replaying the session with --play
+    #[arg(long, value_name = "MOVIE")]
+    record: Option<PathBuf>,
+
+    /// Movie file to replay, with the seed and options it was recorded with
+    #[arg(long, value_name = "MOVIE", conflicts_with = "record")]
+    play: Option<PathBuf>,
+}
+
+/// Utility function to parse a hexadecimal address, with or without a 0x prefix.
+pub fn parse_address(s: &str) -> Result<u16, String> {
+    let digits = s.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).map_err(|e| format!("invalid address {s}: {e}"))
+}
+
+/// Settings for running in headless mode
+struct HeadlessConfig {
+    limits: headless::Limits,
+    key_script: Option<PathBuf>,
+    dump_file: Option<PathBuf>,
+    screenshot_file: Option<PathBuf>,
 }
 
 pub struct Config {
     rom_file: PathBuf,
     chip8_options: Chip8options,
+    key_bindings: KeyBindings,
+    #[cfg(feature = "sdl")]
+    controller_bindings: ControllerBindings,
+    headless: Option<HeadlessConfig>,
+    frontend: Frontend,
+    debug: bool,
+    gdb_port: Option<u16>,
+    trace: Option<(PathBuf, TraceFilter)>,
+    record: Option<PathBuf>,
+    play: Option<PathBuf>,
+    gif: Option<PathBuf>,
 }
 
 impl Config {
     /// Builds the CHIP-8 configuration based on the input arguments.
     pub fn build(args: Args) -> Result<Config, &'static str> {
-        // Read option configurations
-        let chip8_options = Chip8options::get_options();
+        // Read option configurations, with the key bindings for the ROM
+        let options_table = Chip8options::read_options_file().unwrap_or_default();
+        let rom_name = args.rom_file.file_stem().and_then(|s| s.to_str());
+        let key_bindings = KeyBindings::new(&KeyboardOptions::from_table(&options_table), rom_name);
+        #[cfg(feature = "sdl")]
+        let controller_bindings = ControllerBindings::new(
+            &ControllerOptions::from_table(&options_table)
+                .with_profile_file(&args.rom_file.with_extension("controller.toml")),
+        );
+        let mut chip8_options = Chip8options::from_table(args.platform, options_table);
+        if let Some(seed) = args.seed {
+            chip8_options.random.seed = Some(seed);
+        }
+        if let Some(algorithm) = args.rng {
+            chip8_options.random.algorithm = algorithm;
+        }
+
+        let headless = if args.headless {
+            if args.frames.is_none() && args.until_pc.is_none() {
+                return Err("headless mode needs --frames and/or --until-pc");
+            }
+            Some(HeadlessConfig {
+                limits: headless::Limits {
+                    frames: args.frames,
+                    until_pc: args.until_pc,
+                },
+                key_script: args.keys,
+                dump_file: args.dump,
+                screenshot_file: args.screenshot,
+            })
+        } else {
+            None
+        };
+
+        if args.debug && args.frontend == Frontend::Tui {
+            return Err(
+                "the debugger reads commands from the terminal, use it with --frontend sdl",
+            );
+        }
+        if args.frontend == Frontend::Sdl && !cfg!(feature = "sdl") {
+            return Err("built without the sdl feature, use --frontend tui");
+        }
 
         Ok(Config {
             rom_file: args.rom_file,
             chip8_options,
+            key_bindings,
+            #[cfg(feature = "sdl")]
+            controller_bindings,
+            headless,
+            frontend: args.frontend,
+            debug: args.debug,
+            gdb_port: args.gdb,
+            trace: args.trace.map(|f| {
+                let filter = TraceFilter {
+                    pc_ranges: args.trace_pc,
+                    opcodes: args.trace_op,
+                };
+                (f, filter)
+            }),
+            record: args.record,
+            play: args.play,
+            gif: args.gif,
         })
     }
 
     /// Starts running the emulator.
     pub fn run(&self) -> Result<(), Box<dyn Error>> {
-        // Read ROM file
-        let rom = fs::read(&self.rom_file)?.to_vec();
-
-        let mut emulator = Emulator::new(&rom, &self.chip8_options);
-        let sdl_context = sdl2::init()?;
-        let mut peripherals = Peripherals {
-            display: Display::new(&sdl_context, &self.chip8_options.display)?,
-            keyboard: Keyboard::new(&sdl_context)?,
+        // Read ROM file, compiling Octo source
+        let rom = if self.rom_file.extension().is_some_and(|e| e == "8o") {
+            octo::compile_file(&self.rom_file)?
+        } else {
+            fs::read(&self.rom_file)?.to_vec()
+        };
+
+        // A movie is played back with the options and seed it was recorded with
+        let movie = match &self.play {
+            Some(f) => Some(Movie::load(f)?),
+            None => None,
         };
-        emulator.run(&mut peripherals);
+        let options = movie.as_ref().map_or(self.chip8_options, |m| m.options);
+        let mut emulator = Emulator::new(&rom, &options);
+        if let Some(movie) = movie {
+            if movie.rom_hash != movie::rom_hash(&rom) {
+                eprintln!("Warning: The movie was recorded with a different ROM");
+            }
+            emulator.set_seed(movie.seed);
+            emulator.set_playback(Some(KeyScript::new(movie.events)));
+        }
+        if let Some(movie_file) = &self.record {
+            let recorder = Recorder::create(movie_file, &rom, emulator.seed(), &options)?;
+            emulator.set_recorder(Some(recorder));
+        }
+        if let Some((trace_file, filter)) = &self.trace {
+            emulator.set_tracer(Some(Tracer::create(trace_file, filter.clone())?));
+        }
+        if let Some(gif_file) = &self.gif {
+            emulator.start_recording(Box::new(BufWriter::new(fs::File::create(gif_file)?)))?;
+        }
+        if let Some(headless_config) = &self.headless {
+            return run_headless(&mut emulator, headless_config, &self.rom_file);
+        }
+        // Wait for the debugger client before taking over the terminal
+        let gdb_stream = match self.gdb_port {
+            Some(port) => Some(gdbstub::accept(port)?),
+            None => None,
+        };
+        let state_file = self.rom_file.with_extension("state");
+        match self.frontend {
+            #[cfg(feature = "sdl")]
+            Frontend::Sdl => {
+                let sdl_context = sdl2::init()?;
+                let mut peripherals = Peripherals {
+                    display: Display::new(&sdl_context, &self.chip8_options.display)?,
+                    keyboard: Keyboard::new(
+                        &sdl_context,
+                        self.key_bindings.clone(),
+                        // Run with the keyboard only rather than failing without controller support
+                        Controllers::new(&sdl_context, self.controller_bindings.clone())
+                            .inspect_err(|e| {
+                                eprintln!("Warning: Failed to open game controllers: {e}")
+                            })
+                            .ok(),
+                    )?,
+                    // Run without sound rather than failing if there is no audio device
+                    audio: Audio::new(&sdl_context, &self.chip8_options.audio)
+                        .inspect_err(|e| eprintln!("Warning: Failed to open audio device: {e}"))
+                        .ok(),
+                    state_file,
+                    rom_file: self.rom_file.clone(),
+                    disp_options: self.chip8_options.display,
+                };
+                self.run_system(&mut emulator, &mut peripherals, gdb_stream)
+            }
+            #[cfg(not(feature = "sdl"))]
+            Frontend::Sdl => Err("built without the sdl feature".into()),
+            Frontend::Tui => {
+                let mut terminal = Terminal::new(
+                    &self.chip8_options.display,
+                    self.key_bindings.clone(),
+                    state_file,
+                    self.rom_file.clone(),
+                )?;
+                self.run_system(&mut emulator, &mut terminal, gdb_stream)
+            }
+        }
+    }
+
+    /// Runs the emulator connected to the frontend system, in the debugger or GDB stub if requested.
+    fn run_system<T: System>(
+        &self,
+        emulator: &mut Emulator,
+        system: &mut T,
+        gdb_stream: Option<std::net::TcpStream>,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(stream) = gdb_stream {
+            GdbStub::new(stream)?.run(emulator, system)?;
+        } else if self.debug {
+            Debugger::new().run(emulator, system, &debugger::stdin_commands());
+        } else {
+            emulator.run(system);
+        }
+        // The debuggers leave the recording running when they quit
+        if emulator.is_recording() {
+            system.recording_stopped(emulator.stop_recording());
+        }
         Ok(())
     }
 }
+
+/// Runs the emulator in headless mode, and dumps the final state, the screenshots and the recordings.
+fn run_headless(
+    emulator: &mut Emulator,
+    config: &HeadlessConfig,
+    rom_file: &std::path::Path,
+) -> Result<(), Box<dyn Error>> {
+    let key_script = match &config.key_script {
+        Some(f) => headless::parse_key_script(&fs::read_to_string(f)?)?,
+        None => Vec::new(),
+    };
+    let mut system = Headless::new(key_script);
+    let stop_reason = system.run(emulator, config.limits);
+    emulator.stop_recording()?;
+
+    let dump = format!(
+        "Stopped after {} frames: {:?}\n{}",
+        system.frame(),
+        stop_reason,
+        headless::dump_state(emulator)
+    );
+    match &config.dump_file {
+        Some(f) => fs::write(f, dump)?,
+        None => print!("{dump}"),
+    }
+
+    let disp_options = &emulator.options().display;
+    for framebuffer in system.screenshots() {
+        let path = screenshot::timestamped_path(rom_file, "png");
+        screenshot::save_png(&path, framebuffer, disp_options)?;
+        eprintln!("Saved screenshot to {}", path.display());
+    }
+    for recording in system.recordings() {
+        let path = screenshot::timestamped_path(rom_file, "gif");
+        fs::write(&path, recording)?;
+        eprintln!("Saved recording to {}", path.display());
+    }
+    if let Some(f) = &config.screenshot_file {
+        screenshot::save_png(f, emulator.framebuffer(), disp_options)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,134 @@
+//! # Movie
+//!
+//! Records the key events of a session to a movie file, so that the session can be replayed exactly,
+//! e.g. to attach to bug reports or to run as regression tests. Together with the events, the movie
+//! holds everything else a run depends on: the seed of the random number generator, the options and a
+//! hash of the ROM.
+//!
+//! The events are written as the lines of a key script (see [`crate::headless`]), numbered by the frame
+//! they were handled in, after the header lines:
+//!
+//! ```text
+//! # CHIP-8 movie
+//! rom 9C2E5A2B0D77A13F
+//! seed 14283607511829346109
+//! options { display = { display_width = 64, ... }, ... }
+//! 35 down 5
+//! 41 up 5
+//! ```
+
+use crate::chip8options::Chip8options;
+use crate::emulator::KeyEvent;
+use crate::headless;
+use crate::output::StopOnError;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// A parsed movie file
+#[derive(Clone)]
+pub struct Movie {
+    /// FNV-1a hash of the ROM the movie was recorded with
+    pub rom_hash: u64,
+    /// Seed of the random number generator
+    pub seed: u64,
+    pub options: Chip8options,
+    /// The key events by frame number
+    pub events: Vec<(u64, KeyEvent)>,
+}
+
+impl Movie {
+    /// Parses a movie. Returns an error message with the line number if a line is invalid.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut rom_hash = None;
+        let mut seed = None;
+        let mut options = None;
+        // The header lines are blanked out, to keep the line numbers of the key script errors
+        let mut script = String::new();
+        for (line_nbr, line) in source.lines().enumerate() {
+            let error = |msg: &str| format!("Movie line {}: {msg}", line_nbr + 1);
+            match line.trim().split_once(' ') {
+                Some(("rom", hash)) => {
+                    let hash = u64::from_str_radix(hash.trim(), 16);
+                    rom_hash = Some(hash.map_err(|_| error("invalid ROM hash"))?);
+                }
+                Some(("seed", s)) => {
+                    seed = Some(s.trim().parse().map_err(|_| error("invalid seed"))?);
+                }
+                Some(("options", table)) => {
+                    let parsed = format!("options = {table}")
+                        .parse::<toml::Table>()
+                        .ok()
+                        .and_then(|mut t| t.remove("options"))
+                        .and_then(|o| o.try_into().ok());
+                    options = Some(parsed.ok_or_else(|| error("invalid options"))?);
+                }
+                _ => {
+                    script.push_str(line);
+                }
+            }
+            script.push('\n');
+        }
+        Ok(Self {
+            rom_hash: rom_hash.ok_or("Movie has no rom line")?,
+            seed: seed.ok_or("Movie has no seed line")?,
+            options: options.ok_or("Movie has no options line")?,
+            events: headless::parse_key_script(&script)?,
+        })
+    }
+
+    /// Reads and parses a movie file.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::parse(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+/// Writes the key events of a session to a movie.
+pub struct Recorder {
+    writer: StopOnError<Box<dyn Write>>,
+}
+
+impl Recorder {
+    /// Creates a recorder writing to the writer, starting with the header of the movie.
+    /// The seed is the one the random number generator of the emulator was seeded with, so the
+    /// recording has to start before the first frame.
+    pub fn new(
+        mut writer: Box<dyn Write>,
+        rom: &[u8],
+        seed: u64,
+        options: &Chip8options,
+    ) -> io::Result<Self> {
+        let options = toml::Value::try_from(options).map_err(io::Error::other)?;
+        writeln!(writer, "# CHIP-8 movie")?;
+        writeln!(writer, "rom {:016X}", rom_hash(rom))?;
+        writeln!(writer, "seed {seed}")?;
+        writeln!(writer, "options {options}")?;
+        Ok(Self {
+            writer: StopOnError::new(writer, "movie"),
+        })
+    }
+
+    /// Creates a recorder writing to a new file.
+    pub fn create(path: &Path, rom: &[u8], seed: u64, options: &Chip8options) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Self::new(Box::new(file), rom, seed, options)
+    }
+
+    /// Records a key event handled in the frame.
+    pub fn record(&mut self, frame: u64, event: KeyEvent) {
+        self.writer
+            .write(|w| writeln!(w, "{frame} {}", headless::format_key_event(event)));
+    }
+}
+
+/// Hashes the ROM with 64-bit FNV-1a, to tell if a movie is played back with the ROM it was recorded with.
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xCBF2_9CE4_8422_2325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+#[path = "unittest/test_movie.rs"]
+#[cfg(test)]
+mod test_movie;
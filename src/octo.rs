@@ -0,0 +1,957 @@
+//! # Octo
+//!
+//! Compiles Octo assembly language (`.8o` files) to ROM bytes.
+//!
+//! Supported are labels (`: name`), `:alias`, `:const`, `:calc`, `:macro`, `:next`, `:unpack`,
+//! `:org`, `:byte` and `:call`, all the Octo statements for the CHIP-8, SUPER-CHIP and XO-CHIP
+//! instructions, and the structured `if ... then`, `if ... begin ... else ... end`,
+//! `loop ... while ... again` control flow, including the `<`, `>`, `<=` and `>=` comparisons
+//! which use VF as a temporary register. `:breakpoint` and `:monitor` are accepted but ignored.
+//!
+//! As in Octo, tokens are separated by whitespace, comments start with `#`, a bare label name is
+//! a subroutine call and a bare number is a data byte. `:calc` expressions, also usable in braces in
+//! place of any number, are evaluated from right to left without operator precedence.
+//!
+//! ```text
+//! : main
+//!     v0 := 0
+//!     loop
+//!         v0 += 1
+//!         if v0 == 10 then v1 := 1
+//!     again
+//! ```
+//!
+//! Errors are reported as an [`AssembleError`], with the line and column of the offending token.
+
+use crate::assembler::AssembleError;
+use crate::opcode::OpCode;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Address the program is compiled for
+const START: usize = 0x200;
+
+/// Maximum number of macro expansions, to stop recursive macros
+const MAX_EXPANSIONS: usize = 100_000;
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+/// How a forward reference to a label is patched in, once the label is defined
+#[derive(Debug, Clone, Copy)]
+enum Fixup {
+    /// The low 12 bits of an instruction
+    Nnn,
+    /// The two bytes after F000
+    Long,
+    /// The immediate byte of v0 := in :unpack, with the nibble or None for :unpack long
+    UnpackHigh(Option<u8>),
+    /// The immediate byte of v1 := in :unpack
+    UnpackLow,
+}
+
+/// Open control flow statements, with the address of the jump to patch
+#[derive(Debug)]
+enum Flow {
+    If(usize),
+    Else(usize),
+    /// The start address of the loop and the jumps of its while statements
+    Loop(usize, Vec<usize>),
+}
+
+/// Right hand side of a comparison
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    Register(u8),
+    Byte(u8),
+}
+
+/// Compiles Octo source.
+pub fn compile(source: &str) -> Result<Vec<u8>, AssembleError> {
+    Compiler::new("<source>", source).run()
+}
+
+/// Compiles an Octo source file.
+pub fn compile_file(path: &Path) -> Result<Vec<u8>, AssembleError> {
+    let file = path.display().to_string();
+    let source = std::fs::read_to_string(path).map_err(|e| AssembleError {
+        file: file.clone(),
+        line: 0,
+        column: 0,
+        message: e.to_string(),
+    })?;
+    Compiler::new(&file, &source).run()
+}
+
+struct Compiler {
+    file: String,
+    tokens: Vec<Token>,
+    pos: usize,
+    rom: Vec<u8>,
+    here: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, (Vec<String>, Vec<Token>)>,
+    fixups: Vec<(usize, Fixup, Token)>,
+    flow: Vec<(Flow, Token)>,
+    expansions: usize,
+}
+
+impl Compiler {
+    fn new(file: &str, source: &str) -> Self {
+        Self {
+            file: file.to_string(),
+            tokens: tokenize(source),
+            pos: 0,
+            rom: Vec::new(),
+            here: START,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            flow: Vec::new(),
+            expansions: 0,
+        }
+    }
+
+    fn run(mut self) -> Result<Vec<u8>, AssembleError> {
+        while self.pos < self.tokens.len() {
+            self.statement()?;
+        }
+        if let Some((_, token)) = self.flow.last() {
+            return Err(self.error(token, &format!("{} is never closed", token.text)));
+        }
+        for (addr, fixup, token) in std::mem::take(&mut self.fixups) {
+            let Some(&target) = self.labels.get(&token.text) else {
+                return Err(self.error(&token, &format!("undefined label {}", token.text)));
+            };
+            let i = addr - START;
+            match fixup {
+                Fixup::Nnn => {
+                    if target > 0xFFF {
+                        return Err(self.error(&token, "address out of range, use i := long"));
+                    }
+                    self.rom[i] = (self.rom[i] & 0xF0) | (target >> 8) as u8;
+                    self.rom[i + 1] = target as u8;
+                }
+                Fixup::Long => {
+                    self.rom[i] = (target >> 8) as u8;
+                    self.rom[i + 1] = target as u8;
+                }
+                Fixup::UnpackHigh(Some(nibble)) => {
+                    self.rom[i] = (nibble << 4) | ((target >> 8) as u8 & 0xF);
+                }
+                Fixup::UnpackHigh(None) => self.rom[i] = (target >> 8) as u8,
+                Fixup::UnpackLow => self.rom[i] = target as u8,
+            }
+        }
+        Ok(self.rom)
+    }
+
+    fn error(&self, token: &Token, message: &str) -> AssembleError {
+        AssembleError {
+            file: self.file.clone(),
+            line: token.line,
+            column: token.column,
+            message: message.to_string(),
+        }
+    }
+
+    /// Takes the next token, failing at the end of the source.
+    fn next(&mut self) -> Result<Token, AssembleError> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.clone())
+            }
+            None => {
+                let last = self.tokens.last().cloned().unwrap_or(Token {
+                    text: String::new(),
+                    line: 1,
+                    column: 1,
+                });
+                Err(self.error(&last, "unexpected end of source"))
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|t| t.text.as_str())
+    }
+
+    /// Takes the next token, which must be the given text.
+    fn expect(&mut self, text: &str) -> Result<Token, AssembleError> {
+        let token = self.next()?;
+        if token.text != text {
+            return Err(self.error(&token, &format!("expected {text}")));
+        }
+        Ok(token)
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            let i = self.here - START;
+            if self.rom.len() <= i {
+                self.rom.resize(i + 1, 0);
+            }
+            self.rom[i] = *b;
+            self.here += 1;
+        }
+    }
+
+    fn emit_op(&mut self, op: OpCode) {
+        self.emit(&op.to_bytes());
+    }
+
+    /// Defines a label at the address.
+    fn define_label(&mut self, token: &Token, addr: usize) -> Result<(), AssembleError> {
+        if self.labels.contains_key(&token.text) {
+            return Err(self.error(token, &format!("{} is already defined", token.text)));
+        }
+        if !is_name(&token.text) || self.register(token).is_some() {
+            return Err(self.error(token, &format!("invalid name {}", token.text)));
+        }
+        self.labels.insert(token.text.clone(), addr);
+        Ok(())
+    }
+
+    /// Returns the register number of a register name or alias.
+    fn register(&self, token: &Token) -> Option<u8> {
+        if let Some(x) = self.aliases.get(&token.text) {
+            return Some(*x);
+        }
+        let digit = token.text.strip_prefix(['v', 'V'])?;
+        if digit.len() == 1 {
+            u8::from_str_radix(digit, 16).ok()
+        } else {
+            None
+        }
+    }
+
+    fn expect_register(&mut self) -> Result<u8, AssembleError> {
+        let token = self.next()?;
+        self.register(&token).ok_or_else(|| {
+            self.error(
+                &token,
+                &format!("expected a register, found {}", token.text),
+            )
+        })
+    }
+
+    /// Evaluates a number, constant, defined label or braced expression.
+    fn value(&mut self, token: &Token) -> Result<f64, AssembleError> {
+        if token.text == "{" {
+            return self.calc(token);
+        }
+        if let Some(n) = parse_number(&token.text) {
+            return Ok(n as f64);
+        }
+        if let Some(v) = self.constants.get(&token.text) {
+            return Ok(*v);
+        }
+        if let Some(addr) = self.labels.get(&token.text) {
+            return Ok(*addr as f64);
+        }
+        Err(self.error(token, &format!("undefined name {}", token.text)))
+    }
+
+    /// Evaluates the next token as an integer within the range.
+    fn int_value(&mut self, min: i64, max: i64) -> Result<i64, AssembleError> {
+        let token = self.next()?;
+        let value = self.value(&token)? as i64;
+        if value < min || value > max {
+            return Err(self.error(&token, &format!("value {value} out of range")));
+        }
+        Ok(value)
+    }
+
+    fn byte(&mut self) -> Result<u8, AssembleError> {
+        Ok(self.int_value(-128, 255)? as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u8, AssembleError> {
+        Ok(self.int_value(0, 15)? as u8)
+    }
+
+    /// Evaluates the next token as an address, which may refer to a label defined later.
+    /// The fixup is recorded for the instruction at the current address.
+    fn address(&mut self, fixup: Fixup, max: usize) -> Result<u16, AssembleError> {
+        let token = self.next()?;
+        let known = token.text == "{"
+            || parse_number(&token.text).is_some()
+            || self.constants.contains_key(&token.text)
+            || self.labels.contains_key(&token.text);
+        if known {
+            let value = self.value(&token)? as i64;
+            if value < 0 || value > max as i64 {
+                return Err(self.error(&token, &format!("address {value} out of range")));
+            }
+            return Ok(value as u16);
+        }
+        if !is_name(&token.text) {
+            return Err(self.error(
+                &token,
+                &format!("expected an address, found {}", token.text),
+            ));
+        }
+        let at = match fixup {
+            Fixup::Long => self.here + 2,
+            _ => self.here,
+        };
+        self.fixups.push((at, fixup, token));
+        Ok(0)
+    }
+
+    /// Compiles one statement.
+    fn statement(&mut self) -> Result<(), AssembleError> {
+        let token = self.next()?;
+        match token.text.as_str() {
+            ":" => {
+                let name = self.next()?;
+                self.define_label(&name, self.here)?;
+            }
+            ":next" => {
+                let name = self.next()?;
+                self.define_label(&name, self.here + 1)?;
+            }
+            ":alias" => {
+                let name = self.next()?;
+                let register_token = self.next()?;
+                let register = match self.register(&register_token) {
+                    Some(x) => x,
+                    None => {
+                        let value = self.value(&register_token)? as i64;
+                        if !(0..16).contains(&value) {
+                            return Err(self.error(&register_token, "expected a register"));
+                        }
+                        value as u8
+                    }
+                };
+                self.aliases.insert(name.text, register);
+            }
+            ":const" => {
+                let name = self.next()?;
+                let value_token = self.next()?;
+                let value = self.value(&value_token)?;
+                self.define_constant(&name, value)?;
+            }
+            ":calc" => {
+                let name = self.next()?;
+                let open = self.expect("{")?;
+                let value = self.calc(&open)?;
+                self.constants.insert(name.text, value);
+            }
+            ":macro" => {
+                let name = self.next()?;
+                let mut args = Vec::new();
+                loop {
+                    let arg = self.next()?;
+                    if arg.text == "{" {
+                        break;
+                    }
+                    args.push(arg.text);
+                }
+                let mut body = Vec::new();
+                let mut depth = 0;
+                loop {
+                    let t = self.next()?;
+                    match t.text.as_str() {
+                        "{" => depth += 1,
+                        "}" if depth == 0 => break,
+                        "}" => depth -= 1,
+                        _ => (),
+                    }
+                    body.push(t);
+                }
+                self.macros.insert(name.text, (args, body));
+            }
+            ":org" => {
+                let addr_token = self.next()?;
+                let addr = self.value(&addr_token)? as i64;
+                if addr < START as i64 || addr > 0xFFFF {
+                    return Err(self.error(&addr_token, "address out of range"));
+                }
+                self.here = addr as usize;
+            }
+            ":byte" => {
+                let b = self.byte()?;
+                self.emit(&[b]);
+            }
+            ":unpack" => {
+                let nibble = if self.peek() == Some("long") {
+                    self.next()?;
+                    None
+                } else {
+                    Some(self.nibble()?)
+                };
+                let here = self.here;
+                self.here += 1;
+                let addr = self.address(Fixup::UnpackHigh(nibble), 0xFFFF)? as usize;
+                self.here = here;
+                let high = match nibble {
+                    Some(n) => (n << 4) | ((addr >> 8) as u8 & 0xF),
+                    None => (addr >> 8) as u8,
+                };
+                self.emit_op(OpCode::Set { vx: 0, val: high });
+                // Record the low byte fixup too, if the label wasn't known
+                if let Some((_, _, label)) = self.fixups.last().filter(|(a, _, _)| *a == here + 1) {
+                    let label = label.clone();
+                    self.fixups.push((self.here + 1, Fixup::UnpackLow, label));
+                }
+                self.emit_op(OpCode::Set {
+                    vx: 1,
+                    val: addr as u8,
+                });
+            }
+            ":call" => {
+                let addr = self.address(Fixup::Nnn, 0xFFF)?;
+                self.emit_op(OpCode::Call(addr));
+            }
+            ":breakpoint" | ":proto" => {
+                self.next()?;
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            }
+            ";" | "return" => self.emit_op(OpCode::Return),
+            "clear" => self.emit_op(OpCode::ClearScreen),
+            "exit" => self.emit_op(OpCode::Exit),
+            "lores" => self.emit_op(OpCode::LowRes),
+            "hires" => self.emit_op(OpCode::HighRes),
+            "scroll-left" => self.emit_op(OpCode::ScrollLeft),
+            "scroll-right" => self.emit_op(OpCode::ScrollRight),
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit_op(OpCode::ScrollDown(n));
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit_op(OpCode::ScrollUp(n));
+            }
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit_op(OpCode::SelectPlanes(n));
+            }
+            "audio" => self.emit_op(OpCode::LoadAudioPattern),
+            "bcd" => {
+                let vx = self.expect_register()?;
+                self.emit_op(OpCode::BinaryCodedDecimalConversion { vx });
+            }
+            "save" | "load" => {
+                let vx = self.expect_register()?;
+                let store = token.text == "save";
+                let op = if self.peek() == Some("-") {
+                    self.next()?;
+                    let vy = self.expect_register()?;
+                    if store {
+                        OpCode::StoreRange { vx, vy }
+                    } else {
+                        OpCode::LoadRange { vx, vy }
+                    }
+                } else if store {
+                    OpCode::StoreMemory { vx }
+                } else {
+                    OpCode::LoadMemory { vx }
+                };
+                self.emit_op(op);
+            }
+            "saveflags" => {
+                let vx = self.expect_register()?;
+                self.emit_op(OpCode::StoreFlags { vx });
+            }
+            "loadflags" => {
+                let vx = self.expect_register()?;
+                self.emit_op(OpCode::LoadFlags { vx });
+            }
+            "sprite" => {
+                let vx = self.expect_register()?;
+                let vy = self.expect_register()?;
+                let val = self.nibble()?;
+                self.emit_op(OpCode::Display { vx, vy, val });
+            }
+            "jump" => {
+                let addr = self.address(Fixup::Nnn, 0xFFF)?;
+                self.emit_op(OpCode::Jump(addr));
+            }
+            "jump0" => {
+                let val = self.address(Fixup::Nnn, 0xFFF)?;
+                self.emit_op(OpCode::JumpWithOffset {
+                    vx: (val >> 8) as u8,
+                    val,
+                });
+            }
+            "native" => {
+                let addr = self.address(Fixup::Nnn, 0xFFF)?;
+                self.emit(&addr.to_be_bytes());
+            }
+            "pitch" | "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let vx = self.expect_register()?;
+                self.emit_op(match token.text.as_str() {
+                    "pitch" => OpCode::SetPitchToVx { vx },
+                    "delay" => OpCode::SetDelayTimerToVx { vx },
+                    _ => OpCode::SetSoundTimerToVx { vx },
+                });
+            }
+            "i" => self.index_statement()?,
+            "if" => {
+                let (prefix, skip) = self.condition()?;
+                let keyword = self.next()?;
+                for op in prefix {
+                    self.emit_op(op);
+                }
+                match keyword.text.as_str() {
+                    "then" => self.emit_op(skip),
+                    "begin" => {
+                        self.emit_op(negate(skip));
+                        self.flow.push((Flow::If(self.here), token));
+                        self.emit_op(OpCode::Jump(0));
+                    }
+                    _ => return Err(self.error(&keyword, "expected then or begin")),
+                }
+            }
+            "else" => match self.flow.pop() {
+                Some((Flow::If(jump), if_token)) => {
+                    let else_jump = self.here;
+                    self.emit_op(OpCode::Jump(0));
+                    self.patch_jump(jump);
+                    self.flow.push((Flow::Else(else_jump), if_token));
+                }
+                _ => return Err(self.error(&token, "else without if ... begin")),
+            },
+            "end" => match self.flow.pop() {
+                Some((Flow::If(jump) | Flow::Else(jump), _)) => self.patch_jump(jump),
+                _ => return Err(self.error(&token, "end without if ... begin")),
+            },
+            "loop" => self.flow.push((Flow::Loop(self.here, Vec::new()), token)),
+            "while" => {
+                let (prefix, skip) = self.condition()?;
+                for op in prefix {
+                    self.emit_op(op);
+                }
+                self.emit_op(negate(skip));
+                let jump = self.here;
+                self.emit_op(OpCode::Jump(0));
+                match self
+                    .flow
+                    .iter_mut()
+                    .rev()
+                    .find(|(f, _)| matches!(f, Flow::Loop(..)))
+                {
+                    Some((Flow::Loop(_, whiles), _)) => whiles.push(jump),
+                    _ => return Err(self.error(&token, "while outside of loop")),
+                }
+            }
+            "again" => match self.flow.pop() {
+                Some((Flow::Loop(start, whiles), _)) => {
+                    self.emit_op(OpCode::Jump(start as u16));
+                    for jump in whiles {
+                        self.patch_jump(jump);
+                    }
+                }
+                _ => return Err(self.error(&token, "again without loop")),
+            },
+            _ => {
+                if let Some(vx) = self.register(&token) {
+                    return self.register_statement(vx);
+                }
+                if let Some((args, body)) = self.macros.get(&token.text).cloned() {
+                    return self.expand_macro(&token, &args, &body);
+                }
+                if token.text == "{"
+                    || parse_number(&token.text).is_some()
+                    || self.constants.contains_key(&token.text)
+                {
+                    let value = self.value(&token)? as i64;
+                    if !(-128..=255).contains(&value) {
+                        return Err(self.error(&token, &format!("value {value} out of range")));
+                    }
+                    self.emit(&[value as u8]);
+                    return Ok(());
+                }
+                if !is_name(&token.text) {
+                    return Err(self.error(&token, &format!("unexpected {}", token.text)));
+                }
+                // A bare label name calls the subroutine
+                self.pos -= 1;
+                let addr = self.address(Fixup::Nnn, 0xFFF)?;
+                self.emit_op(OpCode::Call(addr));
+            }
+        }
+        Ok(())
+    }
+
+    fn define_constant(&mut self, name: &Token, value: f64) -> Result<(), AssembleError> {
+        if !is_name(&name.text) {
+            return Err(self.error(name, &format!("invalid name {}", name.text)));
+        }
+        self.constants.insert(name.text.clone(), value);
+        Ok(())
+    }
+
+    /// Points the jump instruction at the address to the current address.
+    fn patch_jump(&mut self, addr: usize) {
+        let bytes = OpCode::Jump(self.here as u16).to_bytes();
+        self.rom[addr - START..addr - START + 2].copy_from_slice(&bytes);
+    }
+
+    /// Compiles the statements starting with the index register.
+    fn index_statement(&mut self) -> Result<(), AssembleError> {
+        let op_token = self.next()?;
+        match op_token.text.as_str() {
+            ":=" => match self.peek() {
+                Some("long") => {
+                    self.next()?;
+                    let addr = self.address(Fixup::Long, 0xFFFF)?;
+                    self.emit_op(OpCode::SetIndexLong(addr));
+                }
+                Some("hex") => {
+                    self.next()?;
+                    let vx = self.expect_register()?;
+                    self.emit_op(OpCode::FontCharacter { vx });
+                }
+                Some("bighex") => {
+                    self.next()?;
+                    let vx = self.expect_register()?;
+                    self.emit_op(OpCode::BigFontCharacter { vx });
+                }
+                _ => {
+                    let addr = self.address(Fixup::Nnn, 0xFFF)?;
+                    self.emit_op(OpCode::SetIndex(addr));
+                }
+            },
+            "+=" => {
+                let vx = self.expect_register()?;
+                self.emit_op(OpCode::AddToIndex { vx });
+            }
+            _ => return Err(self.error(&op_token, "expected := or +=")),
+        }
+        Ok(())
+    }
+
+    /// Compiles the statements starting with a register.
+    fn register_statement(&mut self, vx: u8) -> Result<(), AssembleError> {
+        let op_token = self.next()?;
+        let rhs = self.next()?;
+        let vy = self.register(&rhs);
+        let op = match (op_token.text.as_str(), vy) {
+            (":=", Some(vy)) => OpCode::SetVxToVy { vx, vy },
+            (":=", None) => match rhs.text.as_str() {
+                "random" => OpCode::Random {
+                    vx,
+                    val: self.byte()?,
+                },
+                "key" => OpCode::GetKey { vx },
+                "delay" => OpCode::SetVxToDelayTimer { vx },
+                _ => {
+                    self.pos -= 1;
+                    OpCode::Set {
+                        vx,
+                        val: self.byte()?,
+                    }
+                }
+            },
+            ("+=", Some(vy)) => OpCode::AddVyToVx { vx, vy },
+            ("+=", None) => {
+                self.pos -= 1;
+                OpCode::Add {
+                    vx,
+                    val: self.byte()?,
+                }
+            }
+            ("-=", Some(vy)) => OpCode::SubVxVyToVx { vx, vy },
+            ("-=", None) => {
+                self.pos -= 1;
+                OpCode::Add {
+                    vx,
+                    val: self.byte()?.wrapping_neg(),
+                }
+            }
+            ("=-", Some(vy)) => OpCode::SubVyVxToVx { vx, vy },
+            ("|=", Some(vy)) => OpCode::BinaryOr { vx, vy },
+            ("&=", Some(vy)) => OpCode::BinaryAnd { vx, vy },
+            ("^=", Some(vy)) => OpCode::LogicalXor { vx, vy },
+            (">>=", Some(vy)) => OpCode::Shift {
+                vx,
+                vy,
+                left_shift: false,
+            },
+            ("<<=", Some(vy)) => OpCode::Shift {
+                vx,
+                vy,
+                left_shift: true,
+            },
+            ("=-" | "|=" | "&=" | "^=" | ">>=" | "<<=", None) => {
+                return Err(self.error(&rhs, "expected a register"));
+            }
+            _ => return Err(self.error(&op_token, &format!("unexpected {}", op_token.text))),
+        };
+        self.emit_op(op);
+        Ok(())
+    }
+
+    /// Parses a condition. Returns the instructions computing it, and the instruction skipping
+    /// the next one if the condition is false.
+    fn condition(&mut self) -> Result<(Vec<OpCode>, OpCode), AssembleError> {
+        let vx = self.expect_register()?;
+        let cmp = self.next()?;
+        match cmp.text.as_str() {
+            "key" => return Ok((Vec::new(), OpCode::SkipIfKeyNotPressed { vx })),
+            "-key" => return Ok((Vec::new(), OpCode::SkipIfKeyPressed { vx })),
+            _ => (),
+        }
+        let rhs_token = self.next()?;
+        let rhs = match self.register(&rhs_token) {
+            Some(vy) => Operand::Register(vy),
+            None => {
+                self.pos -= 1;
+                Operand::Byte(self.byte()?)
+            }
+        };
+        // Sets VF to 1 if the left hand side is greater than or equal to the right hand side,
+        // or the other way around if swapped
+        let vf = 0xF;
+        let flag = |swapped: bool| match (rhs, swapped) {
+            (Operand::Register(vy), false) => vec![
+                OpCode::SetVxToVy { vx: vf, vy: vx },
+                OpCode::SubVxVyToVx { vx: vf, vy },
+            ],
+            (Operand::Byte(val), false) => vec![
+                OpCode::Set { vx: vf, val },
+                OpCode::SubVyVxToVx { vx: vf, vy: vx },
+            ],
+            (Operand::Register(vy), true) => vec![
+                OpCode::SetVxToVy { vx: vf, vy },
+                OpCode::SubVxVyToVx { vx: vf, vy: vx },
+            ],
+            (Operand::Byte(val), true) => vec![
+                OpCode::Set { vx: vf, val },
+                OpCode::SubVxVyToVx { vx: vf, vy: vx },
+            ],
+        };
+        let skip_if_vf = |val| OpCode::SkipIfVxEq { vx: vf, val };
+        Ok(match (cmp.text.as_str(), rhs) {
+            ("==", Operand::Register(vy)) => (Vec::new(), OpCode::SkipIfVxNeqVy { vx, vy }),
+            ("==", Operand::Byte(val)) => (Vec::new(), OpCode::SkipIfVxNeq { vx, val }),
+            ("!=", Operand::Register(vy)) => (Vec::new(), OpCode::SkipIfVxEqVy { vx, vy }),
+            ("!=", Operand::Byte(val)) => (Vec::new(), OpCode::SkipIfVxEq { vx, val }),
+            ("<", _) => (flag(false), skip_if_vf(1)),
+            (">=", _) => (flag(false), skip_if_vf(0)),
+            (">", _) => (flag(true), skip_if_vf(1)),
+            ("<=", _) => (flag(true), skip_if_vf(0)),
+            _ => return Err(self.error(&cmp, &format!("unknown comparison {}", cmp.text))),
+        })
+    }
+
+    /// Expands a macro invocation in place, substituting the arguments.
+    fn expand_macro(
+        &mut self,
+        token: &Token,
+        args: &[String],
+        body: &[Token],
+    ) -> Result<(), AssembleError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(self.error(token, "too many macro expansions, is the macro recursive?"));
+        }
+        let mut values = HashMap::new();
+        for arg in args {
+            values.insert(arg.clone(), self.next()?);
+        }
+        let expanded: Vec<Token> = body
+            .iter()
+            .map(|t| values.get(&t.text).cloned().unwrap_or_else(|| t.clone()))
+            .collect();
+        self.tokens.splice(self.pos..self.pos, expanded);
+        Ok(())
+    }
+
+    /// Evaluates a :calc expression, after the opening brace. The expression is evaluated from
+    /// right to left without operator precedence, as in Octo.
+    fn calc(&mut self, open: &Token) -> Result<f64, AssembleError> {
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token.text == "}" {
+                break;
+            }
+            tokens.push(token);
+        }
+        if tokens.is_empty() {
+            return Err(self.error(open, "empty expression"));
+        }
+        let (value, rest) = self.calc_expr(&tokens)?;
+        if let Some(t) = rest.first() {
+            return Err(self.error(t, &format!("unexpected {}", t.text)));
+        }
+        Ok(value)
+    }
+
+    /// Evaluates a term, optionally followed by a binary operator and the rest of the expression.
+    fn calc_expr<'a>(&self, tokens: &'a [Token]) -> Result<(f64, &'a [Token]), AssembleError> {
+        let (lhs, rest) = self.calc_term(tokens)?;
+        let Some(op) = rest.first() else {
+            return Ok((lhs, rest));
+        };
+        if op.text == ")" {
+            return Ok((lhs, rest));
+        }
+        let (rhs, rest) = self.calc_expr(&rest[1..])?;
+        let (a, b) = (lhs, rhs);
+        let value = match op.text.as_str() {
+            "+" => a + b,
+            "-" => a - b,
+            "*" => a * b,
+            "/" => a / b,
+            "%" => a % b,
+            "&" => ((a as i64) & (b as i64)) as f64,
+            "|" => ((a as i64) | (b as i64)) as f64,
+            "^" => ((a as i64) ^ (b as i64)) as f64,
+            "<<" | ">>" => {
+                let shifted = u32::try_from(b as i64).ok().and_then(|b| {
+                    if op.text == "<<" {
+                        (a as i64).checked_shl(b)
+                    } else {
+                        (a as i64).checked_shr(b)
+                    }
+                });
+                match shifted {
+                    Some(v) => v as f64,
+                    None => return Err(self.error(op, &format!("can't shift by {b}"))),
+                }
+            }
+            "pow" => a.powf(b),
+            "min" => a.min(b),
+            "max" => a.max(b),
+            "<" => (a < b) as i64 as f64,
+            ">" => (a > b) as i64 as f64,
+            "<=" => (a <= b) as i64 as f64,
+            ">=" => (a >= b) as i64 as f64,
+            "==" => (a == b) as i64 as f64,
+            "!=" => (a != b) as i64 as f64,
+            _ => return Err(self.error(op, &format!("unknown operator {}", op.text))),
+        };
+        Ok((value, rest))
+    }
+
+    /// Evaluates a number, name, unary operation or parenthesized expression.
+    fn calc_term<'a>(&self, tokens: &'a [Token]) -> Result<(f64, &'a [Token]), AssembleError> {
+        let Some((token, rest)) = tokens.split_first() else {
+            let last = tokens.last().unwrap_or(&self.tokens[self.pos - 1]);
+            return Err(self.error(last, "expected a value"));
+        };
+        let unary: Option<fn(f64) -> f64> = match token.text.as_str() {
+            "-" => Some(|v| -v),
+            "~" => Some(|v| !(v as i64) as f64),
+            "!" => Some(|v| (v == 0.0) as i64 as f64),
+            "sin" => Some(f64::sin),
+            "cos" => Some(f64::cos),
+            "tan" => Some(f64::tan),
+            "exp" => Some(f64::exp),
+            "log" => Some(f64::ln),
+            "abs" => Some(f64::abs),
+            "sqrt" => Some(f64::sqrt),
+            "sign" => Some(f64::signum),
+            "ceil" => Some(f64::ceil),
+            "floor" => Some(f64::floor),
+            _ => None,
+        };
+        if let Some(f) = unary {
+            let (value, rest) = self.calc_term(rest)?;
+            return Ok((f(value), rest));
+        }
+        if token.text == "(" {
+            let (value, rest) = self.calc_expr(rest)?;
+            return match rest.split_first() {
+                Some((close, rest)) if close.text == ")" => Ok((value, rest)),
+                _ => Err(self.error(token, "unclosed parenthesis")),
+            };
+        }
+        let value = match token.text.as_str() {
+            "HERE" => self.here as f64,
+            "PI" => std::f64::consts::PI,
+            "E" => std::f64::consts::E,
+            text => match parse_number(text) {
+                Some(n) => n as f64,
+                None => match (self.constants.get(text), self.labels.get(text)) {
+                    (Some(v), _) => *v,
+                    (None, Some(addr)) => *addr as f64,
+                    (None, None) => {
+                        return Err(self.error(token, &format!("undefined name {text}")));
+                    }
+                },
+            },
+        };
+        Ok((value, rest))
+    }
+}
+
+/// Returns the inverse of a skip instruction.
+fn negate(skip: OpCode) -> OpCode {
+    match skip {
+        OpCode::SkipIfVxEq { vx, val } => OpCode::SkipIfVxNeq { vx, val },
+        OpCode::SkipIfVxNeq { vx, val } => OpCode::SkipIfVxEq { vx, val },
+        OpCode::SkipIfVxEqVy { vx, vy } => OpCode::SkipIfVxNeqVy { vx, vy },
+        OpCode::SkipIfVxNeqVy { vx, vy } => OpCode::SkipIfVxEqVy { vx, vy },
+        OpCode::SkipIfKeyPressed { vx } => OpCode::SkipIfKeyNotPressed { vx },
+        OpCode::SkipIfKeyNotPressed { vx } => OpCode::SkipIfKeyPressed { vx },
+        op => op,
+    }
+}
+
+/// Returns true if the text can be used as a label or constant name.
+fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Parses a decimal, hexadecimal (0x) or binary (0b) number, optionally negative.
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(d) => (true, d),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b").or(digits.strip_prefix("0B")) {
+        i64::from_str_radix(bin, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// Splits the source into whitespace separated tokens, skipping comments.
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (line_nbr, line) in source.lines().enumerate() {
+        for word in line.split_whitespace() {
+            if word.starts_with('#') {
+                break;
+            }
+            let offset = word.as_ptr() as usize - line.as_ptr() as usize;
+            tokens.push(Token {
+                text: word.to_string(),
+                line: line_nbr + 1,
+                column: line[..offset].chars().count() + 1,
+            });
+        }
+    }
+    tokens
+}
+
+#[path = "unittest/test_octo.rs"]
+#[cfg(test)]
+mod test_octo;
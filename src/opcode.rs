@@ -1,14 +1,33 @@
 //! # Op code representation
 //!
-//! Contains all op codes for CHIP-8 as an enum, and a decoding function.
+//! Contains all op codes for CHIP-8, SUPER-CHIP 1.1 and XO-CHIP as an enum, and a decoding function.
+//! Op codes are displayed as assembly mnemonics in the Cowgod syntax, extended with the SUPER-CHIP
+//! and XO-CHIP instructions, e.g. `LD V0, 0x05` and `DRW V0, V1, 5`.
+//! Bytes and addresses are written as hexadecimal, and sprite heights, scroll distances and
+//! plane masks as decimal.
+use std::fmt;
 
 /// Enum to represent all possible CHIP-8 OP codes
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum OpCode {
     /// 00E0
     ClearScreen,
     /// 00EE
     Return,
+    /// 00CN (SUPER-CHIP)
+    ScrollDown(u8),
+    /// 00DN (XO-CHIP)
+    ScrollUp(u8),
+    /// 00FB (SUPER-CHIP)
+    ScrollRight,
+    /// 00FC (SUPER-CHIP)
+    ScrollLeft,
+    /// 00FD (SUPER-CHIP)
+    Exit,
+    /// 00FE (SUPER-CHIP)
+    LowRes,
+    /// 00FF (SUPER-CHIP)
+    HighRes,
     /// 1NNN
     Jump(u16),
     /// 2NNN
@@ -25,6 +44,10 @@ pub enum OpCode {
     SkipIfVxEqVy { vx: u8, vy: u8 },
     /// 9XY0
     SkipIfVxNeqVy { vx: u8, vy: u8 },
+    /// 5XY2 (XO-CHIP)
+    StoreRange { vx: u8, vy: u8 },
+    /// 5XY3 (XO-CHIP)
+    LoadRange { vx: u8, vy: u8 },
     /// 8XY0
     SetVxToVy { vx: u8, vy: u8 },
     /// 8XY1
@@ -43,16 +66,24 @@ pub enum OpCode {
     Shift { vx: u8, vy: u8, left_shift: bool },
     /// ANNN
     SetIndex(u16),
+    /// F000 NNNN (XO-CHIP, note that this is the only four byte long op code)
+    SetIndexLong(u16),
     /// BNNN (Note: val contains all three digits in NNN, vx is equal to the first N and only used if quirk is activated)
     JumpWithOffset { vx: u8, val: u16 },
     /// CXNN
     Random { vx: u8, val: u8 },
-    /// DXYN
+    /// DXYN (DXY0 draws a 16x16 sprite on SUPER-CHIP)
     Display { vx: u8, vy: u8, val: u8 },
     /// EX9E
     SkipIfKeyPressed { vx: u8 },
     /// EXA1
     SkipIfKeyNotPressed { vx: u8 },
+    /// FN01 (XO-CHIP, N is the bit mask of the selected planes)
+    SelectPlanes(u8),
+    /// F002 (XO-CHIP)
+    LoadAudioPattern,
+    /// FX3A (XO-CHIP)
+    SetPitchToVx { vx: u8 },
     /// FX07
     SetVxToDelayTimer { vx: u8 },
     /// FX15
@@ -65,17 +96,166 @@ pub enum OpCode {
     GetKey { vx: u8 },
     /// FX29
     FontCharacter { vx: u8 },
+    /// FX30 (SUPER-CHIP)
+    BigFontCharacter { vx: u8 },
     /// FX33
     BinaryCodedDecimalConversion { vx: u8 },
     /// FX55
     StoreMemory { vx: u8 },
     /// FX65
     LoadMemory { vx: u8 },
+    /// FX75 (SUPER-CHIP)
+    StoreFlags { vx: u8 },
+    /// FX85 (SUPER-CHIP)
+    LoadFlags { vx: u8 },
 }
 
 impl OpCode {
+    /// Returns the number of bytes the op code occupies in memory.
+    pub fn size(&self) -> u16 {
+        match self {
+            Self::SetIndexLong(_) => 4,
+            _ => 2,
+        }
+    }
+
+    /// Returns the address operand of the op code, for the op codes that have one.
+    pub fn target(&self) -> Option<u16> {
+        match *self {
+            Self::Jump(addr)
+            | Self::Call(addr)
+            | Self::SetIndex(addr)
+            | Self::SetIndexLong(addr)
+            | Self::JumpWithOffset { val: addr, .. } => Some(addr),
+            _ => None,
+        }
+    }
+
+    /// Returns the mnemonic of the op code, with the address operand replaced by the given target,
+    /// e.g. a label.
+    pub fn to_string_with_target(&self, target: &str) -> String {
+        match *self {
+            Self::ClearScreen => "CLS".to_string(),
+            Self::Return => "RET".to_string(),
+            Self::ScrollDown(n) => format!("SCD {n}"),
+            Self::ScrollUp(n) => format!("SCU {n}"),
+            Self::ScrollRight => "SCR".to_string(),
+            Self::ScrollLeft => "SCL".to_string(),
+            Self::Exit => "EXIT".to_string(),
+            Self::LowRes => "LOW".to_string(),
+            Self::HighRes => "HIGH".to_string(),
+            Self::Jump(_) => format!("JP {target}"),
+            Self::Call(_) => format!("CALL {target}"),
+            Self::Set { vx, val } => format!("LD V{vx:X}, 0x{val:02X}"),
+            Self::Add { vx, val } => format!("ADD V{vx:X}, 0x{val:02X}"),
+            Self::SkipIfVxEq { vx, val } => format!("SE V{vx:X}, 0x{val:02X}"),
+            Self::SkipIfVxNeq { vx, val } => format!("SNE V{vx:X}, 0x{val:02X}"),
+            Self::SkipIfVxEqVy { vx, vy } => format!("SE V{vx:X}, V{vy:X}"),
+            Self::SkipIfVxNeqVy { vx, vy } => format!("SNE V{vx:X}, V{vy:X}"),
+            Self::StoreRange { vx, vy } => format!("SAVE V{vx:X}, V{vy:X}"),
+            Self::LoadRange { vx, vy } => format!("LOAD V{vx:X}, V{vy:X}"),
+            Self::SetVxToVy { vx, vy } => format!("LD V{vx:X}, V{vy:X}"),
+            Self::BinaryOr { vx, vy } => format!("OR V{vx:X}, V{vy:X}"),
+            Self::BinaryAnd { vx, vy } => format!("AND V{vx:X}, V{vy:X}"),
+            Self::LogicalXor { vx, vy } => format!("XOR V{vx:X}, V{vy:X}"),
+            Self::AddVyToVx { vx, vy } => format!("ADD V{vx:X}, V{vy:X}"),
+            Self::SubVxVyToVx { vx, vy } => format!("SUB V{vx:X}, V{vy:X}"),
+            Self::SubVyVxToVx { vx, vy } => format!("SUBN V{vx:X}, V{vy:X}"),
+            Self::Shift { vx, vy, left_shift } => {
+                let mnemonic = if left_shift { "SHL" } else { "SHR" };
+                format!("{mnemonic} V{vx:X}, V{vy:X}")
+            }
+            Self::SetIndex(_) => format!("LD I, {target}"),
+            Self::SetIndexLong(_) => format!("LD I, LONG {target}"),
+            Self::JumpWithOffset { .. } => format!("JP V0, {target}"),
+            Self::Random { vx, val } => format!("RND V{vx:X}, 0x{val:02X}"),
+            Self::Display { vx, vy, val } => format!("DRW V{vx:X}, V{vy:X}, {val}"),
+            Self::SkipIfKeyPressed { vx } => format!("SKP V{vx:X}"),
+            Self::SkipIfKeyNotPressed { vx } => format!("SKNP V{vx:X}"),
+            Self::SelectPlanes(n) => format!("PLANE {n}"),
+            Self::LoadAudioPattern => "AUDIO".to_string(),
+            Self::SetPitchToVx { vx } => format!("PITCH V{vx:X}"),
+            Self::SetVxToDelayTimer { vx } => format!("LD V{vx:X}, DT"),
+            Self::SetDelayTimerToVx { vx } => format!("LD DT, V{vx:X}"),
+            Self::SetSoundTimerToVx { vx } => format!("LD ST, V{vx:X}"),
+            Self::AddToIndex { vx } => format!("ADD I, V{vx:X}"),
+            Self::GetKey { vx } => format!("LD V{vx:X}, K"),
+            Self::FontCharacter { vx } => format!("LD F, V{vx:X}"),
+            Self::BigFontCharacter { vx } => format!("LD HF, V{vx:X}"),
+            Self::BinaryCodedDecimalConversion { vx } => format!("LD B, V{vx:X}"),
+            Self::StoreMemory { vx } => format!("LD [I], V{vx:X}"),
+            Self::LoadMemory { vx } => format!("LD V{vx:X}, [I]"),
+            Self::StoreFlags { vx } => format!("LD R, V{vx:X}"),
+            Self::LoadFlags { vx } => format!("LD V{vx:X}, R"),
+        }
+    }
+
+    /// Encodes the op code to its bytes, i.e. the inverse of from_bytes.
+    /// Operands are masked to the width of their field.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let xy = |op: u16, vx: u8, vy: u8, n: u16| {
+            op | ((vx as u16 & 0xF) << 8) | ((vy as u16 & 0xF) << 4) | (n & 0xF)
+        };
+        let xnn = |op: u16, vx: u8, nn: u8| op | ((vx as u16 & 0xF) << 8) | nn as u16;
+        let nnn = |op: u16, addr: u16| op | (addr & 0xFFF);
+        let word = match *self {
+            Self::ClearScreen => 0x00E0,
+            Self::Return => 0x00EE,
+            Self::ScrollDown(n) => 0x00C0 | (n as u16 & 0xF),
+            Self::ScrollUp(n) => 0x00D0 | (n as u16 & 0xF),
+            Self::ScrollRight => 0x00FB,
+            Self::ScrollLeft => 0x00FC,
+            Self::Exit => 0x00FD,
+            Self::LowRes => 0x00FE,
+            Self::HighRes => 0x00FF,
+            Self::Jump(addr) => nnn(0x1000, addr),
+            Self::Call(addr) => nnn(0x2000, addr),
+            Self::SkipIfVxEq { vx, val } => xnn(0x3000, vx, val),
+            Self::SkipIfVxNeq { vx, val } => xnn(0x4000, vx, val),
+            Self::SkipIfVxEqVy { vx, vy } => xy(0x5000, vx, vy, 0),
+            Self::StoreRange { vx, vy } => xy(0x5000, vx, vy, 2),
+            Self::LoadRange { vx, vy } => xy(0x5000, vx, vy, 3),
+            Self::Set { vx, val } => xnn(0x6000, vx, val),
+            Self::Add { vx, val } => xnn(0x7000, vx, val),
+            Self::SetVxToVy { vx, vy } => xy(0x8000, vx, vy, 0),
+            Self::BinaryOr { vx, vy } => xy(0x8000, vx, vy, 1),
+            Self::BinaryAnd { vx, vy } => xy(0x8000, vx, vy, 2),
+            Self::LogicalXor { vx, vy } => xy(0x8000, vx, vy, 3),
+            Self::AddVyToVx { vx, vy } => xy(0x8000, vx, vy, 4),
+            Self::SubVxVyToVx { vx, vy } => xy(0x8000, vx, vy, 5),
+            Self::Shift { vx, vy, left_shift } => {
+                xy(0x8000, vx, vy, if left_shift { 0xE } else { 6 })
+            }
+            Self::SubVyVxToVx { vx, vy } => xy(0x8000, vx, vy, 7),
+            Self::SkipIfVxNeqVy { vx, vy } => xy(0x9000, vx, vy, 0),
+            Self::SetIndex(addr) => nnn(0xA000, addr),
+            Self::SetIndexLong(addr) => return vec![0xF0, 0x00, (addr >> 8) as u8, addr as u8],
+            Self::JumpWithOffset { val, .. } => nnn(0xB000, val),
+            Self::Random { vx, val } => xnn(0xC000, vx, val),
+            Self::Display { vx, vy, val } => xy(0xD000, vx, vy, val as u16),
+            Self::SkipIfKeyPressed { vx } => xnn(0xE000, vx, 0x9E),
+            Self::SkipIfKeyNotPressed { vx } => xnn(0xE000, vx, 0xA1),
+            Self::SelectPlanes(n) => xnn(0xF000, n, 0x01),
+            Self::LoadAudioPattern => 0xF002,
+            Self::SetVxToDelayTimer { vx } => xnn(0xF000, vx, 0x07),
+            Self::GetKey { vx } => xnn(0xF000, vx, 0x0A),
+            Self::SetDelayTimerToVx { vx } => xnn(0xF000, vx, 0x15),
+            Self::SetSoundTimerToVx { vx } => xnn(0xF000, vx, 0x18),
+            Self::AddToIndex { vx } => xnn(0xF000, vx, 0x1E),
+            Self::FontCharacter { vx } => xnn(0xF000, vx, 0x29),
+            Self::BigFontCharacter { vx } => xnn(0xF000, vx, 0x30),
+            Self::BinaryCodedDecimalConversion { vx } => xnn(0xF000, vx, 0x33),
+            Self::SetPitchToVx { vx } => xnn(0xF000, vx, 0x3A),
+            Self::StoreMemory { vx } => xnn(0xF000, vx, 0x55),
+            Self::LoadMemory { vx } => xnn(0xF000, vx, 0x65),
+            Self::StoreFlags { vx } => xnn(0xF000, vx, 0x75),
+            Self::LoadFlags { vx } => xnn(0xF000, vx, 0x85),
+        };
+        word.to_be_bytes().to_vec()
+    }
+
     /// Takes a u8 slice as input and converts it to an op code. Will return None if length
-    /// of input is less than 2, or if the input doesn't match any supported op code.
+    /// of input is less than 2 (or 4 for F000 NNNN), or if the input doesn't match any supported op code.
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
         if bytes.len() < 2 {
             return None;
@@ -86,19 +266,25 @@ impl OpCode {
             0 => match ((bytes[0] as u16) << 8) + bytes[1] as u16 {
                 0x00E0 => Some(Self::ClearScreen),
                 0x00EE => Some(Self::Return),
+                0x00FB => Some(Self::ScrollRight),
+                0x00FC => Some(Self::ScrollLeft),
+                0x00FD => Some(Self::Exit),
+                0x00FE => Some(Self::LowRes),
+                0x00FF => Some(Self::HighRes),
+                n if n & 0xFFF0 == 0x00C0 => Some(Self::ScrollDown(bytes[1] & 0xF)),
+                n if n & 0xFFF0 == 0x00D0 => Some(Self::ScrollUp(bytes[1] & 0xF)),
                 _ => None,
             },
             1 => Some(Self::Jump(((vx as u16) << 8) + bytes[1] as u16)),
             2 => Some(Self::Call(((vx as u16) << 8) + bytes[1] as u16)),
             3 => Some(Self::SkipIfVxEq { vx, val: bytes[1] }),
             4 => Some(Self::SkipIfVxNeq { vx, val: bytes[1] }),
-            5 => {
-                if (bytes[1] & 0xF) == 0 {
-                    Some(Self::SkipIfVxEqVy { vx, vy })
-                } else {
-                    None
-                }
-            }
+            5 => match bytes[1] & 0xF {
+                0 => Some(Self::SkipIfVxEqVy { vx, vy }),
+                2 => Some(Self::StoreRange { vx, vy }),
+                3 => Some(Self::LoadRange { vx, vy }),
+                _ => None,
+            },
             6 => Some(Self::Set { vx, val: bytes[1] }),
             7 => Some(Self::Add { vx, val: bytes[1] }),
             8 => match bytes[1] & 0xF {
@@ -145,15 +331,24 @@ impl OpCode {
                 _ => None,
             },
             0xf => match bytes[1] {
+                0x00 if vx == 0 => bytes
+                    .get(2..4)
+                    .map(|b| Self::SetIndexLong(((b[0] as u16) << 8) + b[1] as u16)),
+                0x01 => Some(Self::SelectPlanes(vx)),
+                0x02 if vx == 0 => Some(Self::LoadAudioPattern),
+                0x3A => Some(Self::SetPitchToVx { vx }),
                 0x07 => Some(Self::SetVxToDelayTimer { vx }),
                 0x15 => Some(Self::SetDelayTimerToVx { vx }),
                 0x18 => Some(Self::SetSoundTimerToVx { vx }),
                 0x1E => Some(Self::AddToIndex { vx }),
                 0x0A => Some(Self::GetKey { vx }),
                 0x29 => Some(Self::FontCharacter { vx }),
+                0x30 => Some(Self::BigFontCharacter { vx }),
                 0x33 => Some(Self::BinaryCodedDecimalConversion { vx }),
                 0x55 => Some(Self::StoreMemory { vx }),
                 0x65 => Some(Self::LoadMemory { vx }),
+                0x75 => Some(Self::StoreFlags { vx }),
+                0x85 => Some(Self::LoadFlags { vx }),
                 _ => None,
             },
             _ => None,
@@ -161,6 +356,16 @@ impl OpCode {
     }
 }
 
+impl fmt::Display for OpCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let target = match self {
+            Self::SetIndexLong(addr) => format!("0x{addr:04X}"),
+            _ => format!("0x{:03X}", self.target().unwrap_or_default()),
+        };
+        write!(f, "{}", self.to_string_with_target(&target))
+    }
+}
+
 #[path = "unittest/test_opcode.rs"]
 #[cfg(test)]
 mod test_opcode;
@@ -0,0 +1,71 @@
+//! # Output
+//!
+//! Helpers for the files written while the emulator runs: the trace, the movie and the GIF
+//! recording.
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// Output which stops at the first write error, warning about it once rather than for every
+/// write that follows.
+pub(crate) struct StopOnError<T> {
+    output: Option<T>,
+    /// What is written, for the warning
+    name: &'static str,
+}
+
+impl<T> StopOnError<T> {
+    pub(crate) fn new(output: T, name: &'static str) -> Self {
+        Self {
+            output: Some(output),
+            name,
+        }
+    }
+
+    /// Writes to the output, unless an earlier write failed.
+    pub(crate) fn write(&mut self, write: impl FnOnce(&mut T) -> io::Result<()>) {
+        let Some(output) = &mut self.output else {
+            return;
+        };
+        if let Err(e) = write(output) {
+            eprintln!(
+                "Warning: Failed to write {}, stopped writing it: {e}",
+                self.name
+            );
+            self.output = None;
+        }
+    }
+
+    /// Returns the output, or None if a write failed.
+    pub(crate) fn into_inner(self) -> Option<T> {
+        self.output
+    }
+}
+
+/// A writer collecting the output in memory shared with its clones, to read it back while or
+/// after another owner writes to it
+#[derive(Clone, Default)]
+pub(crate) struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    /// Returns a copy of the output so far.
+    pub(crate) fn contents(&self) -> Vec<u8> {
+        self.0.borrow().clone()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[path = "unittest/test_output.rs"]
+#[cfg(test)]
+mod test_output;
@@ -0,0 +1,145 @@
+//! # Pacing
+//!
+//! Keeps the emulator running at the display frequency in real time. The frames are scheduled on
+//! wall-clock deadlines, so the time spent executing and drawing a frame is taken off the wait for
+//! the next one, and the emulator doesn't drift. A frame running late is caught up by running the
+//! following frames without waiting, up to `catch_up_frames` behind. Further behind, e.g. after the
+//! process was suspended, the missed frames are dropped and the schedule starts over from the
+//! current time.
+//!
+//! In slow motion the frames are scheduled further apart, by the slow motion factor.
+//!
+//! The speed actually reached is measured as frames and instructions per second, together with the
+//! frames dropped, for showing in the window title.
+
+use crate::chip8options;
+use std::time::{Duration, Instant};
+
+/// Time over which the speed is measured
+const MEASURE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Schedules the frames on wall-clock deadlines.
+pub struct FramePacer {
+    /// The time between frames at normal speed
+    base_duration: Duration,
+    frame_duration: Duration,
+    catch_up_frames: u32,
+    /// The deadline of the next frame
+    deadline: Instant,
+    dropped: u64,
+}
+
+impl FramePacer {
+    /// Creates a pacer for the timing options, with the first frame due at the start time.
+    pub fn new(timing: &chip8options::Timing, start: Instant) -> Self {
+        let frame_duration = Duration::from_secs(1) / timing.display_frequency.max(1);
+        Self {
+            base_duration: frame_duration,
+            frame_duration,
+            catch_up_frames: timing.catch_up_frames,
+            deadline: start,
+            dropped: 0,
+        }
+    }
+
+    /// True if the next frame is due, in which case it is scheduled as run and the deadline moves
+    /// on to the frame after it.
+    pub fn frame_due(&mut self, now: Instant) -> bool {
+        if now < self.deadline {
+            return false;
+        }
+        let lag = now - self.deadline;
+        // How late a frame may run before the missed frames are dropped
+        if lag > self.frame_duration * self.catch_up_frames {
+            // Too far behind to catch up, skip the missed frames
+            self.dropped += (lag.as_nanos() / self.frame_duration.as_nanos()) as u64;
+            self.deadline = now;
+        }
+        self.deadline += self.frame_duration;
+        true
+    }
+
+    /// Sets how many times slower than the display frequency the frames are scheduled, from the
+    /// next frame on.
+    pub fn set_slow_down(&mut self, factor: u32) {
+        let frame_duration = self.base_duration * factor.max(1);
+        if frame_duration != self.frame_duration {
+            self.deadline = self.deadline - self.frame_duration + frame_duration;
+            self.frame_duration = frame_duration;
+        }
+    }
+
+    /// Starts the schedule over with the next frame due at the time, without counting the frames
+    /// missed as dropped, e.g. after running the frames back to back.
+    pub fn restart(&mut self, now: Instant) {
+        self.deadline = now;
+    }
+
+    /// The time left until the next frame is due.
+    pub fn time_to_next_frame(&self, now: Instant) -> Duration {
+        self.deadline.saturating_duration_since(now)
+    }
+
+    /// Number of frames dropped so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+/// The measured speed of the emulator
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Speed {
+    /// Frames per second
+    pub fps: f64,
+    /// Instructions per second
+    pub ips: f64,
+    /// Frames dropped since the last measurement
+    pub dropped: u64,
+}
+
+/// Measures the speed of the emulator from its frame and instruction counts, and the count of
+/// dropped frames.
+pub struct SpeedMeter {
+    start: Instant,
+    frames: u64,
+    instructions: u64,
+    dropped: u64,
+}
+
+impl SpeedMeter {
+    /// Creates a meter measuring from the start time and counts.
+    pub fn new(start: Instant, frames: u64, instructions: u64, dropped: u64) -> Self {
+        Self {
+            start,
+            frames,
+            instructions,
+            dropped,
+        }
+    }
+
+    /// Returns the speed since the last measurement once a second has passed, None before that.
+    pub fn measure(
+        &mut self,
+        now: Instant,
+        frames: u64,
+        instructions: u64,
+        dropped: u64,
+    ) -> Option<Speed> {
+        let elapsed = now.saturating_duration_since(self.start);
+        if elapsed < MEASURE_INTERVAL {
+            return None;
+        }
+        let seconds = elapsed.as_secs_f64();
+        let speed = Speed {
+            fps: frames.saturating_sub(self.frames) as f64 / seconds,
+            ips: instructions.saturating_sub(self.instructions) as f64 / seconds,
+            dropped: dropped.saturating_sub(self.dropped),
+        };
+        *self = Self::new(now, frames, instructions, dropped);
+        Some(speed)
+    }
+}
+
+#[path = "unittest/test_pacing.rs"]
+#[cfg(test)]
+mod test_pacing;
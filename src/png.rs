@@ -0,0 +1,214 @@
+//! # PNG
+//!
+//! A small PNG encoder for screenshots of the framebuffer, without any image library. The images
+//! are palette based, with one byte per pixel indexing the palette.
+//!
+//! The pixel data is compressed with a single deflate block using the fixed Huffman codes, and
+//! matching only repeats at a few given distances: the previous byte, for runs of the same color,
+//! and the previous row, for scaled up pixels. That is all it takes to compress the blocky images
+//! of a CHIP-8 screen well.
+
+/// Identifies a PNG file
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// Base lengths of the deflate length codes 257-285, and their number of extra bits
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// Base distances of the deflate distance codes 0-29, and their number of extra bits
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_DISTANCE: usize = 32768;
+
+/// Encodes an image as PNG. The pixels hold one palette index per pixel, row by row.
+pub fn encode(width: u32, height: u32, palette: &[(u8, u8, u8)], pixels: &[u8]) -> Vec<u8> {
+    let mut png = SIGNATURE.to_vec();
+
+    let mut header = Vec::new();
+    header.extend(width.to_be_bytes());
+    header.extend(height.to_be_bytes());
+    // Bit depth 8, palette color type, default compression, filter and no interlace
+    header.extend([8, 3, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+
+    let plte: Vec<u8> = palette.iter().flat_map(|(r, g, b)| [*r, *g, *b]).collect();
+    write_chunk(&mut png, b"PLTE", &plte);
+
+    // Each row starts with the filter type, 0 for no filter
+    let stride = width as usize + 1;
+    let mut data = Vec::with_capacity(stride * height as usize);
+    for row in pixels.chunks(width.max(1) as usize) {
+        data.push(0);
+        data.extend(row);
+    }
+    write_chunk(&mut png, b"IDAT", &zlib(&data, &[1, stride]));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+/// Appends a chunk with its length, type, data and CRC.
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+/// Compresses the data in the zlib format, matching repeats at the given distances.
+pub fn zlib(data: &[u8], distances: &[usize]) -> Vec<u8> {
+    // Deflate with a 32 KiB window, no preset dictionary, fastest compression level
+    let mut out = vec![0x78, 0x01];
+    out.extend(deflate(data, distances));
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+/// Compresses the data into a single deflate block with the fixed Huffman codes.
+fn deflate(data: &[u8], distances: &[usize]) -> Vec<u8> {
+    let mut bits = BitWriter::default();
+    // Final block, fixed Huffman codes
+    bits.write(1, 1);
+    bits.write(1, 2);
+    let mut i = 0;
+    while i < data.len() {
+        let (length, distance) = longest_match(data, i, distances);
+        if length >= MIN_MATCH {
+            write_length(&mut bits, length);
+            write_distance(&mut bits, distance);
+            i += length;
+        } else {
+            write_literal(&mut bits, data[i] as u16);
+            i += 1;
+        }
+    }
+    write_literal(&mut bits, 256);
+    bits.finish()
+}
+
+/// Finds the longest repeat at the position among the distances, as (length, distance).
+fn longest_match(data: &[u8], pos: usize, distances: &[usize]) -> (usize, usize) {
+    let max_length = (data.len() - pos).min(MAX_MATCH);
+    distances
+        .iter()
+        .filter(|d| **d <= pos && **d <= MAX_DISTANCE)
+        .map(|d| {
+            let length = (0..max_length)
+                .take_while(|k| data[pos + k] == data[pos + k - d])
+                .count();
+            (length, *d)
+        })
+        .max_by_key(|(length, _)| *length)
+        .unwrap_or((0, 0))
+}
+
+/// Writes a literal/length symbol with its fixed Huffman code.
+fn write_literal(bits: &mut BitWriter, symbol: u16) {
+    let (code, len) = match symbol {
+        0..=143 => (0x30 + symbol, 8),
+        144..=255 => (0x190 + symbol - 144, 9),
+        256..=279 => (symbol - 256, 7),
+        _ => (0xC0 + symbol - 280, 8),
+    };
+    bits.write_huffman(code, len);
+}
+
+fn write_length(bits: &mut BitWriter, length: usize) {
+    let code = LENGTH_BASE
+        .iter()
+        .rposition(|b| *b as usize <= length)
+        .unwrap_or(0);
+    write_literal(bits, 257 + code as u16);
+    bits.write(
+        (length - LENGTH_BASE[code] as usize) as u32,
+        LENGTH_EXTRA[code],
+    );
+}
+
+fn write_distance(bits: &mut BitWriter, distance: usize) {
+    let code = DISTANCE_BASE
+        .iter()
+        .rposition(|b| *b as usize <= distance)
+        .unwrap_or(0);
+    bits.write_huffman(code as u16, 5);
+    bits.write(
+        (distance - DISTANCE_BASE[code] as usize) as u32,
+        DISTANCE_EXTRA[code],
+    );
+}
+
+/// Writes bits least significant bit first, as deflate packs them, and as GIF packs its codes
+#[derive(Default)]
+pub(crate) struct BitWriter {
+    out: Vec<u8>,
+    buffer: u32,
+    count: u8,
+}
+
+impl BitWriter {
+    /// Writes the lowest bits of the value, up to 16, least significant bit first.
+    pub(crate) fn write(&mut self, value: u32, bits: u8) {
+        self.buffer |= (value & ((1 << bits) - 1)) << self.count;
+        self.count += bits;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Writes a Huffman code, which is packed most significant bit first.
+    fn write_huffman(&mut self, code: u16, len: u8) {
+        let reversed = (code as u32).reverse_bits() >> (32 - len as u32);
+        self.write(reversed, len);
+    }
+
+    /// Flushes the last partial byte.
+    pub(crate) fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+/// CRC-32 of the PNG chunks, as in ISO 3309
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(0xFFFF_FFFF, |crc, b| {
+        (0..8).fold(crc ^ *b as u32, |c, _| {
+            if c & 1 == 1 {
+                0xEDB8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            }
+        })
+    })
+}
+
+/// Adler-32 checksum of the zlib data
+pub fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), byte| {
+        let a = (a + *byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    (b << 16) | a
+}
+
+#[path = "unittest/test_png.rs"]
+#[cfg(test)]
+mod test_png;
@@ -0,0 +1,102 @@
+//! # Random
+//!
+//! The random number sources for the random op code (CXNN). The algorithm is selected in the
+//! `[random]` section of the options or with `--rng`, and seeded with a fixed seed for reproducible
+//! runs, or a random one:
+//!
+//! - `pcg`: A PCG32 generator, statistically good random numbers.
+//! - `8-bit`: A simple 8-bit routine with the short-period patterns of 8-bit machines, see
+//!   [`EightBitRandom`].
+//!
+//! When embedding the emulator, any other source can be plugged in by implementing [`RandomSource`].
+
+use crate::chip8options::RandomAlgorithm;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
+
+/// A source of random bytes for the random op code
+pub trait RandomSource {
+    /// Returns the next random byte, before masking by the op code.
+    fn next_byte(&mut self) -> u8;
+
+    /// Takes a snapshot of the generator state for save states.
+    /// Sources which can't be saved are kept as they are when a state is loaded.
+    fn snapshot(&self) -> RandomState {
+        RandomState::External
+    }
+}
+
+impl RandomSource for Pcg32 {
+    fn next_byte(&mut self) -> u8 {
+        self.random()
+    }
+
+    fn snapshot(&self) -> RandomState {
+        RandomState::Pcg(self.clone())
+    }
+}
+
+/// The saved state of a random number source
+#[derive(Serialize, Deserialize, Clone)]
+pub enum RandomState {
+    Pcg(Pcg32),
+    EightBit(EightBitRandom),
+    /// A source plugged in from outside, which isn't saved
+    External,
+}
+
+impl RandomState {
+    /// Restores the random number source, None for the sources which aren't saved.
+    pub fn restore(&self) -> Option<Box<dyn RandomSource>> {
+        match self {
+            RandomState::Pcg(rng) => Some(Box::new(rng.clone())),
+            RandomState::EightBit(rng) => Some(Box::new(rng.clone())),
+            RandomState::External => None,
+        }
+    }
+}
+
+/// Creates the random number source of the algorithm, seeded with the seed.
+pub fn from_seed(algorithm: RandomAlgorithm, seed: u64) -> Box<dyn RandomSource> {
+    match algorithm {
+        RandomAlgorithm::Pcg => Box::new(Pcg32::seed_from_u64(seed)),
+        RandomAlgorithm::EightBit => Box::new(EightBitRandom::from_seed(seed)),
+    }
+}
+
+/// A simple 8-bit pseudo-random routine: a byte counter is stepped on each call and mixed into the
+/// previous number with a rotate and an add. The numbers repeat after at most 65536 calls and show
+/// the short-period patterns of an 8-bit routine, unlike PCG. It isn't the routine of any original
+/// interpreter, so it doesn't reproduce the numbers of a real machine.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EightBitRandom {
+    counter: u8,
+    value: u8,
+}
+
+impl EightBitRandom {
+    /// Creates the generator with its two state bytes taken from the low bytes of the seed.
+    pub fn from_seed(seed: u64) -> Self {
+        Self {
+            counter: seed as u8,
+            value: (seed >> 8) as u8,
+        }
+    }
+}
+
+impl RandomSource for EightBitRandom {
+    fn next_byte(&mut self) -> u8 {
+        self.counter = self.counter.wrapping_add(1);
+        self.value = self.value.rotate_right(1).wrapping_add(self.counter);
+        self.value
+    }
+
+    fn snapshot(&self) -> RandomState {
+        RandomState::EightBit(self.clone())
+    }
+}
+
+#[path = "unittest/test_random.rs"]
+#[cfg(test)]
+mod test_random;
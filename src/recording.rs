@@ -0,0 +1,136 @@
+//! # Recording
+//!
+//! Records the screen updates of a session as an animated GIF, for sharing bug reproductions and ROM
+//! demos. The recording is scaled up by `capture_scale` and drawn in the display colors of the
+//! options, like the screenshots.
+//!
+//! Each screen update becomes a frame, shown until the next update by the frame count of the
+//! emulator, so the recording plays back at the speed of the display frequency however fast the
+//! emulator ran, e.g. in headless mode. GIF delays are counted in hundredths of a second and most
+//! viewers slow down frames shorter than 2/100 s, so updates following each other closer than that
+//! replace the pending frame rather than adding one. Updates which leave the screen as it was only
+//! extend the pending frame.
+//!
+//! The size of the image is fixed by the first frame. Frames of another resolution, after switching
+//! between the low and high resolution modes, are scaled to fit.
+
+use crate::chip8options;
+use crate::framebuffer::Framebuffer;
+use crate::gif;
+use crate::output::StopOnError;
+use crate::screenshot;
+use std::io::{self, Write};
+
+/// The shortest frame delay shown as is by viewers, in hundredths of a second
+const MIN_DELAY: u64 = 2;
+
+/// Writes the screen updates to an animated GIF.
+pub struct GifRecorder {
+    encoder: StopOnError<gif::Encoder<Box<dyn Write>>>,
+    width: usize,
+    height: usize,
+    display_frequency: u64,
+    /// The frame waiting for its delay, with its start time in hundredths of a second
+    pending: Option<(Vec<u8>, u64)>,
+}
+
+impl GifRecorder {
+    /// Creates a recorder writing to the writer, starting with the framebuffer shown from the frame
+    /// number on.
+    pub fn new(
+        writer: Box<dyn Write>,
+        framebuffer: &Framebuffer,
+        options: &chip8options::Chip8options,
+        frame: u64,
+    ) -> io::Result<Self> {
+        let scale = options.display.capture_scale.max(1) as usize;
+        let width = framebuffer.width() * scale;
+        let height = framebuffer.height() * scale;
+        let too_large = |_| io::Error::other(format!("recording size {width}x{height} too large"));
+        let encoder = gif::Encoder::new(
+            writer,
+            u16::try_from(width).map_err(too_large)?,
+            u16::try_from(height).map_err(too_large)?,
+            &screenshot::palette(&options.display),
+        )?;
+        let mut recorder = Self {
+            encoder: StopOnError::new(encoder, "recording"),
+            width,
+            height,
+            display_frequency: options.timing.display_frequency.max(1) as u64,
+            pending: None,
+        };
+        recorder.add_frame(framebuffer, frame);
+        Ok(recorder)
+    }
+
+    /// Adds the framebuffer, shown from the frame number on.
+    pub fn add_frame(&mut self, framebuffer: &Framebuffer, frame: u64) {
+        let pixels = self.resample(framebuffer);
+        let time = self.time(frame);
+        match self.pending.take() {
+            Some((previous, start)) if previous == pixels => {
+                self.pending = Some((previous, start));
+                return;
+            }
+            Some((_, start)) if time < start + MIN_DELAY => {
+                self.pending = Some((pixels, start));
+                return;
+            }
+            Some((previous, start)) => {
+                self.encoder
+                    .write(|encoder| write_frame(encoder, &previous, time - start));
+            }
+            None => (),
+        }
+        self.pending = Some((pixels, time));
+    }
+
+    /// Writes the pending frame, shown until the frame number, and ends the recording.
+    pub fn finish(mut self, frame: u64) -> io::Result<()> {
+        let pending = self.pending.take();
+        let end = self.time(frame);
+        let Some(mut encoder) = self.encoder.into_inner() else {
+            return Err(io::Error::other("recording stopped after a write error"));
+        };
+        if let Some((pixels, start)) = pending {
+            write_frame(
+                &mut encoder,
+                &pixels,
+                end.saturating_sub(start).max(MIN_DELAY),
+            )?;
+        }
+        encoder.finish().map(|_| ())
+    }
+
+    /// The start time of the frame number, in hundredths of a second.
+    fn time(&self, frame: u64) -> u64 {
+        frame * 100 / self.display_frequency
+    }
+
+    /// Scales the framebuffer to the size of the recording, returning its palette indices.
+    fn resample(&self, framebuffer: &Framebuffer) -> Vec<u8> {
+        let (fb_width, fb_height) = (framebuffer.width(), framebuffer.height());
+        let fb_pixels = framebuffer.pixels();
+        let mut pixels = Vec::with_capacity(self.width * self.height);
+        for y in 0..self.height {
+            let row = y * fb_height / self.height * fb_width;
+            pixels
+                .extend((0..self.width).map(|x| fb_pixels[row + x * fb_width / self.width] & 0x3));
+        }
+        pixels
+    }
+}
+
+/// Writes the frame, shown for the delay in hundredths of a second.
+fn write_frame(
+    encoder: &mut gif::Encoder<Box<dyn Write>>,
+    pixels: &[u8],
+    delay: u64,
+) -> io::Result<()> {
+    encoder.write_frame(pixels, delay.min(u16::MAX as u64) as u16)
+}
+
+#[path = "unittest/test_recording.rs"]
+#[cfg(test)]
+mod test_recording;
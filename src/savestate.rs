@@ -0,0 +1,132 @@
+//! # Save state
+//!
+//! Contains a snapshot of the full machine state of the emulator, which can be serialized to a
+//! versioned binary format and restored later.
+//! The binary format starts with a header holding a magic number and the format version,
+//! followed by the bincode encoded state. States read back are checked to fit the emulator, so a
+//! damaged or hand-made file is rejected rather than crashing the emulator when loaded.
+use crate::chip8options::Chip8options;
+use crate::emulator::{BIG_FONTS, FONTS};
+use crate::framebuffer::Framebuffer;
+use crate::random::RandomState;
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// Identifies a CHIP-8 save state file
+const MAGIC: [u8; 4] = *b"CH8S";
+/// Version of the save state format, to be incremented when the contents of SaveState changes
+const VERSION: u32 = 7;
+/// Maximum size of the encoded state, well above the 64 KiB memory of XO-CHIP with a ROM as large
+const MAX_SIZE: u64 = 1 << 20;
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    magic: [u8; 4],
+    version: u32,
+}
+
+/// Snapshot of the machine state of an Emulator, with the ROM and the options it runs with
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SaveState {
+    pub(crate) rom: Vec<u8>,
+    pub(crate) options: Chip8options,
+    pub(crate) machine: MachineState,
+}
+
+/// The state changing while the program runs, which is all the rewind buffer keeps per frame
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct MachineState {
+    pub(crate) memory: Vec<u8>,
+    pub(crate) framebuffer: Framebuffer,
+    pub(crate) hires: bool,
+    pub(crate) selected_planes: u8,
+    pub(crate) pc: u16,
+    pub(crate) reg_i: u16,
+    pub(crate) stack: Vec<u16>,
+    pub(crate) delay_timer: u8,
+    pub(crate) sound_timer: u8,
+    pub(crate) reg_vx: [u8; 16],
+    pub(crate) keypad: [bool; 16],
+    pub(crate) flags: [u8; 16],
+    pub(crate) audio_pattern: Option<[u8; 16]>,
+    pub(crate) pitch: u8,
+    pub(crate) rng: RandomState,
+    pub(crate) halted: bool,
+}
+
+impl SaveState {
+    /// Serializes the save state to the binary format.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let header = Header {
+            magic: MAGIC,
+            version: VERSION,
+        };
+        let mut bytes = bincode::serialize(&header)?;
+        bytes.extend(bincode::serialize(self)?);
+        Ok(bytes)
+    }
+
+    /// Deserializes a save state from the binary format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let header: Header = bincode::deserialize(bytes)?;
+        if header.magic != MAGIC {
+            return Err("not a CHIP-8 save state".into());
+        }
+        if header.version != VERSION {
+            return Err(format!("unsupported save state version {}", header.version).into());
+        }
+        let header_size = bincode::serialized_size(&header)? as usize;
+        // The same encoding as bincode::serialize, with a limit against huge lengths in the data
+        let state: Self = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .with_limit(MAX_SIZE)
+            .deserialize(&bytes[header_size..])?;
+        state.check()?;
+        Ok(state)
+    }
+
+    /// Checks that the memory, the fonts and the ROM agree with the options, and that the
+    /// framebuffer has all its pixels.
+    fn check(&self) -> Result<(), String> {
+        let memory = &self.options.memory;
+        let machine = &self.machine;
+        if machine.memory.len() != memory.mem_size as usize || machine.memory.is_empty() {
+            return Err(format!(
+                "memory size {} doesn't match the options ({})",
+                machine.memory.len(),
+                memory.mem_size
+            ));
+        }
+        let areas = [
+            ("font", memory.font_start, FONTS.len()),
+            ("big font", memory.big_font_start, BIG_FONTS.len()),
+            ("ROM", memory.rom_start, self.rom.len()),
+        ];
+        for (name, start, len) in areas {
+            if start as usize + len > machine.memory.len() {
+                return Err(format!("{name} doesn't fit in memory"));
+            }
+        }
+        if !machine.framebuffer.is_consistent() {
+            return Err("framebuffer pixels don't match its size".to_string());
+        }
+        Ok(())
+    }
+
+    /// Writes the save state to a file.
+    pub fn save(&self, path: &std::path::Path) -> Result<(), Box<dyn Error>> {
+        std::fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+
+    /// Reads a save state from a file.
+    pub fn load(path: &std::path::Path) -> Result<Self, Box<dyn Error>> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+}
+
+#[path = "unittest/test_savestate.rs"]
+#[cfg(test)]
+mod test_savestate;
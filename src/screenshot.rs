@@ -0,0 +1,103 @@
+//! # Screenshot
+//!
+//! Writes the framebuffer to PNG files, in the display colors of the options, with each CHIP-8 pixel
+//! scaled up to a square of `capture_scale` pixels. Screenshots taken with the screenshot key are
+//! saved next to the ROM file, named by the ROM and the time in UTC, e.g.
+//! `blitz_20240131-154502.png`.
+
+use crate::chip8options;
+use crate::framebuffer::Framebuffer;
+use crate::png;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Returns the display colors indexed by the plane bit mask of a pixel.
+pub fn palette(disp_options: &chip8options::Display) -> [(u8, u8, u8); 4] {
+    [
+        disp_options.color_off_rgb,
+        disp_options.color_on_rgb,
+        disp_options.color_plane2_rgb,
+        disp_options.color_both_rgb,
+    ]
+}
+
+/// Scales up the framebuffer, returning the width, height and palette indices of the image.
+pub fn scale_pixels(framebuffer: &Framebuffer, scale: u32) -> (u32, u32, Vec<u8>) {
+    let scale = scale.max(1) as usize;
+    let width = framebuffer.width();
+    let mut pixels = Vec::with_capacity(framebuffer.pixels().len() * scale * scale);
+    for row in framebuffer.pixels().chunks(width.max(1)) {
+        let scaled_row: Vec<u8> = row
+            .iter()
+            .flat_map(|p| std::iter::repeat_n(p & 0x3, scale))
+            .collect();
+        for _ in 0..scale {
+            pixels.extend(&scaled_row);
+        }
+    }
+    let width = (width * scale) as u32;
+    let height = (framebuffer.height() * scale) as u32;
+    (width, height, pixels)
+}
+
+/// Encodes the framebuffer as a PNG image.
+pub fn encode_png(framebuffer: &Framebuffer, disp_options: &chip8options::Display) -> Vec<u8> {
+    let (width, height, pixels) = scale_pixels(framebuffer, disp_options.capture_scale);
+    png::encode(width, height, &palette(disp_options), &pixels)
+}
+
+/// Writes the framebuffer to a PNG file.
+pub fn save_png(
+    path: &Path,
+    framebuffer: &Framebuffer,
+    disp_options: &chip8options::Display,
+) -> io::Result<()> {
+    std::fs::write(path, encode_png(framebuffer, disp_options))
+}
+
+/// Returns a new file name next to the ROM file, named by the ROM and the current time, with the
+/// extension. A counter is added if a file with the name already exists.
+pub fn timestamped_path(rom_file: &Path, extension: &str) -> PathBuf {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let stem = rom_file
+        .file_stem()
+        .map_or("chip8".into(), |s| s.to_string_lossy());
+    let name = format!("{stem}_{}", format_timestamp(seconds));
+    let mut path = rom_file.with_file_name(format!("{name}.{extension}"));
+    let mut counter = 1;
+    while path.exists() {
+        counter += 1;
+        path = rom_file.with_file_name(format!("{name}-{counter}.{extension}"));
+    }
+    path
+}
+
+/// Formats the seconds since the Unix epoch as a UTC `YYYYMMDD-HHMMSS` timestamp.
+pub fn format_timestamp(seconds: u64) -> String {
+    let days = (seconds / 86400) as i64;
+    let time = seconds % 86400;
+    // Civil date from the days since 1970-01-01, counting in 400 year eras starting in March
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}{month:02}{day:02}-{:02}{:02}{:02}",
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+#[path = "unittest/test_screenshot.rs"]
+#[cfg(test)]
+mod test_screenshot;
@@ -0,0 +1,167 @@
+//! # Trace
+//!
+//! Logs every executed instruction to a file, to compare the behaviour with other emulators.
+//! Each line holds the cycle count, the PC, the raw op code bytes, the decoded [`OpCode`],
+//! the V0-VF registers, I, the stack pointer and the delay and sound timers, as the state was
+//! just before the instruction was executed:
+//!
+//! ```text
+//! 0000000000 PC:0200 OP:6005 Set { vx: 0, val: 5 } V:00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I:0000 SP:0 DT:00 ST:00
+//! ```
+//!
+//! The trace can be limited to PC ranges and to kinds of op codes, named as the [`OpCode`] variants
+//! (e.g. `Display` or `Call`). The cycle count keeps counting the instructions filtered out.
+
+use crate::emulator::Emulator;
+use crate::opcode::OpCode;
+use crate::output::StopOnError;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+/// Which instructions are traced. Empty lists trace everything.
+#[derive(Debug, Clone, Default)]
+pub struct TraceFilter {
+    /// PC ranges to trace
+    pub pc_ranges: Vec<RangeInclusive<u16>>,
+    /// Names of the op code kinds to trace, case insensitive
+    pub opcodes: Vec<String>,
+}
+
+impl TraceFilter {
+    /// Returns true if the instruction at the PC should be traced.
+    pub fn matches(&self, pc: u16, opcode: Option<&OpCode>) -> bool {
+        let pc_match = self.pc_ranges.is_empty() || self.pc_ranges.iter().any(|r| r.contains(&pc));
+        let opcode_match = self.opcodes.is_empty()
+            || opcode.is_some_and(|op| {
+                let name = opcode_name(op);
+                self.opcodes.iter().any(|o| o.eq_ignore_ascii_case(&name))
+            });
+        pc_match && opcode_match
+    }
+}
+
+/// Writes the trace of the executed instructions.
+pub struct Tracer {
+    writer: StopOnError<Box<dyn Write>>,
+    filter: TraceFilter,
+    cycle: u64,
+}
+
+impl Tracer {
+    /// Creates a tracer writing to the writer.
+    pub fn new(writer: Box<dyn Write>, filter: TraceFilter) -> Self {
+        Self {
+            writer: StopOnError::new(writer, "trace"),
+            filter,
+            cycle: 0,
+        }
+    }
+
+    /// Creates a tracer writing to a new file.
+    pub fn create(path: &Path, filter: TraceFilter) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(Self::new(Box::new(file), filter))
+    }
+
+    /// Traces the instruction the emulator is about to execute.
+    pub fn trace(&mut self, emulator: &Emulator) {
+        let cycle = self.cycle;
+        self.cycle += 1;
+        let opcode = emulator.next_opcode();
+        if !self.filter.matches(emulator.pc(), opcode.as_ref()) {
+            return;
+        }
+        self.writer
+            .write(|w| writeln!(w, "{}", format_line(cycle, emulator)));
+    }
+}
+
+/// Formats the trace line of the instruction the emulator is about to execute.
+pub fn format_line(cycle: u64, emulator: &Emulator) -> String {
+    let pc = emulator.pc() as usize;
+    let opcode = emulator.next_opcode();
+    let size = opcode.map_or(2, |op| op.size() as usize);
+    let memory = emulator.memory();
+    let raw: String = (pc..pc + size)
+        .map(|a| format!("{:02X}", memory.get(a).copied().unwrap_or(0)))
+        .collect();
+    let decoded = match opcode {
+        Some(op) => format!("{op:?}"),
+        None => "Invalid".to_string(),
+    };
+    let mut line = format!("{cycle:010} PC:{pc:04X} OP:{raw} {decoded} V:");
+    let registers: Vec<String> = emulator
+        .registers()
+        .iter()
+        .map(|v| format!("{v:02X}"))
+        .collect();
+    line.push_str(&registers.join(" "));
+    let _ = write!(
+        line,
+        " I:{:04X} SP:{:X} DT:{:02X} ST:{:02X}",
+        emulator.index(),
+        emulator.stack().len(),
+        emulator.delay_timer(),
+        emulator.sound_timer()
+    );
+    line
+}
+
+/// Returns the name of the op code kind, i.e. the variant name.
+fn opcode_name(opcode: &OpCode) -> String {
+    format!("{opcode:?}")
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect()
+}
+
+/// Returns the names of all kinds of op codes, in op code order.
+fn opcode_names() -> Vec<String> {
+    let mut names = Vec::new();
+    for word in 0..=u16::MAX {
+        let [high, low] = word.to_be_bytes();
+        if let Some(opcode) = OpCode::from_bytes(&[high, low, 0, 0]) {
+            let name = opcode_name(&opcode);
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+/// Parses the name of a kind of op code, e.g. `Display`, ignoring case.
+pub fn parse_opcode_name(s: &str) -> Result<String, String> {
+    let names = opcode_names();
+    match names.iter().find(|name| name.eq_ignore_ascii_case(s)) {
+        Some(name) => Ok(name.clone()),
+        None => Err(format!(
+            "unknown op code {s}, use one of {}",
+            names.join(", ")
+        )),
+    }
+}
+
+/// Parses a hexadecimal PC range, `200-2FF`, or a single address.
+pub fn parse_pc_range(s: &str) -> Result<RangeInclusive<u16>, String> {
+    match s.split_once('-') {
+        Some((start, end)) => {
+            let (start, end) = (crate::parse_address(start)?, crate::parse_address(end)?);
+            if start > end {
+                return Err(format!("invalid range {s}: start after end"));
+            }
+            Ok(start..=end)
+        }
+        None => {
+            let addr = crate::parse_address(s)?;
+            Ok(addr..=addr)
+        }
+    }
+}
+
+#[path = "unittest/test_trace.rs"]
+#[cfg(test)]
+mod test_trace;
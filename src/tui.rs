@@ -0,0 +1,402 @@
+//! # TUI
+//!
+//! Terminal frontend, running without SDL, e.g. over SSH. The framebuffer is drawn with Unicode
+//! upper half block characters, two pixels above each other per character cell, so that 64x32
+//! pixels fit in 64x16 cells. The pixels get the display colors of the options as 24-bit colors.
+//!
+//! The keys are read in raw terminal mode and mapped with the same key bindings as the SDL keyboard,
+//! see [`crate::keybindings`], with Ctrl-C to quit in addition. Terminals which don't tell the keys of
+//! the numeric keypad apart report them as the main keys, which fall back to the keypad bindings.
+//! Most terminals only report key presses, not releases, in which case a key counts as released
+//! when its key repeats stop. Terminals supporting the kitty keyboard protocol report the releases.
+//! The sound is played as the terminal bell, and warnings are shown on the line below the screen
+//! rather than written into it.
+
+use crate::chip8options;
+use crate::emulator::{KeyEvent, System};
+use crate::framebuffer::Framebuffer;
+use crate::keybindings::{Action, Binding, KeyBindings};
+use crate::savestate::SaveState;
+use crate::screenshot;
+use crossterm::event::{
+    self, Event, KeyCode, KeyEventKind, KeyEventState, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::{cursor, queue, terminal};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufWriter, Stdout, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Upper half block, the top pixel is drawn in the foreground color and the bottom one in the background color
+const HALF_BLOCK: char = '\u{2580}';
+
+/// Time after the first press of a key until it counts as released, if it isn't repeated.
+/// Has to be longer than the key repeat delay of the terminal, 660 ms by default on X11.
+const FIRST_RELEASE_DELAY: Duration = Duration::from_millis(750);
+
+/// Time after a key repeat until the key counts as released
+const REPEAT_RELEASE_DELAY: Duration = Duration::from_millis(100);
+
+/// A key held down, when no releases are reported
+#[derive(Debug, Clone, Copy)]
+struct HeldKey {
+    last_press: Instant,
+    repeated: bool,
+}
+
+/// The keys which can be held, and which need a release event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Holdable {
+    Key(u8),
+    Rewind,
+    Turbo,
+}
+
+pub struct Terminal {
+    out: Stdout,
+    /// Colors indexed by the plane bit mask of a pixel
+    colors: [Color; 4],
+    /// Set when the terminal reports key releases
+    release_events: bool,
+    bindings: KeyBindings,
+    held: HashMap<Holdable, HeldKey>,
+    events: VecDeque<KeyEvent>,
+    state_file: PathBuf,
+    rom_file: PathBuf,
+    disp_options: chip8options::Display,
+    /// Last drawn framebuffer size, to clear the screen when it changes
+    size: (usize, usize),
+}
+
+impl Terminal {
+    /// Creates a new Terminal instance, switching the terminal to raw mode and the alternate screen.
+    pub fn new(
+        disp_options: &chip8options::Display,
+        bindings: KeyBindings,
+        state_file: PathBuf,
+        rom_file: PathBuf,
+    ) -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut out = io::stdout();
+        queue!(
+            out,
+            terminal::EnterAlternateScreen,
+            cursor::Hide,
+            terminal::Clear(terminal::ClearType::All)
+        )?;
+        let release_events = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if release_events {
+            queue!(
+                out,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        out.flush()?;
+        Ok(Self {
+            out,
+            colors: [
+                rgb_color(disp_options.color_off_rgb),
+                rgb_color(disp_options.color_on_rgb),
+                rgb_color(disp_options.color_plane2_rgb),
+                rgb_color(disp_options.color_both_rgb),
+            ],
+            release_events,
+            bindings,
+            held: HashMap::new(),
+            events: VecDeque::new(),
+            state_file,
+            rom_file,
+            disp_options: *disp_options,
+            size: (0, 0),
+        })
+    }
+
+    /// Shows a message on the line below the screen.
+    fn show_status(&mut self, message: &str) {
+        let row = self.size.1.div_ceil(2) as u16;
+        let _ = queue!(
+            self.out,
+            cursor::MoveTo(0, row),
+            ResetColor,
+            terminal::Clear(terminal::ClearType::CurrentLine),
+            Print(message)
+        );
+        let _ = self.out.flush();
+    }
+
+    /// Reads the pending terminal events into key events.
+    fn read_events(&mut self) -> io::Result<()> {
+        while event::poll(Duration::ZERO)? {
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            let ctrl_c =
+                key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c');
+            let binding = if ctrl_c {
+                Some(Binding::Action(Action::Quit))
+            } else {
+                self.lookup(key.code, key.state)
+            };
+            let held = match binding {
+                Some(Binding::Key(k)) => Holdable::Key(k),
+                Some(Binding::Action(Action::Rewind)) => Holdable::Rewind,
+                Some(Binding::Action(Action::Turbo)) => Holdable::Turbo,
+                Some(Binding::Action(action)) => {
+                    if key.kind == KeyEventKind::Press {
+                        self.events.push_back(action.press_event());
+                    }
+                    continue;
+                }
+                None => continue,
+            };
+            match key.kind {
+                KeyEventKind::Repeat => self.refresh_held(held),
+                KeyEventKind::Release => {
+                    if self.held.remove(&held).is_some() {
+                        self.events.push_back(release_event(held));
+                    }
+                }
+                KeyEventKind::Press => match self.held.entry(held) {
+                    // Without release events, a repeat is reported as a press
+                    Entry::Occupied(mut h) => {
+                        h.get_mut().last_press = Instant::now();
+                        h.get_mut().repeated = true;
+                    }
+                    Entry::Vacant(h) => {
+                        h.insert(HeldKey {
+                            last_press: Instant::now(),
+                            repeated: false,
+                        });
+                        self.events.push_back(match held {
+                            Holdable::Key(k) => KeyEvent::KeyDown(k),
+                            Holdable::Rewind => KeyEvent::Rewind(true),
+                            Holdable::Turbo => KeyEvent::Turbo(true),
+                        });
+                    }
+                },
+            }
+        }
+        if !self.release_events {
+            self.release_stale_keys();
+        }
+        Ok(())
+    }
+
+    /// Returns what the key is bound to, falling back to the keypad bindings for the main keys.
+    fn lookup(&self, code: KeyCode, state: KeyEventState) -> Option<Binding> {
+        let keypad = state.contains(KeyEventState::KEYPAD);
+        let binding = key_name(code, keypad).and_then(|n| self.bindings.lookup(&n));
+        match binding {
+            None if !keypad => key_name(code, true).and_then(|n| self.bindings.lookup(&n)),
+            binding => binding,
+        }
+    }
+
+    fn refresh_held(&mut self, held: Holdable) {
+        if let Some(h) = self.held.get_mut(&held) {
+            h.last_press = Instant::now();
+            h.repeated = true;
+        }
+    }
+
+    /// Releases the held keys which haven't been repeated for a while.
+    fn release_stale_keys(&mut self) {
+        let now = Instant::now();
+        let stale: Vec<Holdable> = self
+            .held
+            .iter()
+            .filter(|(_, h)| {
+                let delay = if h.repeated {
+                    REPEAT_RELEASE_DELAY
+                } else {
+                    FIRST_RELEASE_DELAY
+                };
+                now.duration_since(h.last_press) > delay
+            })
+            .map(|(k, _)| *k)
+            .collect();
+        for held in stale {
+            self.held.remove(&held);
+            self.events.push_back(release_event(held));
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if self.release_events {
+            let _ = queue!(self.out, PopKeyboardEnhancementFlags);
+        }
+        let _ = queue!(
+            self.out,
+            ResetColor,
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = self.out.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+impl System for Terminal {
+    fn update_screen(&mut self, framebuffer: &Framebuffer) {
+        let size = (framebuffer.width(), framebuffer.height());
+        if size != self.size {
+            self.size = size;
+            let _ = queue!(self.out, terminal::Clear(terminal::ClearType::All));
+        }
+        let _ = self
+            .out
+            .write_all(render(framebuffer, &self.colors).as_bytes());
+        let _ = self.out.flush();
+    }
+
+    fn get_key_event(&mut self) -> Option<KeyEvent> {
+        if self.events.is_empty()
+            && let Err(e) = self.read_events()
+        {
+            self.show_status(&format!("Warning: Failed to read terminal events: {e}"));
+        }
+        self.events.pop_front()
+    }
+
+    fn set_sound_state(&mut self, sound_on: bool) {
+        if sound_on {
+            let _ = self.out.write_all(b"\x07");
+            let _ = self.out.flush();
+        }
+    }
+
+    fn store_state(&mut self, state: SaveState) {
+        let message = match state.save(&self.state_file) {
+            Ok(()) => format!("Saved state to {}", self.state_file.display()),
+            Err(e) => format!("Warning: Failed to save state: {e}"),
+        };
+        self.show_status(&message);
+    }
+
+    fn save_screenshot(&mut self, framebuffer: &Framebuffer) {
+        let path = screenshot::timestamped_path(&self.rom_file, "png");
+        let message = match screenshot::save_png(&path, framebuffer, &self.disp_options) {
+            Ok(()) => format!("Saved screenshot to {}", path.display()),
+            Err(e) => format!("Warning: Failed to save screenshot: {e}"),
+        };
+        self.show_status(&message);
+    }
+
+    fn open_recording(&mut self) -> Option<Box<dyn Write>> {
+        let path = screenshot::timestamped_path(&self.rom_file, "gif");
+        match File::create(&path) {
+            Ok(file) => {
+                self.show_status(&format!("Recording to {}", path.display()));
+                Some(Box::new(BufWriter::new(file)))
+            }
+            Err(e) => {
+                self.show_status(&format!("Warning: Failed to create recording: {e}"));
+                None
+            }
+        }
+    }
+
+    fn recording_stopped(&mut self, result: io::Result<()>) {
+        let message = match result {
+            Ok(()) => "Recording stopped".to_string(),
+            Err(e) => format!("Warning: Failed to save recording: {e}"),
+        };
+        self.show_status(&message);
+    }
+
+    fn show_warning(&mut self, message: &str) {
+        self.show_status(&format!("Warning: {message}"));
+    }
+
+    fn fetch_state(&mut self) -> Option<SaveState> {
+        match SaveState::load(&self.state_file) {
+            Ok(state) => {
+                self.show_status(&format!("Loaded state from {}", self.state_file.display()));
+                Some(state)
+            }
+            Err(e) => {
+                self.show_status(&format!("Warning: Failed to load state: {e}"));
+                None
+            }
+        }
+    }
+}
+
+fn release_event(held: Holdable) -> KeyEvent {
+    match held {
+        Holdable::Key(k) => KeyEvent::KeyUp(k),
+        Holdable::Rewind => KeyEvent::Rewind(false),
+        Holdable::Turbo => KeyEvent::Turbo(false),
+    }
+}
+
+/// Returns the SDL name of a terminal key, e.g. `Q`, `Space` or `Keypad 7`.
+fn key_name(code: KeyCode, keypad: bool) -> Option<String> {
+    let name = match code {
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char(c) => c.to_uppercase().to_string(),
+        KeyCode::F(n) => return Some(format!("F{n}")),
+        KeyCode::Enter if keypad => "Enter".to_string(),
+        KeyCode::Enter => "Return".to_string(),
+        KeyCode::Esc => "Escape".to_string(),
+        KeyCode::Backspace => "Backspace".to_string(),
+        KeyCode::Tab => "Tab".to_string(),
+        KeyCode::Delete => "Delete".to_string(),
+        KeyCode::Insert => "Insert".to_string(),
+        KeyCode::Home => "Home".to_string(),
+        KeyCode::End => "End".to_string(),
+        KeyCode::PageUp => "PageUp".to_string(),
+        KeyCode::PageDown => "PageDown".to_string(),
+        KeyCode::Up => "Up".to_string(),
+        KeyCode::Down => "Down".to_string(),
+        KeyCode::Left => "Left".to_string(),
+        KeyCode::Right => "Right".to_string(),
+        _ => return None,
+    };
+    Some(if keypad {
+        format!("Keypad {name}")
+    } else {
+        name
+    })
+}
+
+fn rgb_color(rgb: (u8, u8, u8)) -> Color {
+    Color::Rgb {
+        r: rgb.0,
+        g: rgb.1,
+        b: rgb.2,
+    }
+}
+
+/// Renders the framebuffer as terminal output starting at the top left corner, with one row of
+/// half blocks for every two rows of pixels. The colors are only changed when they differ.
+fn render(framebuffer: &Framebuffer, colors: &[Color; 4]) -> String {
+    let mut out: Vec<u8> = Vec::new();
+    let width = framebuffer.width();
+    let pixels = framebuffer.pixels();
+    let pixel = |x: usize, y: usize| pixels.get(y * width + x).map_or(0, |p| *p as usize & 0x3);
+    let mut current = None;
+    for row in 0..framebuffer.height().div_ceil(2) {
+        let _ = queue!(out, cursor::MoveTo(0, row as u16));
+        for x in 0..width {
+            let cell = (colors[pixel(x, row * 2)], colors[pixel(x, row * 2 + 1)]);
+            if current != Some(cell) {
+                let _ = queue!(out, SetForegroundColor(cell.0), SetBackgroundColor(cell.1));
+                current = Some(cell);
+            }
+            let _ = queue!(out, Print(HALF_BLOCK));
+        }
+    }
+    let _ = queue!(out, ResetColor);
+    String::from_utf8(out).unwrap_or_default()
+}
+
+#[path = "unittest/test_tui.rs"]
+#[cfg(test)]
+mod test_tui;
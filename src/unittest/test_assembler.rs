@@ -0,0 +1,97 @@
+use super::*;
+use crate::disassembler::disassemble;
+
+#[test]
+fn test_instructions() {
+    let source = "
+        CLS
+        LD V0, 0x0C     ; comment
+        ld v1, #08
+        LD I, LONG 0x1234
+        DRW V0, V1, 15
+        SHR V3
+        LD [I], VA
+        JP V0, 0x300
+    ";
+    let rom = assemble(source, 0x200).unwrap();
+    assert!(
+        rom == [
+            0x00, 0xE0, 0x60, 0x0C, 0x61, 0x08, 0xF0, 0x00, 0x12, 0x34, 0xD0, 0x1F, 0x83, 0x36,
+            0xFA, 0x55, 0xB3, 0x00
+        ]
+    );
+}
+
+#[test]
+fn test_labels_and_constants() {
+    let source = "
+HEIGHT = sprite_end - sprite
+        LD I, sprite
+        DRW V0, V1, HEIGHT
+loop:   JP loop
+sprite:
+        db 0b00111100, 0x42, -1, \"A\"
+sprite_end:
+        dw 0x1234, sprite
+    ";
+    let rom = assemble(source, 0x200).unwrap();
+    assert!(
+        rom == [
+            0xA2, 0x06, 0xD0, 0x14, 0x12, 0x04, 0x3C, 0x42, 0xFF, 0x41, 0x12, 0x34, 0x02, 0x06
+        ]
+    );
+}
+
+#[test]
+fn test_errors() {
+    let error = |source: &str| assemble(source, 0x200).unwrap_err();
+    let e = error("  CLS\n  LD V0, 0x100");
+    assert!(e.line == 2 && e.column == 10 && e.message.contains("out of range"));
+    let e = error("  JP nowhere");
+    assert!(e.line == 1 && e.column == 6 && e.message.contains("undefined symbol nowhere"));
+    let e = error("  MOV V0, V1");
+    assert!(e.column == 3 && e.message.contains("unknown mnemonic"));
+    let e = error("  LD DT, 5");
+    assert!(e.message.contains("invalid operands for LD"));
+    let e = error("a:\na:");
+    assert!(e.line == 2 && e.message.contains("already defined"));
+    let e = error("  db 0x12 0x34");
+    assert!(e.column == 11);
+    let e = error("FOO = BAR\nBAR = FOO\n  db FOO");
+    assert!(e.message.contains("circular"));
+    assert!(
+        error("  include \"missing.asm\"")
+            .message
+            .contains("missing.asm")
+    );
+    assert!(e.to_string().starts_with("<source>:"));
+}
+
+#[test]
+fn test_include() {
+    let dir = std::env::temp_dir().join("chip8_test_assembler_include");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("main.asm"), "  CALL sub\n  include \"sub.asm\"\n").unwrap();
+    std::fs::write(dir.join("sub.asm"), "sub:\n  RET\n  LD V0, oops\n").unwrap();
+    let e = assemble_file(&dir.join("main.asm"), 0x200).unwrap_err();
+    assert!(e.file.ends_with("sub.asm") && e.line == 3 && e.column == 10);
+
+    std::fs::write(dir.join("sub.asm"), "sub:\n  RET\n").unwrap();
+    let rom = assemble_file(&dir.join("main.asm"), 0x200).unwrap();
+    assert!(rom == [0x22, 0x02, 0x00, 0xEE]);
+}
+
+#[test]
+fn test_disassembly_roundtrip() {
+    for entry in std::fs::read_dir("rom_files").unwrap() {
+        let path = entry.unwrap().path();
+        let rom = std::fs::read(&path).unwrap();
+        let source = disassemble(&rom, 0x200);
+        let assembled = assemble(&source, 0x200);
+        assert!(
+            assembled == Ok(rom),
+            "{} failed to round-trip",
+            path.display()
+        );
+    }
+}
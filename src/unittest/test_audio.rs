@@ -0,0 +1,86 @@
+use super::*;
+
+/// A playing generator of 8 samples per period of the tone
+fn generator(waveform: Waveform) -> ToneGenerator {
+    ToneGenerator {
+        sample_rate: 800.0,
+        phase: 0.0,
+        tone_frequency: 100.0,
+        volume: 1.0,
+        waveform,
+        playing: true,
+        pattern: None,
+        pitch: 64,
+    }
+}
+
+fn samples(generator: &mut ToneGenerator, len: usize) -> Vec<f32> {
+    let mut out = vec![0.0; len];
+    generator.callback(&mut out);
+    out
+}
+
+fn approx(a: &[f32], b: &[f32]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-4)
+}
+
+#[test]
+fn test_square() {
+    let out = samples(&mut generator(Waveform::Square), 16);
+    assert!(out[..8] == [1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0, -1.0]);
+    assert!(out[8..] == out[..8]);
+}
+
+#[test]
+fn test_sine() {
+    let out = samples(&mut generator(Waveform::Sine), 16);
+    let h = std::f32::consts::FRAC_1_SQRT_2;
+    assert!(approx(&out[..8], &[0.0, h, 1.0, h, 0.0, -h, -1.0, -h]));
+    assert!(approx(&out[8..], &out[..8]));
+}
+
+#[test]
+fn test_triangle() {
+    let out = samples(&mut generator(Waveform::Triangle), 16);
+    assert!(approx(
+        &out[..8],
+        &[-1.0, -0.5, 0.0, 0.5, 1.0, 0.5, 0.0, -0.5]
+    ));
+    assert!(approx(&out[8..], &out[..8]));
+}
+
+#[test]
+fn test_sawtooth() {
+    let out = samples(&mut generator(Waveform::Sawtooth), 16);
+    assert!(approx(
+        &out[..8],
+        &[-1.0, -0.75, -0.5, -0.25, 0.0, 0.25, 0.5, 0.75]
+    ));
+    assert!(approx(&out[8..], &out[..8]));
+}
+
+#[test]
+fn test_volume_and_silence() {
+    let mut generator = generator(Waveform::Square);
+    generator.volume = 0.25;
+    assert!(samples(&mut generator, 2) == [0.25, 0.25]);
+    generator.playing = false;
+    assert!(samples(&mut generator, 2) == [0.0, 0.0]);
+}
+
+#[test]
+fn test_pattern_bit_order() {
+    // At the default pitch the pattern plays 4000 bits per second, one bit per sample here
+    let mut generator = generator(Waveform::Sine);
+    generator.sample_rate = 4000.0;
+    let mut pattern = [0; 16];
+    pattern[0] = 0b1000_0001;
+    pattern[15] = 0b0100_0000;
+    generator.pattern = Some(pattern);
+    let out = samples(&mut generator, 256);
+    let bits: Vec<usize> = (0..128).filter(|i| out[*i] > 0.0).collect();
+    // The most significant bit of the first byte plays first
+    assert!(bits == [0, 7, 121]);
+    assert!(out.iter().all(|s| s.abs() == 1.0));
+    assert!(out[128..] == out[..128]);
+}
@@ -0,0 +1,65 @@
+use super::*;
+
+#[test]
+fn test_platform_preset() {
+    let options = Chip8options::from_table(None, toml::Table::new());
+    assert!(options.platform == Platform::CosmacVip);
+    assert!(!options.opcode.shift_ignore_vy && options.opcode.store_load_mem_use_i);
+
+    let options = Chip8options::from_table(Some(Platform::XoChip), toml::Table::new());
+    assert!(options.platform == Platform::XoChip);
+    assert!(options.memory.mem_size == 65536);
+}
+
+#[test]
+fn test_platform_from_table() {
+    let table = "platform = \"super-chip-modern\"".parse().unwrap();
+    let options = Chip8options::from_table(None, table);
+    assert!(options.platform == Platform::SuperChipModern);
+    assert!(options.opcode.jump_w_offset_use_vx);
+
+    // The input platform takes precedence over the one in the table
+    let table = "platform = \"super-chip-modern\"".parse().unwrap();
+    let options = Chip8options::from_table(Some(Platform::Chip48), table);
+    assert!(options.platform == Platform::Chip48);
+}
+
+#[test]
+fn test_overrides() {
+    let table = "
+        platform = \"xo-chip\"
+        [display]
+        scaling = 5
+        [opcode]
+        shift_ignore_vy = true
+        [memory]
+        mem_size = 4096
+    "
+    .parse()
+    .unwrap();
+    let options = Chip8options::from_table(None, table);
+    assert!(options.display.scaling == 5 && options.display.display_width == 64);
+    assert!(options.opcode.shift_ignore_vy && options.opcode.store_load_mem_use_i);
+    assert!(options.memory.mem_size == 4096 && options.memory.rom_start == 0x200);
+    assert!(options.timing.cpu_cycles_per_display_tick == 1000);
+}
+
+#[test]
+fn test_invalid_overrides() {
+    let table = "[opcode]\nshift_ignore_vy = 3".parse().unwrap();
+    let options = Chip8options::from_table(Some(Platform::Chip48), table);
+    assert!(options.platform == Platform::Chip48 && options.opcode.shift_ignore_vy);
+}
+
+#[test]
+fn test_random_options() {
+    let options = Chip8options::default();
+    assert!(options.random.algorithm == RandomAlgorithm::Pcg && options.random.seed.is_none());
+
+    let table = "[random]\nalgorithm = \"8-bit\"\nseed = 42"
+        .parse()
+        .unwrap();
+    let options = Chip8options::from_table(None, table);
+    assert!(options.random.algorithm == RandomAlgorithm::EightBit);
+    assert!(options.random.seed == Some(42));
+}
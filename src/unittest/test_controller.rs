@@ -0,0 +1,33 @@
+use super::*;
+use crate::keybindings::Action;
+
+#[test]
+fn test_buttons_bound_to_one_key() {
+    // As in blitz.controller.toml, with a, b and dpdown all bound to key 5
+    let mut held = HeldButtons::default();
+    let key = Binding::Key(0x5);
+    assert!(held.press(0, "a", key) == Some(KeyEvent::KeyDown(0x5)));
+    assert!(held.press(0, "b", key).is_none());
+    assert!(held.press(1, "dpdown", key).is_none());
+    // Repeated presses and releases of buttons which aren't held are ignored
+    assert!(held.press(0, "a", key).is_none());
+    assert!(held.release(0, "x").is_none());
+    // The key stays down until the last of the buttons is released
+    assert!(held.release(0, "a").is_none());
+    assert!(held.release(1, "dpdown").is_none());
+    assert!(held.release(0, "b") == Some(KeyEvent::KeyUp(0x5)));
+    assert!(held.release(0, "b").is_none());
+}
+
+#[test]
+fn test_release_controller() {
+    let mut held = HeldButtons::default();
+    let rewind = Binding::Action(Action::Rewind);
+    assert!(held.press(0, "a", Binding::Key(0x5)).is_some());
+    assert!(held.press(0, "lefttrigger", rewind) == Some(KeyEvent::Rewind(true)));
+    assert!(held.press(1, "b", Binding::Key(0x5)).is_none());
+    // Unplugging the first controller leaves key 5 held by the second
+    assert!(held.release_controller(0) == [KeyEvent::Rewind(false)]);
+    assert!(held.release_controller(1) == [KeyEvent::KeyUp(0x5)]);
+    assert!(held.release_controller(1).is_empty());
+}
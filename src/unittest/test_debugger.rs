@@ -0,0 +1,146 @@
+use super::*;
+use crate::chip8options::Chip8options;
+use crate::headless::Headless;
+
+/// Runs the debugger with the commands, until they are all executed and the debugger is paused.
+fn run_commands(emulator: &mut Emulator, commands: &[&str]) -> Debugger {
+    let (sender, receiver) = mpsc::channel();
+    for c in commands {
+        sender.send(c.to_string()).unwrap();
+    }
+    drop(sender);
+    let mut debugger = Debugger::new();
+    debugger.run(emulator, &mut Headless::new(Vec::new()), &receiver);
+    debugger
+}
+
+#[test]
+fn test_parse_command() {
+    assert!(parse_command("c") == Ok(Command::Continue));
+    assert!(parse_command("step") == Ok(Command::Step(1)));
+    assert!(parse_command("s 10") == Ok(Command::Step(10)));
+    assert!(parse_command("b 0x2A0") == Ok(Command::Add(Breakpoint::Pc(0x2A0))));
+    assert!(
+        parse_command("watch 300 w")
+            == Ok(Command::Add(Breakpoint::Watch {
+                addr: 0x300,
+                access: Access::Write
+            }))
+    );
+    assert!(
+        parse_command("when vA >= 1F")
+            == Ok(Command::Add(Breakpoint::Condition {
+                reg: Register::V(0xA),
+                cmp: Comparison::Ge,
+                val: 0x1F
+            }))
+    );
+    assert!(
+        parse_command("x 200")
+            == Ok(Command::Examine {
+                addr: 0x200,
+                len: 16
+            })
+    );
+    assert!(parse_command("d 2") == Ok(Command::Delete(2)));
+}
+
+#[test]
+fn test_parse_command_errors() {
+    assert!(parse_command("jump").is_err());
+    assert!(parse_command("b").is_err());
+    assert!(parse_command("b xyz").is_err());
+    assert!(parse_command("watch 300 x").is_err());
+    assert!(parse_command("when vG == 1").is_err());
+    assert!(parse_command("when v1 = 1").is_err());
+}
+
+#[test]
+fn test_breakpoint() {
+    // V0 += 1, V1 += 1, jump to 0x200
+    let rom = [0x70, 0x01, 0x71, 0x01, 0x12, 0x00];
+    let mut emulator = Emulator::new(&rom, &Chip8options::default());
+    run_commands(&mut emulator, &["b 202", "c", "c"]);
+    assert!(emulator.pc() == 0x202 && emulator.registers()[0] == 2);
+}
+
+#[test]
+fn test_watchpoint() {
+    // I := 0x300, V0 := 0x7B, V1 += 1, BCD of V0, jump to 0x204
+    let rom = [0xA3, 0x00, 0x60, 0x7B, 0x71, 0x01, 0xF0, 0x33, 0x12, 0x04];
+    let mut emulator = Emulator::new(&rom, &Chip8options::default());
+    run_commands(&mut emulator, &["w 301 r", "w 302 w", "c"]);
+    assert!(emulator.pc() == 0x206 && emulator.memory()[0x302] == 0);
+    run_commands(&mut emulator, &["w 302 w", "s", "c"]);
+    assert!(emulator.pc() == 0x206 && emulator.memory()[0x302] == 3);
+    assert!(emulator.registers()[1] == 2);
+}
+
+#[test]
+fn test_watchpoint_wraps() {
+    // I := 0xFFE, V0 := 0x7B, BCD of V0 writing 0xFFE, 0xFFF and 0x000, jump to 0x206
+    let rom = [0xAF, 0xFE, 0x60, 0x7B, 0xF0, 0x33, 0x12, 0x06];
+    let mut emulator = Emulator::new(&rom, &Chip8options::default());
+    run_commands(&mut emulator, &["w 0 w", "c"]);
+    assert!(emulator.pc() == 0x204 && emulator.memory()[0xFFE] == 0);
+}
+
+#[test]
+fn test_condition() {
+    // V0 += 1, jump to 0x200
+    let rom = [0x70, 0x01, 0x12, 0x00];
+    let mut emulator = Emulator::new(&rom, &Chip8options::default());
+    run_commands(&mut emulator, &["when v0 == 3", "c"]);
+    assert!(emulator.registers()[0] == 3 && emulator.pc() == 0x202);
+    // Already true when added, so it breaks the next time it becomes true, after wrapping around
+    run_commands(&mut emulator, &["when v0 >= 3", "c"]);
+    assert!(emulator.registers()[0] == 3 && emulator.pc() == 0x202);
+    run_commands(&mut emulator, &["when v0 < 3", "c"]);
+    assert!(emulator.registers()[0] == 0);
+}
+
+#[test]
+fn test_invalid_opcode() {
+    // V0 := 1, invalid op code
+    let rom = [0x60, 0x01, 0xFF, 0xFF];
+    let mut emulator = Emulator::new(&rom, &Chip8options::default());
+    let mut debugger = Debugger::new();
+    assert!(debugger.check(&emulator).is_none());
+    emulator.step();
+    assert!(debugger.check(&emulator) == Some(PauseReason::InvalidOpCode));
+}
+
+#[test]
+fn test_step_over_and_out() {
+    // Call 0x206, V1 += 1, jump to 0x202, subroutine: V0 += 1, V0 += 1, return
+    let rom = [
+        0x22, 0x06, 0x71, 0x01, 0x12, 0x02, 0x70, 0x01, 0x70, 0x01, 0x00, 0xEE,
+    ];
+    let mut emulator = Emulator::new(&rom, &Chip8options::default());
+    run_commands(&mut emulator, &["n"]);
+    assert!(emulator.pc() == 0x202 && emulator.registers()[0] == 2);
+
+    let mut emulator = Emulator::new(&rom, &Chip8options::default());
+    run_commands(&mut emulator, &["s 2", "o"]);
+    assert!(emulator.pc() == 0x202 && emulator.stack().is_empty());
+    assert!(emulator.registers()[0] == 2 && emulator.registers()[1] == 0);
+}
+
+#[test]
+fn test_delete_breakpoint() {
+    let emulator = Emulator::new(&[0x12, 0x00], &Chip8options::default());
+    let mut debugger = Debugger::new();
+    let number = debugger.add_breakpoint(Breakpoint::Pc(0x200), &emulator);
+    assert!(debugger.check(&emulator) == Some(PauseReason::Breakpoint(number)));
+    assert!(debugger.delete_breakpoint(number));
+    assert!(!debugger.delete_breakpoint(number));
+    assert!(debugger.check(&emulator).is_none());
+}
+
+#[test]
+fn test_disassemble_around() {
+    let emulator = Emulator::new(&[0x60, 0x01, 0x12, 0x00], &Chip8options::default());
+    let listing = disassemble_around(&emulator, 0x200);
+    assert!(listing.contains("> 0200: 6001"));
+    assert!(listing.contains("  0202: 1200"));
+}
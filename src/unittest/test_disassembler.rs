@@ -0,0 +1,40 @@
+use super::*;
+
+#[test]
+fn test_labels_and_data() {
+    // I := 0x208, call 0x20A, jump to 0x206, data, subroutine: draw, return
+    let rom = [
+        0xA2, 0x08, 0x22, 0x0A, 0x00, 0xE0, 0x12, 0x06, 0x3C, 0x42, 0xD0, 0x12, 0x00, 0xEE,
+    ];
+    let source = disassemble(&rom, 0x200);
+    let lines: Vec<&str> = source.lines().map(|l| l.trim_end()).collect();
+    assert!(lines[0] == "    LD I, data_0208          ; 0200: A208");
+    assert!(lines[1] == "    CALL sub_020A            ; 0202: 220A");
+    assert!(lines[2] == "    CLS                      ; 0204: 00E0");
+    assert!(lines[3] == "loc_0206:");
+    assert!(lines[4] == "    JP loc_0206              ; 0206: 1206");
+    assert!(lines[5] == "data_0208:");
+    assert!(lines[6] == "    db 0x3C, 0x42            ; 0208");
+    assert!(lines[7] == "sub_020A:");
+    assert!(lines[8] == "    DRW V0, V1, 2            ; 020A: D012");
+    assert!(lines[9] == "    RET                      ; 020C: 00EE");
+}
+
+#[test]
+fn test_skips() {
+    // Skip over F000 NNNN, then exit
+    let rom = [0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x00, 0xFD, 0x12];
+    let source = disassemble(&rom, 0x200);
+    assert!(source.contains("LD I, LONG 0x1234"));
+    assert!(source.contains("EXIT"));
+    assert!(source.contains("db 0x12"));
+}
+
+#[test]
+fn test_target_inside_instruction() {
+    // Jump into the middle of the long index load
+    let rom = [0xF0, 0x00, 0x12, 0x03, 0x12, 0x03];
+    let source = disassemble(&rom, 0x200);
+    assert!(source.starts_with("loc_0203 = 0x203\n"));
+    assert!(source.contains("JP loc_0203"));
+}
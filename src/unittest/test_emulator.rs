@@ -1,4 +1,5 @@
 use super::*;
+use crate::chip8options::{Platform, RandomAlgorithm};
 
 #[test]
 fn test_jump() {
@@ -295,6 +296,7 @@ fn test_timers() {
 #[test]
 fn test_addtoindex() {
     let mut test_emulator = Emulator::new(&[0], &Chip8options::default());
+    test_emulator.options.opcode.add_index_overflow_vf = true;
     test_emulator.reg_vx[1] = 3;
     // Regular add
     test_emulator.reg_i = 8;
@@ -304,6 +306,12 @@ fn test_addtoindex() {
     test_emulator.reg_i = 0xFFF;
     test_emulator.execute_opcode(OpCode::AddToIndex { vx: 1 });
     assert!(test_emulator.reg_i == 2 && test_emulator.reg_vx[0xF] == 1);
+    // Add with overflow, VF unchanged
+    test_emulator.options.opcode.add_index_overflow_vf = false;
+    test_emulator.reg_vx[0xF] = 0;
+    test_emulator.reg_i = 0xFFF;
+    test_emulator.execute_opcode(OpCode::AddToIndex { vx: 1 });
+    assert!(test_emulator.reg_i == 2 && test_emulator.reg_vx[0xF] == 0);
 }
 
 #[test]
@@ -379,3 +387,484 @@ fn test_loadmemory() {
     assert!(test_emulator.reg_vx[3] == 0);
     assert!(test_emulator.reg_i == 0x303);
 }
+
+#[test]
+fn test_resolution() {
+    let mut test_emulator = Emulator::new(&[0], &Chip8options::default());
+    assert!(test_emulator.framebuffer.pixels().len() == 64 * 32);
+    test_emulator.execute_opcode(OpCode::HighRes);
+    assert!(test_emulator.hires);
+    assert!(test_emulator.framebuffer.width() == 128 && test_emulator.framebuffer.height() == 64);
+    assert!(test_emulator.framebuffer.pixels().len() == 128 * 64);
+    test_emulator.execute_opcode(OpCode::LowRes);
+    assert!(!test_emulator.hires);
+    assert!(test_emulator.framebuffer.pixels().len() == 64 * 32);
+}
+
+#[test]
+fn test_scroll() {
+    let mut test_emulator = Emulator::new(&[0], &Chip8options::default());
+    test_emulator.framebuffer.toggle(0, 0, framebuffer::PLANE_1);
+    test_emulator.execute_opcode(OpCode::ScrollDown(3));
+    let pixels = test_emulator.framebuffer.pixels();
+    assert!(pixels[0] == 0 && pixels[3 * 64] == 1);
+    test_emulator.execute_opcode(OpCode::ScrollRight);
+    assert!(test_emulator.framebuffer.pixels()[3 * 64 + 4] == 1);
+    test_emulator.execute_opcode(OpCode::ScrollUp(3));
+    assert!(test_emulator.framebuffer.pixels()[4] == 1);
+    test_emulator.execute_opcode(OpCode::ScrollLeft);
+    test_emulator.execute_opcode(OpCode::ScrollLeft);
+    assert!(test_emulator.framebuffer.pixels().iter().all(|p| *p == 0));
+}
+
+#[test]
+fn test_display_16x16() {
+    let mut test_emulator = Emulator::new(&[0], &Chip8options::default());
+    test_emulator.execute_opcode(OpCode::HighRes);
+    test_emulator.reg_i = 0x300;
+    for i in 0..32 {
+        test_emulator.memory[0x300 + i] = 0xFF;
+    }
+    test_emulator.execute_opcode(OpCode::Display {
+        vx: 0,
+        vy: 1,
+        val: 0,
+    });
+    let pixels = test_emulator.framebuffer.pixels();
+    let lit = pixels.iter().filter(|p| **p > 0).count();
+    assert!(lit == 16 * 16 && test_emulator.reg_vx[0xF] == 0);
+    assert!(pixels[15 + 15 * 128] == 1);
+    assert!(pixels[16] == 0);
+    test_emulator.execute_opcode(OpCode::Display {
+        vx: 0,
+        vy: 1,
+        val: 0,
+    });
+    assert!(test_emulator.framebuffer.pixels().iter().all(|p| *p == 0));
+    assert!(test_emulator.reg_vx[0xF] == 1);
+}
+
+#[test]
+fn test_bigfontchar() {
+    let mut test_emulator = Emulator::new(&[0], &Chip8options::default());
+    test_emulator.reg_vx[1] = 3;
+    test_emulator.execute_opcode(OpCode::BigFontCharacter { vx: 1 });
+    assert!(test_emulator.reg_i == test_emulator.options.memory.big_font_start + (10 * 3));
+}
+
+#[test]
+fn test_flags() {
+    let mut test_emulator = Emulator::new(&[0], &Chip8options::default());
+    test_emulator.reg_vx[0] = 11;
+    test_emulator.reg_vx[1] = 13;
+    test_emulator.reg_vx[2] = 15;
+    test_emulator.execute_opcode(OpCode::StoreFlags { vx: 1 });
+    test_emulator.reset();
+    assert!(test_emulator.reg_vx[0] == 0 && test_emulator.reg_vx[1] == 0);
+    test_emulator.execute_opcode(OpCode::LoadFlags { vx: 2 });
+    assert!(test_emulator.reg_vx[0] == 11);
+    assert!(test_emulator.reg_vx[1] == 13);
+    assert!(test_emulator.reg_vx[2] == 0);
+}
+
+#[test]
+fn test_exit() {
+    let mut test_emulator = Emulator::new(&[0], &Chip8options::default());
+    test_emulator.execute_opcode(OpCode::Exit);
+    assert!(test_emulator.halted);
+}
+
+#[test]
+fn test_display_planes() {
+    let mut test_emulator = Emulator::new(&[0], &Chip8options::default());
+    test_emulator.reg_i = 0x300;
+    test_emulator.memory[0x300] = 0x80;
+    test_emulator.memory[0x301] = 0xC0;
+    test_emulator.execute_opcode(OpCode::SelectPlanes(3));
+    test_emulator.execute_opcode(OpCode::Display {
+        vx: 0,
+        vy: 0,
+        val: 1,
+    });
+    let pixels = test_emulator.framebuffer.pixels();
+    assert!(pixels[0] == framebuffer::ALL_PLANES && pixels[1] == framebuffer::PLANE_2);
+    // Clearing only affects the selected planes
+    test_emulator.execute_opcode(OpCode::SelectPlanes(1));
+    test_emulator.execute_opcode(OpCode::ClearScreen);
+    let pixels = test_emulator.framebuffer.pixels();
+    assert!(pixels[0] == framebuffer::PLANE_2 && pixels[1] == framebuffer::PLANE_2);
+    // Collisions in the second plane set VF
+    test_emulator.execute_opcode(OpCode::SelectPlanes(2));
+    test_emulator.execute_opcode(OpCode::Display {
+        vx: 0,
+        vy: 0,
+        val: 1,
+    });
+    assert!(test_emulator.reg_vx[0xF] == 1);
+    assert!(test_emulator.framebuffer.pixels()[0] == 0);
+}
+
+#[test]
+fn test_store_load_range() {
+    let mut test_emulator = Emulator::new(&[0], &Chip8options::default());
+    test_emulator.reg_vx[2] = 11;
+    test_emulator.reg_vx[3] = 13;
+    test_emulator.reg_vx[4] = 15;
+    test_emulator.reg_i = 0x300;
+    test_emulator.execute_opcode(OpCode::StoreRange { vx: 2, vy: 4 });
+    assert!(test_emulator.memory[0x300..0x303] == [11, 13, 15]);
+    test_emulator.execute_opcode(OpCode::StoreRange { vx: 4, vy: 2 });
+    assert!(test_emulator.memory[0x300..0x303] == [15, 13, 11]);
+    assert!(test_emulator.reg_i == 0x300);
+    test_emulator.execute_opcode(OpCode::LoadRange { vx: 5, vy: 7 });
+    assert!(test_emulator.reg_vx[5..8] == [15, 13, 11]);
+}
+
+#[test]
+fn test_memory_wraps_at_top() {
+    // I at the top of the 64 KiB memory of XO-CHIP, the accesses wrap around to address 0
+    let mut test_emulator = Emulator::new(&[0], &Platform::XoChip.options());
+    let top = test_emulator.memory.len() - 8;
+    test_emulator.reg_i = top as u16;
+    for x in 0..16 {
+        test_emulator.reg_vx[x] = x as u8 + 1;
+    }
+    test_emulator.execute_opcode(OpCode::StoreRange { vx: 0, vy: 15 });
+    assert!(test_emulator.memory[top..] == [1, 2, 3, 4, 5, 6, 7, 8]);
+    assert!(test_emulator.memory[..8] == [9, 10, 11, 12, 13, 14, 15, 16]);
+    test_emulator.execute_opcode(OpCode::LoadRange { vx: 15, vy: 0 });
+    assert!(test_emulator.reg_vx[15] == 1 && test_emulator.reg_vx[0] == 16);
+    test_emulator.execute_opcode(OpCode::LoadAudioPattern);
+    assert!(
+        test_emulator
+            .audio_pattern
+            .is_some_and(|p| p[7] == 8 && p[8] == 9)
+    );
+
+    test_emulator.reg_i = u16::MAX;
+    test_emulator.reg_vx[0] = 123;
+    test_emulator.execute_opcode(OpCode::BinaryCodedDecimalConversion { vx: 0 });
+    assert!(test_emulator.memory[0xFFFF] == 1 && test_emulator.memory[..2] == [2, 3]);
+    test_emulator.options.opcode.store_load_mem_use_i = true;
+    test_emulator.execute_opcode(OpCode::LoadMemory { vx: 2 });
+    assert!(test_emulator.reg_vx[..3] == [1, 2, 3] && test_emulator.reg_i == 2);
+    test_emulator.reg_i = u16::MAX;
+    test_emulator.execute_opcode(OpCode::StoreMemory { vx: 1 });
+    assert!(test_emulator.memory[0xFFFF] == 1 && test_emulator.memory[0] == 2);
+}
+
+#[test]
+fn test_skip_long_index() {
+    let mut test_emulator = Emulator::new(
+        &[0x30, 0x00, 0xF0, 0x00, 0x12, 0x34],
+        &Chip8options::default(),
+    );
+    test_emulator.pc += 2;
+    test_emulator.execute_opcode(OpCode::SkipIfVxEq { vx: 0, val: 0 });
+    assert!(test_emulator.pc == 0x206);
+    test_emulator.execute_opcode(OpCode::SetIndexLong(0x1234));
+    assert!(test_emulator.reg_i == 0x1234);
+}
+
+#[test]
+fn test_audio_pattern() {
+    let mut test_emulator = Emulator::new(&[0], &Chip8options::default());
+    test_emulator.reg_i = 0x300;
+    test_emulator.memory[0x30F] = 0xAA;
+    test_emulator.execute_opcode(OpCode::LoadAudioPattern);
+    assert!(test_emulator.audio_pattern.is_some_and(|p| p[15] == 0xAA));
+    assert!(test_emulator.audio_updated);
+    test_emulator.reg_vx[1] = 100;
+    test_emulator.execute_opcode(OpCode::SetPitchToVx { vx: 1 });
+    assert!(test_emulator.pitch == 100);
+}
+
+#[test]
+fn test_logic_reset_vf() {
+    let mut test_emulator = Emulator::new(&[0], &Chip8options::default());
+    test_emulator.options.opcode.logic_reset_vf = false;
+    test_emulator.reg_vx[0xF] = 1;
+    test_emulator.execute_opcode(OpCode::BinaryOr { vx: 1, vy: 2 });
+    test_emulator.execute_opcode(OpCode::BinaryAnd { vx: 1, vy: 2 });
+    test_emulator.execute_opcode(OpCode::LogicalXor { vx: 1, vy: 2 });
+    assert!(test_emulator.reg_vx[0xF] == 1);
+}
+
+#[test]
+fn test_sprite_clipping() {
+    let mut test_emulator = Emulator::new(&[0], &Chip8options::default());
+    test_emulator.reg_i = 0x300;
+    test_emulator.memory[0x300] = 0xFF;
+    test_emulator.memory[0x301] = 0xFF;
+    test_emulator.reg_vx[0] = 60;
+    test_emulator.reg_vx[1] = 31;
+    test_emulator.options.opcode.sprite_clipping = true;
+    test_emulator.execute_opcode(OpCode::Display {
+        vx: 0,
+        vy: 1,
+        val: 2,
+    });
+    let lit = test_emulator
+        .framebuffer
+        .pixels()
+        .iter()
+        .filter(|p| **p > 0);
+    assert!(lit.count() == 4);
+
+    test_emulator.execute_opcode(OpCode::ClearScreen);
+    test_emulator.options.opcode.sprite_clipping = false;
+    test_emulator.execute_opcode(OpCode::Display {
+        vx: 0,
+        vy: 1,
+        val: 2,
+    });
+    let pixels = test_emulator.framebuffer.pixels();
+    assert!(pixels.iter().filter(|p| **p > 0).count() == 16);
+    // Wrapped around to the top left corner
+    assert!(pixels[0] == 1 && pixels[3] == 1 && pixels[4] == 0);
+}
+
+/// Random source counting up from 0xF0
+struct CountingSource(u8);
+
+impl RandomSource for CountingSource {
+    fn next_byte(&mut self) -> u8 {
+        self.0 = self.0.wrapping_add(1);
+        0xEF_u8.wrapping_add(self.0)
+    }
+}
+
+#[test]
+fn test_random_source() {
+    let mut test_emulator = Emulator::new(&[0], &Chip8options::default());
+    test_emulator.set_random_source(Box::new(CountingSource(0)));
+    test_emulator.execute_opcode(OpCode::Random { vx: 1, val: 0xFF });
+    test_emulator.execute_opcode(OpCode::Random { vx: 2, val: 0x0F });
+    test_emulator.execute_opcode(OpCode::Random { vx: 3, val: 0x00 });
+    assert!(test_emulator.reg_vx[1] == 0xF0);
+    assert!(test_emulator.reg_vx[2] == 0x01);
+    assert!(test_emulator.reg_vx[3] == 0x00);
+
+    // The external source isn't part of the save state, and keeps counting
+    let state = test_emulator.save_state();
+    test_emulator.load_state(&state);
+    test_emulator.execute_opcode(OpCode::Random { vx: 4, val: 0xFF });
+    assert!(test_emulator.reg_vx[4] == 0xF3);
+}
+
+#[test]
+fn test_random_seed() {
+    let mut options = Chip8options::default();
+    options.random.seed = Some(7);
+    for algorithm in [RandomAlgorithm::Pcg, RandomAlgorithm::EightBit] {
+        options.random.algorithm = algorithm;
+        let mut expected = random::from_seed(algorithm, 7);
+        let mut test_emulator = Emulator::new(&[0], &options);
+        assert!(test_emulator.seed() == 7);
+        for _ in 0..8 {
+            test_emulator.execute_opcode(OpCode::Random { vx: 0, val: 0xFF });
+            assert!(test_emulator.reg_vx[0] == expected.next_byte());
+        }
+        // Reseeding starts the sequence over
+        test_emulator.set_seed(7);
+        test_emulator.execute_opcode(OpCode::Random { vx: 0, val: 0x3C });
+        assert!(test_emulator.reg_vx[0] == random::from_seed(algorithm, 7).next_byte() & 0x3C);
+    }
+}
+
+#[test]
+fn test_step() {
+    // V0 := 5, invalid op code, exit
+    let mut test_emulator = Emulator::new(
+        &[0x60, 0x05, 0x81, 0x0D, 0x00, 0xFD],
+        &Chip8options::default(),
+    );
+    assert!(test_emulator.step() == Some(OpCode::Set { vx: 0, val: 5 }));
+    assert!(test_emulator.registers()[0] == 5 && test_emulator.pc() == 0x202);
+    assert!(test_emulator.step().is_none() && test_emulator.pc() == 0x204);
+    assert!(test_emulator.step() == Some(OpCode::Exit) && test_emulator.is_halted());
+    assert!(test_emulator.step().is_none() && test_emulator.pc() == 0x206);
+}
+
+#[test]
+fn test_run_frame() {
+    // Loop: V0 += 1, jump back
+    let mut test_emulator = Emulator::new(&[0x70, 0x01, 0x12, 0x00], &Chip8options::default());
+    test_emulator.options.timing.cpu_cycles_per_display_tick = 10;
+    test_emulator.delay_timer = 2;
+    assert!(test_emulator.run_frame() == FrameResult::Completed);
+    assert!(test_emulator.registers()[0] == 5 && test_emulator.delay_timer() == 1);
+    let result = test_emulator.run_frame_until(|e| e.registers()[0] == 7);
+    assert!(result == FrameResult::Stopped);
+    assert!(test_emulator.registers()[0] == 7 && test_emulator.delay_timer() == 0);
+}
+
+#[test]
+fn test_display_wait() {
+    // Sprite, V0 := 0, sprite, then loop
+    let rom = [0xD0, 0x01, 0x60, 0x00, 0xD0, 0x01, 0x12, 0x06];
+    let mut test_emulator = Emulator::new(&rom, &Chip8options::default());
+    test_emulator.options.opcode.display_wait = true;
+    test_emulator.run_frame();
+    assert!(test_emulator.pc() == 0x204);
+    test_emulator.run_frame();
+    assert!(test_emulator.pc() == 0x206);
+
+    let mut test_emulator = Emulator::new(&rom, &Chip8options::default());
+    test_emulator.options.opcode.display_wait = false;
+    test_emulator.run_frame();
+    assert!(test_emulator.pc() == 0x206);
+}
+
+#[test]
+fn test_rewind_depth() {
+    let mut test_emulator = Emulator::new(&[0x12, 0x00], &Chip8options::default());
+    test_emulator.options.timing.rewind_seconds = 1;
+    test_emulator.options.timing.display_frequency = 3;
+    for _ in 0..5 {
+        test_emulator.record_rewind_frame();
+    }
+    assert!(test_emulator.rewind_buffer.len() == 3);
+    assert!(test_emulator.rewind() && test_emulator.rewind() && test_emulator.rewind());
+    assert!(!test_emulator.rewind());
+
+    test_emulator.options.timing.rewind_seconds = 0;
+    test_emulator.record_rewind_frame();
+    assert!(test_emulator.rewind_buffer.is_empty());
+
+    // A depth beyond u32 saturates instead of overflowing
+    test_emulator.options.timing.rewind_seconds = u32::MAX;
+    test_emulator.options.timing.display_frequency = 60;
+    test_emulator.record_rewind_frame();
+    assert!(test_emulator.rewind_buffer.len() == 1);
+}
+
+#[test]
+fn test_memory_access() {
+    let mut test_emulator = Emulator::new(&[0], &Chip8options::default());
+    test_emulator.reg_i = 0x300;
+    assert!(
+        test_emulator.memory_access(OpCode::BinaryCodedDecimalConversion { vx: 0 })
+            == Some(MemoryAccess {
+                write: true,
+                range: 0x300..0x303,
+                wrapped: 0..0
+            })
+    );
+    assert!(
+        test_emulator.memory_access(OpCode::LoadRange { vx: 5, vy: 2 })
+            == Some(MemoryAccess {
+                write: false,
+                range: 0x300..0x304,
+                wrapped: 0..0
+            })
+    );
+    test_emulator.selected_planes = framebuffer::ALL_PLANES;
+    assert!(
+        test_emulator
+            .memory_access(OpCode::Display {
+                vx: 0,
+                vy: 0,
+                val: 0
+            })
+            .is_some_and(|a| a.range == (0x300..0x340))
+    );
+    assert!(test_emulator.memory_access(OpCode::Jump(0x300)).is_none());
+
+    // Accesses near the end of memory wrap around to the start
+    test_emulator.reg_i = 0xFFE;
+    let access = test_emulator.memory_access(OpCode::StoreMemory { vx: 3 });
+    assert!(
+        access
+            == Some(MemoryAccess {
+                write: true,
+                range: 0xFFE..0x1000,
+                wrapped: 0..2
+            })
+    );
+    assert!(access.is_some_and(|a| a.contains(0xFFF) && a.contains(1) && !a.contains(2)));
+}
+
+#[test]
+fn test_write_memory() {
+    let mut test_emulator = Emulator::new(&[0], &Chip8options::default());
+    assert!(test_emulator.write_memory(0xFFE, &[1, 2]));
+    assert!(test_emulator.memory[0xFFE..] == [1, 2]);
+    assert!(!test_emulator.write_memory(0xFFF, &[3, 4]));
+    assert!(!test_emulator.write_memory(usize::MAX, &[3]));
+    assert!(test_emulator.memory[0xFFF] == 2);
+}
+
+/// A system feeding queued key events, counting the screen updates and keeping the warnings
+#[derive(Default)]
+struct CountingSystem {
+    events: VecDeque<KeyEvent>,
+    screens: u32,
+    warnings: Vec<String>,
+}
+
+impl System for CountingSystem {
+    fn update_screen(&mut self, _framebuffer: &Framebuffer) {
+        self.screens += 1;
+    }
+
+    fn get_key_event(&mut self) -> Option<KeyEvent> {
+        self.events.pop_front()
+    }
+
+    fn show_warning(&mut self, message: &str) {
+        self.warnings.push(message.to_string());
+    }
+}
+
+#[test]
+fn test_warnings_shown_by_system() {
+    // Return with an empty stack, then loop at 0x202
+    let mut test_emulator = Emulator::new(&[0x00, 0xEE, 0x12, 0x02], &Chip8options::default());
+    let mut system = CountingSystem::default();
+    test_emulator.run_system_frame(&mut system, |_| false);
+    assert!(system.warnings == ["Return called with empty stack."]);
+    assert!(test_emulator.take_warnings().is_empty());
+
+    // Warnings from stepping are kept until taken
+    test_emulator.pc = 0x200;
+    test_emulator.step();
+    assert!(test_emulator.take_warnings().len() == 1);
+}
+
+#[test]
+fn test_speed_controls() {
+    // Clear the screen, jump to 0x200
+    let mut test_emulator = Emulator::new(&[0x00, 0xE0, 0x12, 0x00], &Chip8options::default());
+    let mut system = CountingSystem::default();
+    test_emulator.run_system_frame(&mut system, |_| false);
+    assert!(system.screens == 1);
+
+    // Paused frames show nothing until a frame advance
+    system.events.push_back(KeyEvent::Pause);
+    for _ in 0..3 {
+        test_emulator.run_system_frame(&mut system, |_| false);
+    }
+    assert!(test_emulator.is_paused() && test_emulator.frame() == 1 && system.screens == 1);
+    system.events.push_back(KeyEvent::FrameAdvance);
+    test_emulator.run_system_frame(&mut system, |_| false);
+    test_emulator.run_system_frame(&mut system, |_| false);
+    assert!(test_emulator.frame() == 2 && system.screens == 2);
+
+    // Turbo shows every 8th frame, and doesn't run while paused
+    system.events.push_back(KeyEvent::ToggleTurbo);
+    test_emulator.run_system_frame(&mut system, |_| false);
+    assert!(!test_emulator.is_turbo());
+    system.events.push_back(KeyEvent::Pause);
+    for _ in 0..14 {
+        test_emulator.run_system_frame(&mut system, |_| false);
+    }
+    assert!(test_emulator.is_turbo() && test_emulator.frame() == 16 && system.screens == 4);
+    system.events.push_back(KeyEvent::ToggleTurbo);
+    test_emulator.run_system_frame(&mut system, |_| false);
+    assert!(!test_emulator.is_turbo() && system.screens == 5);
+
+    system.events.push_back(KeyEvent::SlowMotion);
+    test_emulator.run_system_frame(&mut system, |_| false);
+    assert!(test_emulator.is_slow_motion());
+}
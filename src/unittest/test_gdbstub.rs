@@ -0,0 +1,162 @@
+use super::*;
+use crate::chip8options::Chip8options;
+use crate::headless::Headless;
+
+// LD V0, 0x05; LD I, 0x20A; CALL 0x208; JP 0x206; RET
+const PROGRAM: [u8; 10] = [0x60, 0x05, 0xA2, 0x0A, 0x22, 0x08, 0x12, 0x06, 0x00, 0xEE];
+
+/// Client side of the connection
+struct Client(TcpStream);
+
+impl Client {
+    /// Sends a packet and returns the reply packet.
+    fn request(&mut self, data: &str) -> String {
+        let packet = format!("${data}#{:02x}", checksum(data.as_bytes()));
+        self.0.write_all(packet.as_bytes()).unwrap();
+        self.reply()
+    }
+
+    /// Reads the next packet, skipping the acknowledgments.
+    fn reply(&mut self) -> String {
+        let mut data = Vec::new();
+        let mut byte = [0];
+        loop {
+            self.0.read_exact(&mut byte).unwrap();
+            match byte[0] {
+                b'+' if data.is_empty() => (),
+                b'#' => break,
+                b'$' => data.clear(),
+                b => data.push(b),
+            }
+        }
+        let mut sum = [0; 2];
+        self.0.read_exact(&mut sum).unwrap();
+        assert!(u8::from_str_radix(std::str::from_utf8(&sum).unwrap(), 16) == Ok(checksum(&data)));
+        String::from_utf8(data).unwrap()
+    }
+}
+
+/// Runs the stub on the program, with the client session in another thread.
+/// Returns the replies of the session.
+fn run_session<F>(session: F) -> Vec<String>
+where
+    F: FnOnce(&mut Client) -> Vec<String> + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let client = std::thread::spawn(move || {
+        let mut client = Client(TcpStream::connect(("127.0.0.1", port)).unwrap());
+        let replies = session(&mut client);
+        client.0.write_all(b"$k#6b").unwrap();
+        replies
+    });
+    let (stream, _) = listener.accept().unwrap();
+    let mut emulator = Emulator::new(&PROGRAM, &Chip8options::default());
+    let mut system = Headless::new(Vec::new());
+    GdbStub::new(stream)
+        .unwrap()
+        .run(&mut emulator, &mut system)
+        .unwrap();
+    client.join().unwrap()
+}
+
+#[test]
+fn test_registers_and_memory() {
+    let replies = run_session(|c| {
+        vec![
+            c.request("qSupported:swbreak+"),
+            c.request("?"),
+            c.request("g"),
+            c.request("s"),
+            c.request("p11"),
+            c.request("P3=2a"),
+            c.request("P10=3412"),
+            c.request("p3"),
+            c.request("p10"),
+            c.request("m200,4"),
+            c.request("M300,2:abcd"),
+            c.request("m300,2"),
+            c.request("M1000,1:00"),
+            c.request("vMustReplyEmpty"),
+            c.request("m1,ffffffffffffffff"),
+            c.request("M1,ffffffffffffffff:00"),
+            c.request("m0,2000"),
+            c.request("qXfer:features:read:target.xml:1,ffffffffffffffff"),
+        ]
+    });
+    assert!(replies[0].contains("qXfer:features:read+"));
+    assert!(replies[1] == "S05");
+    assert!(replies[2] == format!("{}00000002000000", "00".repeat(16)));
+    assert!(replies[3] == "S05");
+    assert!(replies[4] == "0202");
+    assert!(replies[5] == "OK" && replies[6] == "OK");
+    assert!(replies[7] == "2a");
+    assert!(replies[8] == "3412");
+    assert!(replies[9] == "6005a20a");
+    assert!(replies[10] == "OK");
+    assert!(replies[11] == "abcd");
+    assert!(replies[12] == "E01");
+    assert!(replies[13].is_empty());
+    // Overflowing ranges are rejected, and the replies fit in a packet
+    assert!(replies[14] == "E01" && replies[15] == "E01");
+    assert!(replies[16].len() == PACKET_SIZE);
+    assert!(replies[17] == "E01");
+}
+
+#[test]
+fn test_breakpoints_and_interrupt() {
+    let replies = run_session(|c| {
+        let mut replies = vec![
+            c.request("Z0,208,2"),
+            c.request("c"),
+            c.request("p11"),
+            c.request("p12"),
+            c.request("z0,208,2"),
+        ];
+        // The program loops at 0x206 until interrupted
+        c.0.write_all(b"$c#63").unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        c.0.write_all(&[0x03]).unwrap();
+        replies.push(c.reply());
+        replies.push(c.request("p11"));
+        replies
+    });
+    assert!(replies[0] == "OK");
+    assert!(replies[1] == "T05swbreak:;");
+    assert!(replies[2] == "0802");
+    assert!(replies[3] == "01");
+    assert!(replies[4] == "OK");
+    assert!(replies[5] == "S02");
+    assert!(replies[6] == "0602");
+}
+
+#[test]
+fn test_watchpoint() {
+    let replies = run_session(|c| {
+        vec![
+            // Replace the jump with LD B, V0 writing to I = 0x20A..0x20D
+            c.request("M206,2:f033"),
+            c.request("Z2,20b,1"),
+            c.request("c"),
+            c.request("p11"),
+        ]
+    });
+    assert!(replies[1] == "OK");
+    assert!(replies[2] == "T05watch:20b;");
+    assert!(replies[3] == "0602");
+}
+
+#[test]
+fn test_target_xml() {
+    let replies = run_session(|c| {
+        vec![
+            c.request("qXfer:features:read:target.xml:0,20"),
+            c.request("qXfer:features:read:target.xml:0,1000"),
+        ]
+    });
+    assert!(replies[0].starts_with("m<?xml") && replies[0].len() == 0x21);
+    assert!(replies[1].starts_with('l') && replies[1][1..] == target_xml());
+    assert!(
+        replies[1].contains("<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\" regnum=\"17\"/>")
+    );
+}
@@ -0,0 +1,137 @@
+use super::*;
+
+/// Decodes the LZW codes of a GIF image, as a GIF decoder does.
+fn decode_lzw(data: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1usize << min_code_size;
+    let end = clear + 1;
+    let mut pos = 0;
+    let mut read = |bits: u8| {
+        let mut code = 0;
+        for i in 0..bits as usize {
+            let bit = (data[(pos + i) / 8] >> ((pos + i) % 8)) & 1;
+            code |= (bit as usize) << i;
+        }
+        pos += bits as usize;
+        code
+    };
+    let mut table: Vec<Vec<u8>> = Vec::new();
+    let mut code_size = min_code_size + 1;
+    let mut previous: Option<usize> = None;
+    let mut out = Vec::new();
+    loop {
+        let code = read(code_size);
+        if code == clear {
+            table = (0..clear).map(|c| vec![c as u8]).collect();
+            table.extend([vec![], vec![]]);
+            code_size = min_code_size + 1;
+            previous = None;
+            continue;
+        }
+        if code == end {
+            return out;
+        }
+        let entry = match previous {
+            None => table[code].clone(),
+            Some(p) => {
+                let entry = if code < table.len() {
+                    table[code].clone()
+                } else {
+                    let mut e = table[p].clone();
+                    e.push(table[p][0]);
+                    e
+                };
+                if table.len() < 4096 {
+                    let mut new = table[p].clone();
+                    new.push(entry[0]);
+                    table.push(new);
+                    if table.len() == 1 << code_size && code_size < 12 {
+                        code_size += 1;
+                    }
+                }
+                entry
+            }
+        };
+        out.extend(&entry);
+        previous = Some(code);
+    }
+}
+
+/// Pseudo-random pixels, to fill up the code table
+fn noise(len: usize, colors: u32) -> Vec<u8> {
+    let mut x: u32 = 12345;
+    (0..len)
+        .map(|_| {
+            x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            ((x >> 16) % colors) as u8
+        })
+        .collect()
+}
+
+#[test]
+fn test_lzw_roundtrip() {
+    let mut runs = vec![0; 2000];
+    runs.extend([3; 500]);
+    runs.extend((0..1000).map(|i| (i % 4) as u8));
+    for pixels in [vec![], vec![2], vec![1, 1], runs, noise(50_000, 4)] {
+        assert!(decode_lzw(&lzw(&pixels, 2), 2) == pixels);
+    }
+    let pixels = noise(50_000, 256);
+    assert!(decode_lzw(&lzw(&pixels, 8), 8) == pixels);
+}
+
+#[test]
+fn test_lzw_compresses_runs() {
+    assert!(lzw(&[0; 10_000], 2).len() < 200);
+}
+
+#[test]
+fn test_encoder() {
+    let palette = [(0, 0, 0), (255, 255, 255), (85, 85, 85), (170, 170, 170)];
+    let mut encoder = Encoder::new(Vec::new(), 3, 2, &palette).unwrap();
+    encoder.write_frame(&[0, 1, 2, 3, 2, 1], 2).unwrap();
+    encoder.write_frame(&[1; 6], 300).unwrap();
+    let gif = encoder.finish().unwrap();
+
+    assert!(gif[..13] == [b'G', b'I', b'F', b'8', b'9', b'a', 3, 0, 2, 0, 0xF1, 0, 0]);
+    assert!(gif[13..25] == [0, 0, 0, 255, 255, 255, 85, 85, 85, 170, 170, 170]);
+    assert!(gif[25..28] == [0x21, 0xFF, 11] && &gif[28..39] == b"NETSCAPE2.0");
+    assert!(gif.last() == Some(&0x3B));
+
+    // Walk the frames: graphic control extension, image descriptor and the data sub-blocks
+    let mut pos = 44;
+    let mut frames = Vec::new();
+    while gif[pos] == 0x21 {
+        assert!(gif[pos..pos + 4] == [0x21, 0xF9, 4, 0x04]);
+        let delay = u16::from_le_bytes([gif[pos + 4], gif[pos + 5]]);
+        pos += 8;
+        assert!(gif[pos..pos + 10] == [0x2C, 0, 0, 0, 0, 3, 0, 2, 0, 0]);
+        let min_code_size = gif[pos + 10];
+        pos += 11;
+        let mut data = Vec::new();
+        while gif[pos] != 0 {
+            let len = gif[pos] as usize;
+            data.extend(&gif[pos + 1..pos + 1 + len]);
+            pos += len + 1;
+        }
+        pos += 1;
+        frames.push((delay, decode_lzw(&data, min_code_size)));
+    }
+    assert!(pos == gif.len() - 1);
+    assert!(frames == vec![(2, vec![0, 1, 2, 3, 2, 1]), (300, vec![1; 6])]);
+}
+
+#[test]
+fn test_encoder_palette_size() {
+    // The palette is padded to a power of two, with at least four colors
+    let gif = Encoder::new(Vec::new(), 1, 1, &[(1, 2, 3)])
+        .unwrap()
+        .finish()
+        .unwrap();
+    assert!(gif[10] == 0xF1 && gif[13..16] == [1, 2, 3] && gif[16..25] == [0; 9]);
+    let palette = [(9, 9, 9); 5];
+    let gif = Encoder::new(Vec::new(), 1, 1, &palette)
+        .unwrap()
+        .finish()
+        .unwrap();
+    assert!(gif[10] == 0xF2 && gif.len() == 13 + 8 * 3 + 19 + 1);
+}
@@ -0,0 +1,243 @@
+use super::*;
+use crate::chip8options::Chip8options;
+use crate::output::SharedBuffer;
+
+#[test]
+fn test_parse_key_script() {
+    let script = "# comment\n\n10 down 5\n 12 up F\n20 restart\n30 quit\n40 save\n50 load\n";
+    let events = parse_key_script(script).unwrap();
+    assert!(
+        events
+            == vec![
+                (10, KeyEvent::KeyDown(5)),
+                (12, KeyEvent::KeyUp(0xF)),
+                (20, KeyEvent::Restart),
+                (30, KeyEvent::Quit),
+                (40, KeyEvent::SaveState),
+                (50, KeyEvent::LoadState),
+            ]
+    );
+}
+
+#[test]
+fn test_format_key_event() {
+    let events = [
+        KeyEvent::KeyDown(0xA),
+        KeyEvent::KeyUp(3),
+        KeyEvent::Restart,
+        KeyEvent::Quit,
+        KeyEvent::SaveState,
+        KeyEvent::LoadState,
+        KeyEvent::Rewind(true),
+        KeyEvent::Rewind(false),
+        KeyEvent::Screenshot,
+        KeyEvent::Record,
+        KeyEvent::Pause,
+        KeyEvent::FrameAdvance,
+        KeyEvent::Turbo(true),
+        KeyEvent::Turbo(false),
+        KeyEvent::ToggleTurbo,
+        KeyEvent::SlowMotion,
+    ];
+    let script: String = events
+        .iter()
+        .map(|e| format!("7 {}\n", format_key_event(*e)))
+        .collect();
+    assert!(script.starts_with("7 down A\n7 up 3\n"));
+    let parsed = parse_key_script(&script).unwrap();
+    assert!(parsed.iter().map(|(_, e)| *e).eq(events));
+}
+
+#[test]
+fn test_parse_key_script_errors() {
+    assert!(parse_key_script("x down 5").is_err_and(|e| e.contains("line 1")));
+    assert!(parse_key_script("\n10 down 10").is_err_and(|e| e.contains("line 2")));
+    assert!(parse_key_script("10 down").is_err());
+    assert!(
+        parse_key_script("10 press 1")
+            .is_err_and(|e| e.contains("pause") && e.contains("slow-motion"))
+    );
+}
+
+#[test]
+fn test_run_frames() {
+    // Infinite loop at 0x200
+    let mut emulator = Emulator::new(&[0x12, 0x00], &Chip8options::default());
+    let mut headless = Headless::new(Vec::new());
+    let limits = Limits {
+        frames: Some(5),
+        until_pc: None,
+    };
+    assert!(headless.run(&mut emulator, limits) == StopReason::FrameLimit);
+    assert!(headless.frame() == 5);
+}
+
+#[test]
+fn test_run_until_pc() {
+    // V0 := 1, V1 := 2, jump to 0x200
+    let rom = [0x60, 0x01, 0x61, 0x02, 0x12, 0x00];
+    let mut emulator = Emulator::new(&rom, &Chip8options::default());
+    let mut headless = Headless::new(Vec::new());
+    let limits = Limits {
+        frames: Some(5),
+        until_pc: Some(0x204),
+    };
+    assert!(headless.run(&mut emulator, limits) == StopReason::PcReached);
+    assert!(emulator.pc() == 0x204 && emulator.registers()[1] == 2);
+}
+
+#[test]
+fn test_run_halted_and_keys() {
+    // Wait for key into V0, then exit
+    let rom = [0xF0, 0x0A, 0x00, 0xFD];
+    let mut emulator = Emulator::new(&rom, &Chip8options::default());
+    let mut headless = Headless::new(vec![(3, KeyEvent::KeyDown(7))]);
+    let limits = Limits {
+        frames: Some(10),
+        until_pc: None,
+    };
+    assert!(headless.run(&mut emulator, limits) == StopReason::Halted);
+    assert!(headless.frame() == 4 && emulator.registers()[0] == 7);
+
+    let mut emulator = Emulator::new(&rom, &Chip8options::default());
+    let mut headless = Headless::new(vec![(2, KeyEvent::Quit)]);
+    assert!(headless.run(&mut emulator, limits) == StopReason::Quit);
+}
+
+#[test]
+fn test_dump_state() {
+    let mut emulator = Emulator::new(&[0x12, 0x00], &Chip8options::default());
+    emulator.set_key(0, true);
+    let dump = dump_state(&emulator);
+    assert!(dump.starts_with("PC: 0200  I: 0000  SP: 0"));
+    assert!(dump.contains("Framebuffer 64x32:\n"));
+    assert!(dump.lines().count() == 4 + 32);
+}
+
+#[test]
+fn test_run_save_and_load_state() {
+    // V0 += 1, jump to 0x200
+    let rom = [0x70, 0x01, 0x12, 0x00];
+    let limits = Limits {
+        frames: Some(3),
+        until_pc: None,
+    };
+    let mut emulator = Emulator::new(&rom, &Chip8options::default());
+    // Saved after frame 0, and restored before frame 2
+    let mut headless = Headless::new(vec![(1, KeyEvent::SaveState), (2, KeyEvent::LoadState)]);
+    headless.run(&mut emulator, limits);
+
+    let mut reference = Emulator::new(&rom, &Chip8options::default());
+    let mut reference_headless = Headless::new(Vec::new());
+    reference_headless.run(
+        &mut reference,
+        Limits {
+            frames: Some(2),
+            until_pc: None,
+        },
+    );
+    assert!(emulator.registers() == reference.registers() && emulator.pc() == reference.pc());
+}
+
+#[test]
+fn test_run_rewind() {
+    // V0 += 1, jump to 0x200
+    let rom = [0x70, 0x01, 0x12, 0x00];
+    let limits = Limits {
+        frames: Some(3),
+        until_pc: None,
+    };
+    let mut reference = Emulator::new(&rom, &Chip8options::default());
+    Headless::new(Vec::new()).run(&mut reference, limits);
+
+    // Run 5 frames, rewind 3 frames back to the state after 2 frames, and run one more
+    let script = vec![(5, KeyEvent::Rewind(true)), (8, KeyEvent::Rewind(false))];
+    let mut emulator = Emulator::new(&rom, &Chip8options::default());
+    let mut headless = Headless::new(script);
+    headless.run(
+        &mut emulator,
+        Limits {
+            frames: Some(9),
+            until_pc: None,
+        },
+    );
+    assert!(emulator.registers() == reference.registers() && emulator.pc() == reference.pc());
+}
+
+#[test]
+fn test_run_pause_and_advance() {
+    // V0 += 1, jump to 0x200
+    let rom = [0x70, 0x01, 0x12, 0x00];
+    let limits = Limits {
+        frames: Some(4),
+        until_pc: None,
+    };
+    let mut reference = Emulator::new(&rom, &Chip8options::default());
+    Headless::new(Vec::new()).run(&mut reference, limits);
+
+    // Run 2 frames, pause for 5 frames advancing one frame, and resume for 1 frame
+    let script = vec![
+        (2, KeyEvent::Pause),
+        (4, KeyEvent::FrameAdvance),
+        (7, KeyEvent::Pause),
+    ];
+    let mut emulator = Emulator::new(&rom, &Chip8options::default());
+    let mut headless = Headless::new(script);
+    headless.run(
+        &mut emulator,
+        Limits {
+            frames: Some(6),
+            until_pc: None,
+        },
+    );
+    assert!(emulator.is_paused());
+    assert!(emulator.frame() == 3);
+    headless.run(
+        &mut emulator,
+        Limits {
+            frames: Some(8),
+            until_pc: None,
+        },
+    );
+    assert!(!emulator.is_paused());
+    assert!(emulator.frame() == 4);
+    assert!(emulator.registers() == reference.registers() && emulator.pc() == reference.pc());
+}
+
+#[test]
+fn test_run_recording() {
+    // Draw the font sprite of V0, V0 += 1, clear the screen, jump to 0x200
+    let rom = [0xF0, 0x29, 0xD1, 0x15, 0x70, 0x01, 0x00, 0xE0, 0x12, 0x00];
+    let mut options = Chip8options::default();
+    options.opcode.display_wait = true;
+    let mut emulator = Emulator::new(&rom, &options);
+    let buffer = SharedBuffer::default();
+    emulator.start_recording(Box::new(buffer.clone())).unwrap();
+    assert!(emulator.is_recording());
+    let limits = Limits {
+        frames: Some(30),
+        until_pc: None,
+    };
+    // The key script stops the recording after 10 frames, and records frames 20-29 in memory
+    let mut headless = Headless::new(vec![(10, KeyEvent::Record), (20, KeyEvent::Record)]);
+    headless.run(&mut emulator, limits);
+    assert!(emulator.is_recording());
+    emulator.stop_recording().unwrap();
+    assert!(!emulator.is_recording());
+
+    let recordings = headless.recordings();
+    assert!(recordings.len() == 1);
+    // The delays add up to the times of the start and stop frames at 60 Hz, in hundredths of a
+    // second, however the updates are merged into frames
+    let expected = [(buffer.contents(), 16), (recordings[0].clone(), 50 - 33)];
+    for (gif, duration) in expected {
+        assert!(gif.starts_with(b"GIF89a") && gif.last() == Some(&0x3B));
+        let delays: u32 = gif
+            .windows(4)
+            .enumerate()
+            .filter(|(_, w)| *w == [0x21, 0xF9, 4, 0x04])
+            .map(|(i, _)| u16::from_le_bytes([gif[i + 4], gif[i + 5]]) as u32)
+            .sum();
+        assert!(delays == duration);
+    }
+}
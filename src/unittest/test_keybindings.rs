@@ -0,0 +1,166 @@
+use super::*;
+
+fn options(toml: &str) -> KeyboardOptions {
+    KeyboardOptions::from_table(&toml.parse().unwrap())
+}
+
+#[test]
+fn test_default_bindings() {
+    let bindings = KeyBindings::default();
+    assert!(bindings.lookup("X") == Some(Binding::Key(0x0)));
+    assert!(bindings.lookup("1") == Some(Binding::Key(0x1)));
+    assert!(bindings.lookup("q") == Some(Binding::Key(0x4)));
+    assert!(bindings.lookup("V") == Some(Binding::Key(0xF)));
+    assert!(bindings.lookup("Escape") == Some(Binding::Action(Action::Quit)));
+    assert!(bindings.lookup("F5") == Some(Binding::Action(Action::Restart)));
+    assert!(bindings.lookup("backspace") == Some(Binding::Action(Action::Rewind)));
+    assert!(bindings.lookup("Y").is_none());
+    assert!(bindings.lookup("F12") == Some(Binding::Action(Action::Screenshot)));
+    assert!(bindings.lookup("F11") == Some(Binding::Action(Action::Record)));
+    assert!(bindings.lookup("F6") == Some(Binding::Action(Action::Pause)));
+    assert!(bindings.lookup("F7") == Some(Binding::Action(Action::FrameAdvance)));
+    assert!(bindings.lookup("tab") == Some(Binding::Action(Action::Turbo)));
+    assert!(bindings.lookup("F8") == Some(Binding::Action(Action::ToggleTurbo)));
+    assert!(bindings.lookup("F9") == Some(Binding::Action(Action::SlowMotion)));
+    for (k, name) in Layout::Qwerty.keys().iter().enumerate() {
+        assert!(bindings.lookup(name) == Some(Binding::Key(k as u8)));
+    }
+}
+
+#[test]
+fn test_missing_section_uses_defaults() {
+    assert!(options("platform = \"xo-chip\"") == KeyboardOptions::default());
+}
+
+#[test]
+fn test_numpad_layout() {
+    let options = options("[keyboard]\nlayout = \"numpad\"");
+    let bindings = KeyBindings::new(&options, None);
+    assert!(bindings.lookup("Keypad 0") == Some(Binding::Key(0x0)));
+    assert!(bindings.lookup("Keypad 9") == Some(Binding::Key(0x9)));
+    assert!(bindings.lookup("Keypad /") == Some(Binding::Key(0xA)));
+    assert!(bindings.lookup("Keypad Enter") == Some(Binding::Key(0xE)));
+    assert!(bindings.lookup("Keypad .") == Some(Binding::Key(0xF)));
+    assert!(bindings.lookup("Q").is_none());
+}
+
+#[test]
+fn test_rebound_keys_and_actions() {
+    // AZERTY keyboard
+    let options = options(
+        r#"
+        [keyboard]
+        keys = { 4 = "A", 5 = "Z", 7 = "Q", a = "W", 0 = "X" }
+        quit = "F10"
+        restart = "Q"
+        "#,
+    );
+    let bindings = KeyBindings::new(&options, None);
+    assert!(bindings.lookup("A") == Some(Binding::Key(0x4)));
+    assert!(bindings.lookup("W") == Some(Binding::Key(0xA)));
+    assert!(bindings.lookup("Z") == Some(Binding::Key(0x5)));
+    // Bound to both a CHIP-8 key and an action, the CHIP-8 key wins
+    assert!(bindings.lookup("Q") == Some(Binding::Key(0x7)));
+    assert!(bindings.lookup("F10") == Some(Binding::Action(Action::Quit)));
+    assert!(bindings.lookup("Escape").is_none());
+}
+
+#[test]
+fn test_duplicate_keys() {
+    let mut keys = Layout::Qwerty.keys().map(|k| k.to_string());
+    assert!(duplicate_keys(&keys).is_empty());
+    // "W" moved to key A without moving key 5 away from it
+    keys[0xA] = "w".to_string();
+    assert!(duplicate_keys(&keys) == [("w", 0x5, 0xA)]);
+    let options = options("[keyboard]\nkeys = { A = \"W\" }");
+    let bindings = KeyBindings::new(&options, None);
+    assert!(bindings.lookup("W") == Some(Binding::Key(0xA)));
+    // Key 5 is left unbound
+    let keys = Layout::Qwerty.keys();
+    assert!(
+        keys.iter()
+            .all(|k| bindings.lookup(k) != Some(Binding::Key(0x5)))
+    );
+}
+
+#[test]
+fn test_rom_overrides() {
+    let options = options(
+        r#"
+        [keyboard]
+        keys = { 5 = "Up" }
+
+        [keyboard.rom.blitz]
+        keys = { 5 = "Space", g = "G" }
+
+        [keyboard.rom.brix]
+        layout = "numpad"
+        "#,
+    );
+    let blitz = KeyBindings::new(&options, Some("blitz"));
+    assert!(blitz.lookup("Space") == Some(Binding::Key(0x5)));
+    assert!(blitz.lookup("Up").is_none());
+    assert!(blitz.lookup("G").is_none());
+
+    let brix = KeyBindings::new(&options, Some("brix"));
+    assert!(brix.lookup("Keypad 4") == Some(Binding::Key(0x4)));
+    assert!(brix.lookup("Up") == Some(Binding::Key(0x5)));
+
+    let other = KeyBindings::new(&options, Some("pong"));
+    assert!(other.lookup("Up") == Some(Binding::Key(0x5)));
+    assert!(other.lookup("W").is_none());
+}
+
+#[test]
+fn test_invalid_section_uses_defaults() {
+    assert!(options("[keyboard]\nlayout = \"dvorak\"") == KeyboardOptions::default());
+}
+
+#[test]
+fn test_default_controller_bindings() {
+    let bindings = ControllerBindings::default();
+    assert!(bindings.lookup("dpup") == Some(Binding::Key(0x5)));
+    assert!(bindings.lookup("dpright") == Some(Binding::Key(0x9)));
+    assert!(bindings.lookup("a") == Some(Binding::Key(0x6)));
+    assert!(bindings.lookup("start").is_none());
+}
+
+#[test]
+fn test_controller_bindings() {
+    let table: toml::Table = r#"
+        [controller.buttons]
+        a = "c"
+        start = "restart"
+        lefttrigger = "Rewind"
+        nosuchbutton = "1"
+        x = "jump"
+        "#
+    .parse()
+    .unwrap();
+    let bindings = ControllerBindings::new(&ControllerOptions::from_table(&table));
+    assert!(bindings.lookup("a") == Some(Binding::Key(0xC)));
+    assert!(bindings.lookup("start") == Some(Binding::Action(Action::Restart)));
+    assert!(bindings.lookup("lefttrigger") == Some(Binding::Action(Action::Rewind)));
+    assert!(bindings.lookup("nosuchbutton").is_none());
+    assert!(bindings.lookup("x").is_none());
+    // The section replaces the default buttons
+    assert!(bindings.lookup("dpup").is_none());
+}
+
+#[test]
+fn test_controller_profile() {
+    let dir = std::env::temp_dir().join("chip8_test_controller_profile");
+    std::fs::create_dir_all(&dir).unwrap();
+    let profile = dir.join("game.controller.toml");
+    std::fs::write(&profile, "[buttons]\ndpup = \"2\"\nstart = \"quit\"\n").unwrap();
+
+    let options = ControllerOptions::default().with_profile_file(&profile);
+    let bindings = ControllerBindings::new(&options);
+    assert!(bindings.lookup("dpup") == Some(Binding::Key(0x2)));
+    assert!(bindings.lookup("start") == Some(Binding::Action(Action::Quit)));
+    assert!(bindings.lookup("dpdown") == Some(Binding::Key(0x8)));
+
+    let missing = ControllerOptions::default().with_profile_file(&dir.join("missing.toml"));
+    assert!(missing == ControllerOptions::default());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
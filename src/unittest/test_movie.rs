@@ -0,0 +1,178 @@
+use super::*;
+use crate::chip8options::Platform;
+use crate::emulator::{Emulator, System};
+use crate::framebuffer::Framebuffer;
+use crate::headless::{self, Headless, KeyScript, Limits};
+use crate::output::SharedBuffer;
+use crate::savestate::SaveState;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+// LD V1, 5; SKP V1; JP 0x202; RND V2, 0xFF; ADD V3, V2; JP 0x202
+const PROGRAM: [u8; 12] = [
+    0x61, 0x05, 0xE1, 0x9E, 0x12, 0x02, 0xC2, 0xFF, 0x83, 0x24, 0x12, 0x02,
+];
+
+const LIMITS: Limits = Limits {
+    frames: Some(20),
+    until_pc: None,
+};
+
+#[test]
+fn test_record_and_play_back() {
+    let options = Platform::XoChip.options();
+    let buffer = SharedBuffer::default();
+    let mut emulator = Emulator::new(&PROGRAM, &options);
+    let recorder = Recorder::new(
+        Box::new(buffer.clone()),
+        &PROGRAM,
+        emulator.seed(),
+        &options,
+    )
+    .unwrap();
+    emulator.set_recorder(Some(recorder));
+    let script = headless::parse_key_script("3 down 5\n6 up 5\n10 down 5\n11 up 5").unwrap();
+    Headless::new(script).run(&mut emulator, LIMITS);
+    let recorded_state = headless::dump_state(&emulator);
+
+    let movie_file = String::from_utf8(buffer.contents()).unwrap();
+    assert!(movie_file.starts_with("# CHIP-8 movie\nrom "));
+    assert!(movie_file.ends_with("\n3 down 5\n6 up 5\n10 down 5\n11 up 5\n"));
+
+    let movie = Movie::parse(&movie_file).unwrap();
+    assert!(movie.seed == emulator.seed());
+    assert!(movie.rom_hash == rom_hash(&PROGRAM));
+    assert!(movie.events.len() == 4);
+
+    // Played back with another seed, without the key script
+    let mut replay = Emulator::new(&PROGRAM, &movie.options);
+    replay.set_seed(movie.seed);
+    replay.set_playback(Some(KeyScript::new(movie.events)));
+    assert!(replay.is_playing_back());
+    Headless::new(Vec::new()).run(&mut replay, LIMITS);
+    assert!(!replay.is_playing_back());
+    assert!(headless::dump_state(&replay) == recorded_state);
+    assert!(replay.registers()[3] != 0);
+}
+
+/// A system keeping its save state in a file, like the frontends
+struct FileSystem {
+    events: VecDeque<(u64, KeyEvent)>,
+    frame: u64,
+    state_file: PathBuf,
+}
+
+impl System for FileSystem {
+    fn update_screen(&mut self, _framebuffer: &Framebuffer) {}
+
+    fn get_key_event(&mut self) -> Option<KeyEvent> {
+        match self.events.front() {
+            Some((frame, _)) if *frame <= self.frame => self.events.pop_front().map(|(_, k)| k),
+            _ => None,
+        }
+    }
+
+    fn store_state(&mut self, state: SaveState) {
+        state.save(&self.state_file).unwrap();
+    }
+
+    fn fetch_state(&mut self) -> Option<SaveState> {
+        SaveState::load(&self.state_file).ok()
+    }
+}
+
+impl FileSystem {
+    fn run(&mut self, emulator: &mut Emulator) {
+        for frame in 0..20 {
+            self.frame = frame;
+            emulator.run_system_frame(self, |_| false);
+        }
+    }
+}
+
+#[test]
+fn test_play_back_save_and_load() {
+    let options = Platform::XoChip.options();
+    let state_file =
+        std::env::temp_dir().join(format!("chip8_test_movie_{}.state", std::process::id()));
+    // A save state file of another run
+    let mut other = Emulator::new(&PROGRAM, &options);
+    other.set_seed(1);
+    other.set_key(5, true);
+    Headless::new(Vec::new()).run(&mut other, LIMITS);
+    other.save_state().save(&state_file).unwrap();
+
+    let buffer = SharedBuffer::default();
+    let mut emulator = Emulator::new(&PROGRAM, &options);
+    let recorder = Recorder::new(
+        Box::new(buffer.clone()),
+        &PROGRAM,
+        emulator.seed(),
+        &options,
+    )
+    .unwrap();
+    emulator.set_recorder(Some(recorder));
+    let script = "2 down 5\n5 save\n12 load\n14 up 5";
+    let mut system = FileSystem {
+        events: headless::parse_key_script(script).unwrap().into(),
+        frame: 0,
+        state_file: state_file.clone(),
+    };
+    system.run(&mut emulator);
+    let recorded_state = headless::dump_state(&emulator);
+    // The save went to the movie, not the file
+    let file_state = SaveState::load(&state_file).unwrap();
+    assert!(file_state.to_bytes().unwrap() == other.save_state().to_bytes().unwrap());
+
+    // Played back with the file changed in between
+    let mut changed = Emulator::new(&PROGRAM, &options);
+    changed.set_seed(2);
+    changed.save_state().save(&state_file).unwrap();
+    let movie_file = String::from_utf8(buffer.contents()).unwrap();
+    let movie = Movie::parse(&movie_file).unwrap();
+    let mut replay = Emulator::new(&PROGRAM, &movie.options);
+    replay.set_seed(movie.seed);
+    replay.set_playback(Some(KeyScript::new(movie.events)));
+    let mut system = FileSystem {
+        events: VecDeque::new(),
+        frame: 0,
+        state_file: state_file.clone(),
+    };
+    system.run(&mut replay);
+    assert!(headless::dump_state(&replay) == recorded_state);
+    let file_state = SaveState::load(&state_file).unwrap();
+    assert!(file_state.to_bytes().unwrap() == changed.save_state().to_bytes().unwrap());
+    let _ = std::fs::remove_file(&state_file);
+}
+
+#[test]
+fn test_seeded_runs_are_reproducible() {
+    let run = |seed| {
+        let mut emulator = Emulator::new(&PROGRAM, &Platform::XoChip.options());
+        emulator.set_seed(seed);
+        emulator.set_key(5, true);
+        Headless::new(Vec::new()).run(&mut emulator, LIMITS);
+        emulator.registers()[3]
+    };
+    assert!(run(1) == run(1));
+    assert!(run(1) != run(2));
+}
+
+#[test]
+fn test_parse_errors() {
+    let header = "rom 0123\nseed 42\noptions {}\n";
+    assert!(Movie::parse("seed 42\n").is_err_and(|e| e.contains("no rom")));
+    assert!(Movie::parse("rom 0123\nseed x\n").is_err_and(|e| e.contains("line 2")));
+    assert!(Movie::parse("rom 0123\nseed 42\noptions { x").is_err_and(|e| e.contains("options")));
+    assert!(Movie::parse(header).is_err_and(|e| e.contains("options")));
+    let options = toml::Value::try_from(Chip8options::default()).unwrap();
+    let header = format!("rom 0123\nseed 42\noptions {options}\n");
+    assert!(Movie::parse(&format!("{header}1 press 5")).is_err_and(|e| e.contains("line 4")));
+    assert!(Movie::parse(&format!("{header}1 down 5")).is_ok_and(|m| m.seed == 42));
+}
+
+#[test]
+fn test_rom_hash() {
+    assert!(rom_hash(&[]) == 0xCBF2_9CE4_8422_2325);
+    assert!(rom_hash(b"a") == 0xAF63_DC4C_8601_EC8C);
+}
@@ -0,0 +1,213 @@
+use super::*;
+use crate::chip8options::Platform;
+use crate::emulator::Emulator;
+
+#[test]
+fn test_statements() {
+    let source = "
+        clear       # comment
+        v0 := 0x0C
+        v1 := 8
+        v2 += -1
+        v3 -= 2
+        v3 -= v4
+        v5 =- v6
+        v7 >>= v7
+        v8 := random 0xFF
+        va := key
+        i := long 0x1234
+        i := hex v2
+        sprite v0 v1 15
+        save va
+        load v2 - v4
+        delay := v1
+        buzzer := v1
+        jump0 0x300
+        ;
+    ";
+    let rom = compile(source).unwrap();
+    assert!(
+        rom == [
+            0x00, 0xE0, 0x60, 0x0C, 0x61, 0x08, 0x72, 0xFF, 0x73, 0xFE, 0x83, 0x45, 0x85, 0x67,
+            0x87, 0x76, 0xC8, 0xFF, 0xFA, 0x0A, 0xF0, 0x00, 0x12, 0x34, 0xF2, 0x29, 0xD0, 0x1F,
+            0xFA, 0x55, 0x52, 0x43, 0xF1, 0x15, 0xF1, 0x18, 0xB3, 0x00, 0x00, 0xEE
+        ]
+    );
+}
+
+#[test]
+fn test_labels_and_data() {
+    let source = "
+        : main
+            i := sprite
+            sprite v0 v1 2
+            draw
+        : forever
+            jump forever
+        : draw
+            return
+        : sprite
+            0b00111100 0x42
+    ";
+    let rom = compile(source).unwrap();
+    assert!(
+        rom == [
+            0xA2, 0x0A, 0xD0, 0x12, 0x22, 0x08, 0x12, 0x06, 0x00, 0xEE, 0x3C, 0x42
+        ]
+    );
+}
+
+#[test]
+fn test_alias_const_calc() {
+    let source = "
+        :alias x v3
+        :const SPEED 4
+        :calc DOUBLE { SPEED * 2 + 1 }
+        x := SPEED
+        x += DOUBLE
+        x := { 10 - 4 - 1 }
+        x := { 1 << 4 >> 1 }
+    ";
+    let rom = compile(source).unwrap();
+    // Right to left evaluation: 2 + 1 first, 10 - (4 - 1) and 1 << (4 >> 1)
+    assert!(rom == [0x63, 0x04, 0x73, 0x0C, 0x63, 0x07, 0x63, 0x04]);
+}
+
+#[test]
+fn test_if_then() {
+    let source = "
+        if v0 == 5 then v1 := 1
+        if v0 != v2 then v1 := 1
+        if v3 key then v1 := 1
+        if v3 -key then v1 := 1
+    ";
+    let rom = compile(source).unwrap();
+    assert!(
+        rom == [
+            0x40, 0x05, 0x61, 0x01, 0x50, 0x20, 0x61, 0x01, 0xE3, 0xA1, 0x61, 0x01, 0xE3, 0x9E,
+            0x61, 0x01
+        ]
+    );
+}
+
+#[test]
+fn test_if_else_and_loop() {
+    let source = "
+        loop
+            if v0 == 3 begin
+                v1 := 1
+            else
+                v1 := 2
+            end
+            while v0 != 10
+            v0 += 1
+        again
+    ";
+    let rom = compile(source).unwrap();
+    assert!(
+        rom == [
+            0x30, 0x03, 0x12, 0x08, 0x61, 0x01, 0x12, 0x0A, 0x61, 0x02, 0x40, 0x0A, 0x12, 0x12,
+            0x70, 0x01, 0x12, 0x00
+        ]
+    );
+}
+
+#[test]
+fn test_comparisons() {
+    // Runs every comparison for a few values, counting the true ones in v2
+    let cases = [
+        ("<", 3, 5, 1),
+        ("<", 5, 5, 0),
+        (">", 6, 5, 1),
+        (">", 5, 5, 0),
+        ("<=", 5, 5, 1),
+        ("<=", 6, 5, 0),
+        (">=", 5, 5, 1),
+        (">=", 4, 5, 0),
+    ];
+    for (cmp, a, b, expected) in cases {
+        for rhs in ["5", "v1"] {
+            let source = format!(
+                "v0 := {a} v1 := {b} v2 := 0 if v0 {cmp} {rhs} then v2 += 1 : done jump done",
+                rhs = if rhs == "5" {
+                    b.to_string()
+                } else {
+                    rhs.to_string()
+                }
+            );
+            let rom = compile(&source).unwrap();
+            let mut emulator = Emulator::new(&rom, &Platform::XoChip.options());
+            emulator.run_frame();
+            assert!(
+                emulator.registers()[2] == expected,
+                "v0 {cmp} {rhs} with {a}, {b}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_macro_next_unpack() {
+    let source = "
+        :macro add-both a b { a += 1 b += 1 }
+        add-both v0 v1
+        : counter
+        :next limit
+        v2 := 0
+        :unpack 0xA sprite
+        :unpack long sprite
+        : sprite
+    ";
+    let rom = compile(source).unwrap();
+    assert!(
+        rom == [
+            0x70, 0x01, 0x71, 0x01, 0x62, 0x00, 0x60, 0xA2, 0x61, 0x0E, 0x60, 0x02, 0x61, 0x0E
+        ]
+    );
+    // :next labels the byte after the next instruction's first byte
+    let rom = compile(": main :next limit v2 := 7 i := limit").unwrap();
+    assert!(rom == [0x62, 0x07, 0xA2, 0x01]);
+}
+
+#[test]
+fn test_errors() {
+    let error = compile("v0 := 256").unwrap_err();
+    assert!(error.line == 1 && error.column == 7);
+    assert!(error.message.contains("out of range"));
+    let error = compile("\n  jump nowhere").unwrap_err();
+    assert!(error.line == 2 && error.column == 8);
+    assert!(error.message.contains("undefined label nowhere"));
+    assert!(
+        compile("if v0 == 1 begin")
+            .unwrap_err()
+            .message
+            .contains("never closed")
+    );
+    assert!(compile("again").is_err());
+    let error = compile(":calc x { 1 << 70 }").unwrap_err();
+    assert!(error.column == 13 && error.message.contains("can't shift by 70"));
+    assert!(
+        compile(":calc x { 8 >> -1 }")
+            .unwrap_err()
+            .message
+            .contains("can't shift")
+    );
+    assert!(
+        compile(": a : a")
+            .unwrap_err()
+            .message
+            .contains("already defined")
+    );
+    assert!(
+        compile(":macro m { m } m")
+            .unwrap_err()
+            .message
+            .contains("recursive")
+    );
+    assert!(
+        compile("v0 := foo")
+            .unwrap_err()
+            .message
+            .contains("undefined name")
+    );
+}
@@ -134,7 +134,8 @@ fn test_skipifvxeqvy() {
 
 #[test]
 fn test_op5nonexistent() {
-    for i in 1..0xF {
+    // 5XY2 and 5XY3 are XO-CHIP op codes
+    for i in (1..0xF).filter(|i| ![2, 3].contains(i)) {
         assert!(OpCode::from_bytes(&[0x51, i]).is_none());
     }
 }
@@ -505,3 +506,111 @@ fn test_loadmemory() {
         assert!(false);
     }
 }
+
+#[test]
+fn test_superchip_screen() {
+    assert!(matches!(
+        OpCode::from_bytes(&[0x00, 0xC5]),
+        Some(OpCode::ScrollDown(5))
+    ));
+    assert!(matches!(
+        OpCode::from_bytes(&[0x00, 0xFB]),
+        Some(OpCode::ScrollRight)
+    ));
+    assert!(matches!(
+        OpCode::from_bytes(&[0x00, 0xFC]),
+        Some(OpCode::ScrollLeft)
+    ));
+    assert!(matches!(
+        OpCode::from_bytes(&[0x00, 0xFD]),
+        Some(OpCode::Exit)
+    ));
+    assert!(matches!(
+        OpCode::from_bytes(&[0x00, 0xFE]),
+        Some(OpCode::LowRes)
+    ));
+    assert!(matches!(
+        OpCode::from_bytes(&[0x00, 0xFF]),
+        Some(OpCode::HighRes)
+    ));
+}
+
+#[test]
+fn test_superchip_fx() {
+    assert!(matches!(
+        OpCode::from_bytes(&[0xF3, 0x30]),
+        Some(OpCode::BigFontCharacter { vx: 3 })
+    ));
+    assert!(matches!(
+        OpCode::from_bytes(&[0xF7, 0x75]),
+        Some(OpCode::StoreFlags { vx: 7 })
+    ));
+    assert!(matches!(
+        OpCode::from_bytes(&[0xF7, 0x85]),
+        Some(OpCode::LoadFlags { vx: 7 })
+    ));
+}
+
+#[test]
+fn test_xochip() {
+    assert!(matches!(
+        OpCode::from_bytes(&[0x00, 0xD3]),
+        Some(OpCode::ScrollUp(3))
+    ));
+    assert!(matches!(
+        OpCode::from_bytes(&[0x51, 0x42]),
+        Some(OpCode::StoreRange { vx: 1, vy: 4 })
+    ));
+    assert!(matches!(
+        OpCode::from_bytes(&[0x51, 0x43]),
+        Some(OpCode::LoadRange { vx: 1, vy: 4 })
+    ));
+    assert!(matches!(
+        OpCode::from_bytes(&[0xF2, 0x01]),
+        Some(OpCode::SelectPlanes(2))
+    ));
+    assert!(matches!(
+        OpCode::from_bytes(&[0xF0, 0x02]),
+        Some(OpCode::LoadAudioPattern)
+    ));
+    assert!(matches!(
+        OpCode::from_bytes(&[0xF5, 0x3A]),
+        Some(OpCode::SetPitchToVx { vx: 5 })
+    ));
+}
+
+#[test]
+fn test_setindexlong() {
+    let op = OpCode::from_bytes(&[0xF0, 0x00, 0x12, 0x34]);
+    assert!(matches!(op, Some(OpCode::SetIndexLong(0x1234))));
+    assert!(op.is_some_and(|op| op.size() == 4));
+    assert!(OpCode::from_bytes(&[0xF0, 0x00, 0x12]).is_none());
+    assert!(OpCode::from_bytes(&[0xF1, 0x00, 0x12, 0x34]).is_none());
+}
+
+#[test]
+fn test_mnemonics() {
+    let mnemonic = |bytes: &[u8]| OpCode::from_bytes(bytes).unwrap().to_string();
+    assert!(mnemonic(&[0x00, 0xE0]) == "CLS");
+    assert!(mnemonic(&[0x12, 0x4E]) == "JP 0x24E");
+    assert!(mnemonic(&[0x6A, 0x05]) == "LD VA, 0x05");
+    assert!(mnemonic(&[0x81, 0x2E]) == "SHL V1, V2");
+    assert!(mnemonic(&[0xB3, 0x00]) == "JP V0, 0x300");
+    assert!(mnemonic(&[0xD0, 0x1F]) == "DRW V0, V1, 15");
+    assert!(mnemonic(&[0xF3, 0x65]) == "LD V3, [I]");
+    assert!(mnemonic(&[0xF0, 0x00, 0x01, 0x23]) == "LD I, LONG 0x0123");
+    assert!(OpCode::Call(0x208).to_string_with_target("sub") == "CALL sub");
+}
+
+#[test]
+fn test_to_bytes_roundtrip() {
+    // Every decodable two byte op code encodes back to the same bytes
+    for word in 0..=0xFFFFu16 {
+        let bytes = word.to_be_bytes();
+        if let Some(op) = OpCode::from_bytes(&bytes) {
+            assert!(op.to_bytes() == bytes);
+        }
+    }
+    let long = [0xF0, 0x00, 0xAB, 0xCD];
+    assert!(OpCode::from_bytes(&long).is_some_and(|op| op.to_bytes() == long));
+}
@@ -0,0 +1,30 @@
+use super::*;
+
+#[test]
+fn test_stop_on_error() {
+    let mut output = StopOnError::new(SharedBuffer::default(), "test output");
+    let mut writes = 0;
+    output.write(|w| {
+        writes += 1;
+        w.write_all(b"first")
+    });
+    output.write(|_| {
+        writes += 1;
+        Err(io::Error::other("disk full"))
+    });
+    // Nothing is written after the error
+    output.write(|w| {
+        writes += 1;
+        w.write_all(b"second")
+    });
+    assert!(writes == 2);
+}
+
+#[test]
+fn test_shared_buffer() {
+    let buffer = SharedBuffer::default();
+    let mut writer = buffer.clone();
+    writer.write_all(b"GIF").unwrap();
+    writer.write_all(b"89a").unwrap();
+    assert!(buffer.contents() == b"GIF89a");
+}
@@ -0,0 +1,105 @@
+use super::*;
+use crate::chip8options::Timing;
+
+fn timing(catch_up_frames: u32) -> Timing {
+    Timing {
+        display_frequency: 50,
+        catch_up_frames,
+        ..Timing::default()
+    }
+}
+
+const FRAME: Duration = Duration::from_millis(20);
+
+#[test]
+fn test_frame_pacer_on_time() {
+    let start = Instant::now();
+    let mut pacer = FramePacer::new(&timing(6), start);
+    assert!(pacer.frame_due(start));
+    assert!(!pacer.frame_due(start));
+    assert!(pacer.time_to_next_frame(start) == FRAME);
+    // Time spent on the frame is taken off the wait
+    let now = start + Duration::from_millis(5);
+    assert!(pacer.time_to_next_frame(now) == Duration::from_millis(15));
+    assert!(!pacer.frame_due(now));
+    assert!(pacer.frame_due(start + FRAME));
+    assert!(pacer.time_to_next_frame(start + FRAME * 3) == Duration::ZERO);
+    assert!(pacer.dropped() == 0);
+}
+
+#[test]
+fn test_frame_pacer_catches_up() {
+    let start = Instant::now();
+    let mut pacer = FramePacer::new(&timing(6), start);
+    // Three frames late, the missed frames run without waiting
+    let now = start + FRAME * 3 + Duration::from_millis(1);
+    let mut frames = 0;
+    while pacer.frame_due(now) {
+        frames += 1;
+    }
+    assert!(frames == 4);
+    assert!(pacer.dropped() == 0);
+    assert!(pacer.time_to_next_frame(now) == Duration::from_millis(19));
+}
+
+#[test]
+fn test_frame_pacer_drops_frames() {
+    let start = Instant::now();
+    let mut pacer = FramePacer::new(&timing(2), start);
+    // Ten frames late, beyond the limit, the schedule starts over
+    let now = start + FRAME * 10;
+    let mut frames = 0;
+    while pacer.frame_due(now) {
+        frames += 1;
+    }
+    assert!(frames == 1);
+    assert!(pacer.dropped() == 10);
+    assert!(pacer.time_to_next_frame(now) == FRAME);
+}
+
+#[test]
+fn test_speed_meter() {
+    let start = Instant::now();
+    let mut meter = SpeedMeter::new(start, 100, 5000, 0);
+    assert!(
+        meter
+            .measure(start + Duration::from_millis(500), 130, 6000, 0)
+            .is_none()
+    );
+    let speed = meter.measure(start + Duration::from_secs(2), 220, 23_000, 4);
+    assert!(
+        speed
+            == Some(Speed {
+                fps: 60.0,
+                ips: 9000.0,
+                dropped: 4
+            })
+    );
+    // The next measurement starts from the last one
+    assert!(
+        meter
+            .measure(start + Duration::from_millis(2500), 250, 30_000, 4)
+            .is_none()
+    );
+    let speed = meter.measure(start + Duration::from_secs(3), 280, 33_000, 4);
+    assert!(speed.is_some_and(|s| s.fps == 60.0 && s.ips == 10_000.0 && s.dropped == 0));
+}
+
+#[test]
+fn test_frame_pacer_slow_down() {
+    let start = Instant::now();
+    let mut pacer = FramePacer::new(&timing(6), start);
+    assert!(pacer.frame_due(start));
+    pacer.set_slow_down(4);
+    assert!(pacer.time_to_next_frame(start) == FRAME * 4);
+    assert!(!pacer.frame_due(start + FRAME * 3));
+    assert!(pacer.frame_due(start + FRAME * 4));
+    pacer.set_slow_down(1);
+    assert!(pacer.time_to_next_frame(start + FRAME * 4) == FRAME);
+
+    // Starting over after running frames back to back drops nothing
+    pacer.restart(start + FRAME * 100);
+    assert!(pacer.frame_due(start + FRAME * 100));
+    assert!(!pacer.frame_due(start + FRAME * 100));
+    assert!(pacer.dropped() == 0);
+}
@@ -0,0 +1,116 @@
+use super::*;
+
+/// Reads bits least significant bit first
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, n: u8) -> u32 {
+        let mut value = 0;
+        for i in 0..n {
+            let bit = (self.data[self.pos / 8] >> (self.pos % 8)) & 1;
+            value |= (bit as u32) << i;
+            self.pos += 1;
+        }
+        value
+    }
+
+    fn huffman(&mut self, n: u8) -> u32 {
+        (0..n).fold(0, |code, _| (code << 1) | self.bits(1))
+    }
+
+    /// Decodes a literal/length symbol with the fixed Huffman codes.
+    fn symbol(&mut self) -> u16 {
+        let code = self.huffman(7);
+        if code <= 0x17 {
+            return 256 + code as u16;
+        }
+        let code = (code << 1) | self.bits(1);
+        match code {
+            0x30..=0xBF => (code - 0x30) as u16,
+            0xC0..=0xC7 => (280 + code - 0xC0) as u16,
+            _ => (144 + ((code << 1) | self.bits(1)) - 0x190) as u16,
+        }
+    }
+}
+
+/// Inflates a single fixed Huffman deflate block.
+fn inflate(data: &[u8]) -> Vec<u8> {
+    let mut reader = BitReader { data, pos: 0 };
+    assert!(reader.bits(1) == 1 && reader.bits(2) == 1);
+    let mut out: Vec<u8> = Vec::new();
+    loop {
+        let symbol = reader.symbol();
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return out,
+            _ => {
+                let code = (symbol - 257) as usize;
+                let length = LENGTH_BASE[code] as usize + reader.bits(LENGTH_EXTRA[code]) as usize;
+                let code = reader.huffman(5) as usize;
+                let distance =
+                    DISTANCE_BASE[code] as usize + reader.bits(DISTANCE_EXTRA[code]) as usize;
+                for _ in 0..length {
+                    out.push(out[out.len() - distance]);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_checksums() {
+    assert!(crc32(b"IEND") == 0xAE42_6082);
+    assert!(crc32(b"123456789") == 0xCBF4_3926);
+    assert!(adler32(b"Wikipedia") == 0x11E6_0398);
+    assert!(adler32(&[]) == 1);
+}
+
+#[test]
+fn test_bit_writer() {
+    let mut bits = BitWriter::default();
+    bits.write(0b101, 3);
+    // Only the lowest bits of the value are written
+    bits.write(0xFF0, 9);
+    bits.write_huffman(0b110, 3);
+    assert!(bits.finish() == [0b1000_0101, 0b0011_1111]);
+}
+
+#[test]
+fn test_deflate_roundtrip() {
+    let mut data: Vec<u8> = (0..=255).collect();
+    data.extend([7; 1000]);
+    data.extend((0..300).map(|i| (i % 3) as u8));
+    data.extend_from_slice(&data.clone()[..600]);
+    for distances in [&[1][..], &[1, 600], &[]] {
+        let compressed = deflate(&data, distances);
+        assert!(inflate(&compressed) == data);
+    }
+    // The runs and repeats compress well
+    assert!(deflate(&data, &[1, 1556]).len() < 600);
+}
+
+#[test]
+fn test_encode() {
+    let palette = [(0, 0, 0), (255, 255, 255)];
+    let pixels = [0, 1, 1, 0, 1, 0];
+    let png = encode(3, 2, &palette, &pixels);
+    assert!(png.starts_with(&SIGNATURE));
+    // IHDR with the size, bit depth 8 and the palette color type
+    assert!(png[8..16] == [0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+    assert!(png[16..29] == [0, 0, 0, 3, 0, 0, 0, 2, 8, 3, 0, 0, 0]);
+    assert!(png[29..33] == crc32(&png[12..29]).to_be_bytes());
+    assert!(png[33..45] == [0, 0, 0, 6, b'P', b'L', b'T', b'E', 0, 0, 0, 255]);
+    assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]));
+
+    // IDAT holds the zlib compressed rows, each starting with filter type 0
+    let idat = png.windows(4).position(|w| w == b"IDAT").unwrap();
+    let length = u32::from_be_bytes(png[idat - 4..idat].try_into().unwrap()) as usize;
+    let zlib = &png[idat + 4..idat + 4 + length];
+    assert!(zlib[..2] == [0x78, 0x01]);
+    let rows = inflate(&zlib[2..length - 4]);
+    assert!(rows == [0, 0, 1, 1, 0, 0, 1, 0]);
+    assert!(zlib[length - 4..] == adler32(&rows).to_be_bytes());
+}
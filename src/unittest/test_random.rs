@@ -0,0 +1,47 @@
+use super::*;
+
+#[test]
+fn test_seeded_sources_repeat() {
+    for algorithm in [RandomAlgorithm::Pcg, RandomAlgorithm::EightBit] {
+        let mut a = from_seed(algorithm, 1234);
+        let mut b = from_seed(algorithm, 1234);
+        let mut c = from_seed(algorithm, 4321);
+        let a: Vec<u8> = (0..32).map(|_| a.next_byte()).collect();
+        let b: Vec<u8> = (0..32).map(|_| b.next_byte()).collect();
+        let c: Vec<u8> = (0..32).map(|_| c.next_byte()).collect();
+        assert!(a == b);
+        assert!(a != c);
+    }
+}
+
+#[test]
+fn test_eight_bit_random() {
+    let mut rng = EightBitRandom::from_seed(0x0100);
+    assert!(
+        rng == EightBitRandom {
+            counter: 0,
+            value: 1
+        }
+    );
+    // 1 rotated right is 0x80, plus the counter 1
+    assert!(rng.next_byte() == 0x81);
+    assert!(rng.next_byte() == 0xC2);
+    assert!(rng.next_byte() == 0x64);
+    // Every byte value comes up within a few periods of the counter
+    let mut seen = [false; 256];
+    for _ in 0..1024 {
+        seen[rng.next_byte() as usize] = true;
+    }
+    assert!(seen.iter().filter(|s| **s).count() > 200);
+}
+
+#[test]
+fn test_snapshot_restore() {
+    for algorithm in [RandomAlgorithm::Pcg, RandomAlgorithm::EightBit] {
+        let mut rng = from_seed(algorithm, 99);
+        rng.next_byte();
+        let mut restored = rng.snapshot().restore().unwrap();
+        assert!((0..8).all(|_| rng.next_byte() == restored.next_byte()));
+    }
+    assert!(RandomState::External.restore().is_none());
+}
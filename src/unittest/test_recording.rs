@@ -0,0 +1,63 @@
+use super::*;
+use crate::chip8options::Chip8options;
+use crate::output::SharedBuffer;
+
+/// Returns the delays of the frames of the GIF, from the graphic control extensions.
+fn frame_delays(gif: &[u8]) -> Vec<u16> {
+    gif.windows(4)
+        .enumerate()
+        .filter(|(_, w)| *w == [0x21, 0xF9, 4, 0x04])
+        .map(|(i, _)| u16::from_le_bytes([gif[i + 4], gif[i + 5]]))
+        .collect()
+}
+
+fn framebuffer_with_pixel(x: usize) -> Framebuffer {
+    let mut framebuffer = Framebuffer::new(64, 32);
+    framebuffer.toggle(x, 0, crate::framebuffer::PLANE_1);
+    framebuffer
+}
+
+#[test]
+fn test_frame_timing() {
+    let buffer = SharedBuffer::default();
+    let options = Chip8options::default();
+    let mut recorder = GifRecorder::new(
+        Box::new(buffer.clone()),
+        &framebuffer_with_pixel(0),
+        &options,
+        0,
+    )
+    .unwrap();
+    // At 60 Hz: frames 0-6 are 0.1 s, and the 1/60 s of frame 6 is replaced by frame 7
+    recorder.add_frame(&framebuffer_with_pixel(1), 6);
+    recorder.add_frame(&framebuffer_with_pixel(2), 7);
+    recorder.add_frame(&framebuffer_with_pixel(3), 8);
+    // Unchanged screens extend the frame
+    recorder.add_frame(&framebuffer_with_pixel(3), 20);
+    recorder.add_frame(&framebuffer_with_pixel(4), 60);
+    recorder.finish(120).unwrap();
+
+    let gif = buffer.contents();
+    assert!(gif[6..10] == [0, 1, 128, 0]);
+    assert!(frame_delays(&gif) == vec![10, 3, 87, 100]);
+}
+
+#[test]
+fn test_resolution_change() {
+    let options = Chip8options::default();
+    let mut recorder = GifRecorder::new(
+        Box::new(io::sink()),
+        &framebuffer_with_pixel(0),
+        &options,
+        0,
+    )
+    .unwrap();
+    let mut hires = Framebuffer::new(128, 64);
+    hires.toggle(127, 63, crate::framebuffer::PLANE_2);
+    let pixels = recorder.resample(&hires);
+    assert!(pixels.len() == 256 * 128);
+    assert!(pixels.iter().filter(|p| **p != 0).count() == 4);
+    assert!(pixels[256 * 128 - 1] == 2 && pixels[256 * 127 - 2] == 2 && pixels[256 * 128 - 3] == 0);
+    recorder.add_frame(&hires, 1);
+    assert!(recorder.finish(10).is_ok());
+}
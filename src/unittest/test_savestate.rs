@@ -0,0 +1,86 @@
+use super::*;
+use crate::emulator::Emulator;
+
+#[test]
+fn test_roundtrip() {
+    // V0 := 5, I := font character V0, call 0x208, sprite at (V0, V0), return
+    let rom = [
+        0x60, 0x05, 0xF0, 0x29, 0x22, 0x08, 0x12, 0x06, 0xD0, 0x05, 0x00, 0xEE,
+    ];
+    let mut emulator = Emulator::new(&rom, &Chip8options::default());
+    for _ in 0..4 {
+        emulator.step();
+    }
+    let bytes = emulator.save_state().to_bytes().unwrap();
+    let state = SaveState::from_bytes(&bytes).unwrap();
+
+    let mut restored = Emulator::new(&[0], &Chip8options::default());
+    restored.load_state(&state);
+    assert!(restored.pc() == emulator.pc());
+    assert!(restored.index() == emulator.index());
+    assert!(restored.registers() == emulator.registers());
+    assert!(restored.stack() == emulator.stack());
+    assert!(restored.memory() == emulator.memory());
+    assert!(restored.framebuffer().pixels() == emulator.framebuffer().pixels());
+    assert!(restored.framebuffer().pixels().iter().any(|p| *p > 0));
+}
+
+#[test]
+fn test_rng_restored() {
+    // VX := rand 0xFF for all registers
+    let rom: Vec<u8> = (0..16).flat_map(|x| [0xC0 | x, 0xFF]).collect();
+    let mut emulator = Emulator::new(&rom, &Chip8options::default());
+    let state = emulator.save_state();
+    for _ in 0..16 {
+        emulator.step();
+    }
+    let mut restored = Emulator::new(&rom, &Chip8options::default());
+    restored.load_state(&state);
+    for _ in 0..16 {
+        restored.step();
+    }
+    assert!(restored.registers() == emulator.registers());
+}
+
+#[test]
+fn test_invalid_header() {
+    let emulator = Emulator::new(&[0], &Chip8options::default());
+    let mut bytes = emulator.save_state().to_bytes().unwrap();
+    bytes[4] = VERSION as u8 + 1;
+    assert!(SaveState::from_bytes(&bytes).is_err_and(|e| e.to_string().contains("version")));
+    bytes[0] = b'X';
+    assert!(SaveState::from_bytes(&bytes).is_err_and(|e| e.to_string().contains("not a CHIP-8")));
+    assert!(SaveState::from_bytes(&[]).is_err());
+}
+
+#[test]
+fn test_invalid_state() {
+    let state = Emulator::new(&[0x12, 0x00], &Chip8options::default()).save_state();
+    let check = |change: &dyn Fn(&mut SaveState), error: &str| {
+        let mut state = state.clone();
+        change(&mut state);
+        let bytes = state.to_bytes().unwrap();
+        assert!(SaveState::from_bytes(&bytes).is_err_and(|e| e.to_string().contains(error)));
+    };
+    check(&|s| s.machine.memory.clear(), "memory size 0");
+    check(&|s| s.machine.memory.truncate(0x300), "memory size 768");
+    check(&|s| s.options.memory.mem_size = 0x300, "memory size 4096");
+    check(&|s| s.options.memory.rom_start = 0xFFF, "ROM doesn't fit");
+    check(&|s| s.options.memory.font_start = 0xFF0, "font doesn't fit");
+    check(&|s| s.rom = vec![0; 0x1000], "ROM doesn't fit");
+    check(
+        &|s| {
+            s.machine.framebuffer = bincode::deserialize(
+                &bincode::serialize(&(64usize, 32usize, vec![0u8; 10])).unwrap(),
+            )
+            .unwrap()
+        },
+        "framebuffer",
+    );
+    assert!(SaveState::from_bytes(&state.to_bytes().unwrap()).is_ok());
+
+    // A huge length in the data is refused rather than allocated
+    let mut bytes = state.to_bytes().unwrap();
+    bytes[8..16].copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+    assert!(SaveState::from_bytes(&bytes).is_err());
+}
@@ -0,0 +1,46 @@
+use super::*;
+use crate::chip8options::Chip8options;
+use crate::framebuffer;
+
+#[test]
+fn test_format_timestamp() {
+    assert!(format_timestamp(0) == "19700101-000000");
+    assert!(format_timestamp(951_782_400) == "20000229-000000");
+    assert!(format_timestamp(1_706_715_902) == "20240131-154502");
+    assert!(format_timestamp(4_102_444_799) == "20991231-235959");
+}
+
+#[test]
+fn test_scale_pixels() {
+    let mut framebuffer = Framebuffer::new(2, 2);
+    framebuffer.toggle(1, 0, framebuffer::PLANE_1);
+    framebuffer.toggle(0, 1, framebuffer::PLANE_2);
+    let (width, height, pixels) = scale_pixels(&framebuffer, 2);
+    assert!(width == 4 && height == 4);
+    assert!(pixels[..8] == [0, 0, 1, 1, 0, 0, 1, 1]);
+    assert!(pixels[8..] == [2, 2, 0, 0, 2, 2, 0, 0]);
+}
+
+#[test]
+fn test_save_png() {
+    let dir = std::env::temp_dir().join("chip8_test_screenshot");
+    std::fs::create_dir_all(&dir).unwrap();
+    let rom_file = dir.join("game.ch8");
+    let options = Chip8options::default();
+    let framebuffer = Framebuffer::new(64, 32);
+
+    let first = timestamped_path(&rom_file, "png");
+    let name = first.file_name().unwrap().to_string_lossy().to_string();
+    assert!(name.starts_with("game_") && name.ends_with(".png") && name.len() == 24);
+    save_png(&first, &framebuffer, &options.display).unwrap();
+    let second = timestamped_path(&rom_file, "png");
+    assert!(second != first);
+
+    let png = std::fs::read(&first).unwrap();
+    // 64x32 pixels at the default scale of 4
+    assert!(png[16..24] == [0, 0, 1, 0, 0, 0, 0, 128]);
+    // The colors of the options in the palette
+    let plte = png.windows(4).position(|w| w == b"PLTE").unwrap();
+    assert!(png[plte + 4..plte + 10] == [0, 0, 0, 255, 255, 255]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
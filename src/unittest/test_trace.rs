@@ -0,0 +1,73 @@
+use super::*;
+use crate::chip8options::Chip8options;
+use crate::output::SharedBuffer;
+
+/// Runs the program for a number of instructions with a tracer, and returns the trace lines.
+fn trace_program(rom: &[u8], steps: usize, filter: TraceFilter) -> Vec<String> {
+    let buffer = SharedBuffer::default();
+    let mut emulator = Emulator::new(rom, &Chip8options::default());
+    emulator.set_tracer(Some(Tracer::new(Box::new(buffer.clone()), filter)));
+    for _ in 0..steps {
+        emulator.step();
+    }
+    let trace = String::from_utf8(buffer.contents()).unwrap();
+    trace.lines().map(|l| l.to_string()).collect()
+}
+
+// LD V0, 0x05; LD I, 0x20A; CALL 0x208; JP 0x206; RET
+const PROGRAM: [u8; 10] = [0x60, 0x05, 0xA2, 0x0A, 0x22, 0x08, 0x12, 0x06, 0x00, 0xEE];
+
+#[test]
+fn test_trace_lines() {
+    let lines = trace_program(&PROGRAM, 3, TraceFilter::default());
+    assert!(lines.len() == 3);
+    assert!(
+        lines[0]
+            == "0000000000 PC:0200 OP:6005 Set { vx: 0, val: 5 } \
+                V:00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I:0000 SP:0 DT:00 ST:00"
+    );
+    assert!(
+        lines[2]
+            == "0000000002 PC:0204 OP:2208 Call(520) \
+                V:05 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I:020A SP:0 DT:00 ST:00"
+    );
+}
+
+#[test]
+fn test_trace_filters() {
+    let filter = TraceFilter {
+        pc_ranges: vec![0x206..=0x208],
+        opcodes: Vec::new(),
+    };
+    let lines = trace_program(&PROGRAM, 6, filter);
+    // CALL, RET, JP, JP, JP executed at 0x204, 0x208, 0x206, 0x206
+    assert!(lines.len() == 3);
+    assert!(lines[0].starts_with("0000000003 PC:0208 OP:00EE Return"));
+    assert!(lines[1].starts_with("0000000004 PC:0206 OP:1206 Jump"));
+
+    let filter = TraceFilter {
+        pc_ranges: Vec::new(),
+        opcodes: vec!["call".to_string(), "SetIndex".to_string()],
+    };
+    let lines = trace_program(&PROGRAM, 6, filter);
+    assert!(lines.len() == 2);
+    assert!(lines[0].contains("SetIndex(522)"));
+    assert!(lines[1].contains("Call(520)"));
+}
+
+#[test]
+fn test_parse_pc_range() {
+    assert!(parse_pc_range("200-2ff") == Ok(0x200..=0x2FF));
+    assert!(parse_pc_range("0x300") == Ok(0x300..=0x300));
+    assert!(parse_pc_range("300-200").is_err());
+    assert!(parse_pc_range("xyz").is_err());
+}
+
+#[test]
+fn test_parse_opcode_name() {
+    assert!(parse_opcode_name("display") == Ok("Display".to_string()));
+    assert!(parse_opcode_name("SetIndexLong") == Ok("SetIndexLong".to_string()));
+    let error = parse_opcode_name("Draw").unwrap_err();
+    assert!(error.starts_with("unknown op code Draw, use one of ScrollDown, ScrollUp,"));
+    assert!(error.contains(", Display,") && error.ends_with(", LoadFlags"));
+}
@@ -0,0 +1,55 @@
+use super::*;
+
+const COLORS: [Color; 4] = [Color::Black, Color::White, Color::Red, Color::Green];
+
+#[test]
+fn test_render_half_blocks() {
+    let mut framebuffer = Framebuffer::new(64, 32);
+    framebuffer.toggle(0, 1, crate::framebuffer::PLANE_1);
+    let output = render(&framebuffer, &COLORS);
+    // 64x16 cells, two pixels above each other per cell
+    assert!(output.chars().filter(|c| *c == HALF_BLOCK).count() == 64 * 16);
+    assert!(output.matches("\x1b[").count() > 16);
+
+    // The first cell has the top pixel off and the bottom one on, the rest of the row both off
+    let mut expected: Vec<u8> = Vec::new();
+    let _ = queue!(
+        expected,
+        cursor::MoveTo(0, 0),
+        SetForegroundColor(Color::Black),
+        SetBackgroundColor(Color::White),
+        Print(HALF_BLOCK),
+        SetForegroundColor(Color::Black),
+        SetBackgroundColor(Color::Black),
+        Print(HALF_BLOCK),
+        Print(HALF_BLOCK)
+    );
+    assert!(output.starts_with(std::str::from_utf8(&expected).unwrap()));
+}
+
+#[test]
+fn test_render_odd_height_and_planes() {
+    let mut framebuffer = Framebuffer::new(2, 3);
+    framebuffer.toggle(1, 2, crate::framebuffer::PLANE_2);
+    let output = render(&framebuffer, &COLORS);
+    assert!(output.chars().filter(|c| *c == HALF_BLOCK).count() == 2 * 2);
+    let mut plane2: Vec<u8> = Vec::new();
+    let _ = queue!(
+        plane2,
+        SetForegroundColor(Color::Red),
+        SetBackgroundColor(Color::Black)
+    );
+    assert!(output.contains(std::str::from_utf8(&plane2).unwrap()));
+}
+
+#[test]
+fn test_key_names() {
+    assert!(key_name(KeyCode::Char('q'), false).as_deref() == Some("Q"));
+    assert!(key_name(KeyCode::Char(' '), false).as_deref() == Some("Space"));
+    assert!(key_name(KeyCode::F(5), false).as_deref() == Some("F5"));
+    assert!(key_name(KeyCode::Esc, false).as_deref() == Some("Escape"));
+    assert!(key_name(KeyCode::Enter, false).as_deref() == Some("Return"));
+    assert!(key_name(KeyCode::Enter, true).as_deref() == Some("Keypad Enter"));
+    assert!(key_name(KeyCode::Char('7'), true).as_deref() == Some("Keypad 7"));
+    assert!(key_name(KeyCode::Null, false).is_none());
+}
@@ -0,0 +1,294 @@
+//! # Test ROMs
+//!
+//! Runs the test suite ROMs of Timendus in rom_files headless, with the platform preset and key
+//! script each test needs, and compares the final framebuffer with a reference bitmap in
+//! tests/reference. The pass and fail marks the ROMs draw are reported, and failing marks fail the
+//! test, so a broken op code or quirk is named rather than only showing up as a different picture.
+//!
+//! To update the references after an intended change, run the tests with `UPDATE_REFERENCES=1`
+//! and review the diff of the reference files.
+
+use chip8::chip8options::Platform;
+use chip8::emulator::Emulator;
+use chip8::headless::{self, Headless, Limits};
+use std::fs;
+use std::path::Path;
+
+/// The marks the test ROMs draw after each result, 3x3 pixels with an empty border
+const CHECK_MARK: [&str; 3] = ["#.#", "##.", "#.."];
+const CROSS: [&str; 3] = ["#.#", ".#.", "#.#"];
+
+/// The results of the quirks test, top to bottom
+const QUIRKS: [&str; 6] = [
+    "vF reset",
+    "Memory",
+    "Display wait",
+    "Clipping",
+    "Shifting",
+    "Jumping",
+];
+
+struct TestRom {
+    /// Name of the reference bitmap
+    name: &'static str,
+    rom: &'static str,
+    platform: Platform,
+    frames: u64,
+    key_script: &'static str,
+    /// Names of the results, in the order of the marks on the screen
+    results: &'static [&'static str],
+    /// Number of results expected on the screen
+    result_count: usize,
+}
+
+impl Default for TestRom {
+    fn default() -> Self {
+        Self {
+            name: "",
+            rom: "",
+            platform: Platform::CosmacVip,
+            frames: 60,
+            key_script: "",
+            results: &[],
+            result_count: 0,
+        }
+    }
+}
+
+/// Runs the test ROM, and returns the final emulator and the framebuffer as text.
+fn run(test: &TestRom) -> (Emulator, String) {
+    let rom = fs::read(Path::new("rom_files").join(test.rom)).unwrap();
+    let mut emulator = Emulator::new(&rom, &test.platform.options());
+    let key_script = headless::parse_key_script(test.key_script).unwrap();
+    let limits = Limits {
+        frames: Some(test.frames),
+        until_pc: None,
+    };
+    Headless::new(key_script).run(&mut emulator, limits);
+    let dump = headless::dump_state(&emulator);
+    let framebuffer = dump[dump.find("Framebuffer").unwrap()..].to_string();
+    (emulator, framebuffer)
+}
+
+/// Finds the check marks and crosses on the screen, in reading order, true for a check mark.
+fn find_marks(framebuffer: &str) -> Vec<bool> {
+    let rows: Vec<&[u8]> = framebuffer.lines().skip(1).map(str::as_bytes).collect();
+    let pixel = |x: usize, y: usize| rows.get(y).and_then(|r| r.get(x)) == Some(&b'#');
+    let matches = |x: usize, y: usize, mark: &[&str; 3]| {
+        (0..5).all(|dy| {
+            (0..5).all(|dx| {
+                let inside = (1..4).contains(&dx) && (1..4).contains(&dy);
+                let set = inside && mark[dy - 1].as_bytes()[dx - 1] == b'#';
+                // The border is off the screen at the edges
+                pixel((x + dx).wrapping_sub(1), (y + dy).wrapping_sub(1)) == set
+            })
+        })
+    };
+    let mut marks = Vec::new();
+    for (y, row) in rows.iter().enumerate() {
+        for x in 0..row.len() {
+            if matches(x, y, &CHECK_MARK) {
+                marks.push(true);
+            } else if matches(x, y, &CROSS) {
+                marks.push(false);
+            }
+        }
+    }
+    marks
+}
+
+/// Runs the test ROM, reports its results and compares the screen with the reference bitmap.
+fn check(test: TestRom) {
+    let (_, framebuffer) = run(&test);
+
+    let marks = find_marks(&framebuffer);
+    let failed: Vec<String> = marks
+        .iter()
+        .enumerate()
+        .filter(|(_, passed)| !**passed)
+        .map(|(i, _)| {
+            test.results
+                .get(i)
+                .map_or(format!("#{}", i + 1), |r| r.to_string())
+        })
+        .collect();
+    println!(
+        "{}: {} passed, {} failed",
+        test.name,
+        marks.len() - failed.len(),
+        failed.len()
+    );
+    for (i, passed) in marks.iter().enumerate() {
+        if let Some(result) = test.results.get(i) {
+            println!("  {} {result}", if *passed { "pass" } else { "FAIL" });
+        }
+    }
+    assert!(
+        marks.len() == test.result_count,
+        "{}: found {} results on the screen, expected {}\n{framebuffer}",
+        test.name,
+        marks.len(),
+        test.result_count
+    );
+    assert!(
+        failed.is_empty(),
+        "{}: failed {}\n{framebuffer}",
+        test.name,
+        failed.join(", ")
+    );
+
+    let reference_file = Path::new("tests/reference").join(format!("{}.txt", test.name));
+    if std::env::var_os("UPDATE_REFERENCES").is_some() {
+        fs::write(&reference_file, &framebuffer).unwrap();
+        return;
+    }
+    let reference = fs::read_to_string(&reference_file).unwrap_or_else(|e| {
+        panic!(
+            "{}: {e}, run with UPDATE_REFERENCES=1 to create it",
+            reference_file.display()
+        )
+    });
+    assert!(
+        framebuffer == reference,
+        "{}: the screen differs from the reference\nExpected {reference}\nActual {framebuffer}",
+        test.name
+    );
+}
+
+#[test]
+fn test_chip8_logo() {
+    check(TestRom {
+        name: "1-chip8-logo",
+        rom: "1-chip8-logo.ch8",
+        ..TestRom::default()
+    });
+}
+
+#[test]
+fn test_ibm_logo() {
+    check(TestRom {
+        name: "2-ibm-logo",
+        rom: "2-ibm-logo.ch8",
+        ..TestRom::default()
+    });
+}
+
+#[test]
+fn test_corax() {
+    check(TestRom {
+        name: "3-corax+",
+        rom: "3-corax+.ch8",
+        frames: 100,
+        result_count: 22,
+        ..TestRom::default()
+    });
+}
+
+#[test]
+fn test_flags() {
+    check(TestRom {
+        name: "4-flags",
+        rom: "4-flags.ch8",
+        frames: 200,
+        result_count: 47,
+        ..TestRom::default()
+    });
+}
+
+#[test]
+fn test_quirks_chip8() {
+    check(TestRom {
+        name: "5-quirks-chip8",
+        rom: "5-quirks.ch8",
+        frames: 600,
+        // Pick CHIP-8 in the menu
+        key_script: "100 down 1\n105 up 1",
+        results: &QUIRKS,
+        result_count: QUIRKS.len(),
+        ..TestRom::default()
+    });
+}
+
+#[test]
+fn test_quirks_super_chip_modern() {
+    check(TestRom {
+        name: "5-quirks-super-chip-modern",
+        rom: "5-quirks.ch8",
+        platform: Platform::SuperChipModern,
+        frames: 600,
+        // Pick SUPER-CHIP, then the modern variant
+        key_script: "100 down 2\n105 up 2\n200 down 1\n205 up 1",
+        results: &QUIRKS,
+        result_count: QUIRKS.len(),
+    });
+}
+
+#[test]
+fn test_quirks_super_chip_legacy() {
+    check(TestRom {
+        name: "5-quirks-super-chip-legacy",
+        rom: "5-quirks.ch8",
+        platform: Platform::SuperChipLegacy,
+        frames: 600,
+        // Pick SUPER-CHIP, then the legacy variant
+        key_script: "100 down 2\n105 up 2\n200 down 2\n205 up 2",
+        results: &QUIRKS,
+        result_count: QUIRKS.len(),
+    });
+}
+
+#[test]
+fn test_quirks_xo_chip() {
+    check(TestRom {
+        name: "5-quirks-xo-chip",
+        rom: "5-quirks.ch8",
+        platform: Platform::XoChip,
+        frames: 300,
+        // Pick XO-CHIP in the menu
+        key_script: "100 down 3\n105 up 3",
+        results: &QUIRKS,
+        result_count: QUIRKS.len(),
+    });
+}
+
+#[test]
+fn test_keypad() {
+    // Pick the EX9E test in the menu and hold key 5, which is highlighted on the keypad
+    check(TestRom {
+        name: "6-keypad",
+        rom: "6-keypad.ch8",
+        frames: 300,
+        key_script: "100 down 1\n110 up 1\n200 down 5",
+        ..TestRom::default()
+    });
+}
+
+#[test]
+#[ignore = "GetKey (FX0A) takes a key when pressed, the COSMAC VIP waits for the release"]
+fn test_keypad_get_key() {
+    // Pick the FX0A test in the menu, then press and release key 5
+    check(TestRom {
+        name: "6-keypad-get-key",
+        rom: "6-keypad.ch8",
+        frames: 300,
+        key_script: "100 down 3\n110 up 3\n200 down 5\n220 up 5",
+        results: &["GetKey waits for the key release"],
+        result_count: 1,
+        ..TestRom::default()
+    });
+}
+
+#[test]
+fn test_beep() {
+    // Holding key B beeps
+    let test = TestRom {
+        name: "7-beep",
+        rom: "7-beep.ch8",
+        frames: 100,
+        key_script: "50 down B",
+        ..TestRom::default()
+    };
+    let (emulator, _) = run(&test);
+    assert!(emulator.sound_timer() > 0);
+    check(test);
+}